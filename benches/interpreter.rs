@@ -0,0 +1,68 @@
+use chip8_core::{Chip8Keypad, Chip8State, Quirks};
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use std::hint::black_box;
+use std::time::Duration;
+
+/// Cycles executed per benchmark iteration.
+const CYCLES: u64 = 10_000;
+
+/// Simulated time between two cycles (700Hz).
+const CYCLE_TIME: Duration = Duration::from_micros(1429);
+
+/// Sprites are drawn immediately, so that every cycle executes an instruction.
+fn quirks() -> Quirks {
+    Quirks {
+        display_wait: false,
+        ..Quirks::default()
+    }
+}
+
+/// Run `CYCLES` cycles of a machine powered on with `rom`, restarting it whenever it stops.
+fn bench_rom(c: &mut Criterion, name: &str, rom: &[u8]) {
+    let keypad = Chip8Keypad::default();
+    let mut group = c.benchmark_group(name);
+    group.throughput(Throughput::Elements(CYCLES));
+    group.bench_function("update", |b| {
+        let mut state = Chip8State::new_seeded(rom, quirks(), 1).unwrap();
+        b.iter(|| {
+            for _ in 0..CYCLES {
+                if state.update(CYCLE_TIME, &keypad).is_err() || state.halted {
+                    state = Chip8State::new_seeded(rom, quirks(), 1).unwrap();
+                }
+            }
+            black_box(&state);
+        })
+    });
+    group.finish();
+}
+
+/// A mix of arithmetic, memory and drawing instructions checking their own results.
+fn opcode_test(c: &mut Criterion) {
+    bench_rom(
+        c,
+        "opcode_test",
+        include_bytes!("../tests/roms/test_opcode.ch8"),
+    );
+}
+
+/// Nothing but `DXYN`, the most expensive instruction, followed by a jump back.
+fn draw_sprites(c: &mut Criterion) {
+    // v0 = 3, v1 = 5, i = "0" glyph, then draw a 15 row sprite over and over
+    let mut rom = vec![0x60, 0x03, 0x61, 0x05, 0xa0, 0x50];
+    for _ in 0..64 {
+        rom.extend_from_slice(&[0xd0, 0x1f]);
+    }
+    rom.extend_from_slice(&[0x12, 0x06]);
+    bench_rom(c, "draw_8x15", &rom);
+
+    // Same with 16x16 sprites in high resolution
+    let mut rom = vec![0x00, 0xff, 0x60, 0x03, 0x61, 0x05, 0xa0, 0x50];
+    for _ in 0..64 {
+        rom.extend_from_slice(&[0xd0, 0x10]);
+    }
+    rom.extend_from_slice(&[0x12, 0x08]);
+    bench_rom(c, "draw_16x16", &rom);
+}
+
+criterion_group!(benches, opcode_test, draw_sprites);
+criterion_main!(benches);
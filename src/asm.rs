@@ -0,0 +1,429 @@
+use crate::state::PROGRAM_START;
+use std::collections::HashMap;
+use std::fmt;
+
+/// Errors found while assembling, with the line they were found on, counting from 1.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AsmError {
+    /// No instruction has this mnemonic and these kinds of operands.
+    UnknownInstruction { line: usize, text: String },
+    /// The operand is not a register, number or label.
+    InvalidOperand { line: usize, operand: String },
+    /// The value does not fit in the bits the instruction has for it.
+    ValueTooLarge { line: usize, value: u32, max: u32 },
+    /// The label is used but never defined.
+    UnknownLabel { line: usize, label: String },
+    /// The label was defined on an earlier line.
+    DuplicateLabel { line: usize, label: String },
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AsmError::UnknownInstruction { line, text } => {
+                write!(f, "Line {}: unknown instruction {}", line, text)
+            }
+            AsmError::InvalidOperand { line, operand } => {
+                write!(f, "Line {}: invalid operand {}", line, operand)
+            }
+            AsmError::ValueTooLarge { line, value, max } => write!(
+                f,
+                "Line {}: 0x{:x} is larger than the maximum 0x{:x}",
+                line, value, max
+            ),
+            AsmError::UnknownLabel { line, label } => {
+                write!(f, "Line {}: unknown label {}", line, label)
+            }
+            AsmError::DuplicateLabel { line, label } => {
+                write!(f, "Line {}: label {} is already defined", line, label)
+            }
+        }
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+/// A number, or the address of a label once all of them are known.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    Number(u32),
+    Label(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Operand {
+    Reg(u16),
+    Value(Value),
+    I,
+    /// `[I]`, the ram at the index register.
+    IndirectI,
+    Dt,
+    St,
+    K,
+    F,
+    /// `HF`, the big font.
+    Hf,
+    B,
+    R,
+    /// `LONG`, optionally followed by the address that 0xf000 loads.
+    Long(Option<Value>),
+}
+
+enum Statement {
+    Data(Vec<u8>),
+    Instruction {
+        mnemonic: String,
+        operands: Vec<Operand>,
+    },
+}
+
+/// Assemble `src` into a rom loaded at 0x200, see [`assemble_at`].
+pub fn assemble(src: &str) -> Result<Vec<u8>, AsmError> {
+    assemble_at(src, PROGRAM_START as u16)
+}
+
+/// Assemble `src` into a rom whose labels are addresses from `load_addr`.
+///
+/// Lines hold the mnemonics printed by the disassembler, e.g. `LD V0, 0x12` or `DRW V0, V1, 5`,
+/// and may start with a `name:` label. Anything after `;` is a comment. Registers are written
+/// `V0` to `V15` or `VA` to `VF`, numbers in decimal, `0x` hexadecimal or `0b` binary, and a
+/// label can stand for any address. `DB` lists data bytes: each number takes as many bytes as
+/// its hexadecimal or binary digits, so the `DB 0x5121` of the disassembler is two bytes.
+/// `LD I, LONG 0x1234` is the four byte long load of XO-CHIP.
+pub fn assemble_at(src: &str, load_addr: u16) -> Result<Vec<u8>, AsmError> {
+    // First pass: parse every line and place the labels
+    let mut statements = vec![];
+    let mut labels = HashMap::new();
+    let mut size = 0;
+    for (idx, line) in src.lines().enumerate() {
+        let line_number = idx + 1;
+        let mut text = line.split(';').next().unwrap_or_default().trim();
+        if let Some((label, rest)) = text.split_once(':')
+            && matches!(
+                parse_operand(label.trim()),
+                Some(Operand::Value(Value::Label(_)))
+            )
+        {
+            let label = label.trim().to_string();
+            if labels.contains_key(&label) {
+                return Err(AsmError::DuplicateLabel {
+                    line: line_number,
+                    label,
+                });
+            }
+            labels.insert(label, load_addr as u32 + size as u32);
+            text = rest.trim();
+        }
+        if text.is_empty() {
+            continue;
+        }
+
+        let (mnemonic, operands) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+        let operands: Vec<&str> = if operands.trim().is_empty() {
+            vec![]
+        } else {
+            operands.split(',').map(str::trim).collect()
+        };
+        let invalid = |operand: &str| AsmError::InvalidOperand {
+            line: line_number,
+            operand: operand.to_string(),
+        };
+        let statement = if mnemonic.eq_ignore_ascii_case("DB") {
+            let mut bytes = vec![];
+            for operand in operands {
+                let (value, width) = parse_number(operand).ok_or_else(|| invalid(operand))?;
+                if width > 4 {
+                    return Err(invalid(operand));
+                }
+                bytes.extend_from_slice(&value.to_be_bytes()[4 - width..]);
+            }
+            Statement::Data(bytes)
+        } else {
+            let operands = operands
+                .into_iter()
+                .map(|operand| parse_operand(operand).ok_or_else(|| invalid(operand)))
+                .collect::<Result<Vec<_>, _>>()?;
+            Statement::Instruction {
+                mnemonic: mnemonic.to_ascii_uppercase(),
+                operands,
+            }
+        };
+        size += match &statement {
+            Statement::Data(bytes) => bytes.len(),
+            Statement::Instruction { operands, .. } => match operands.as_slice() {
+                [Operand::I, Operand::Long(Some(_))] => 4,
+                _ => 2,
+            },
+        };
+        statements.push((line_number, text, statement));
+    }
+
+    // Second pass: encode, now that every label has an address
+    let mut rom = Vec::with_capacity(size);
+    for (line, text, statement) in statements {
+        match statement {
+            Statement::Data(bytes) => rom.extend_from_slice(&bytes),
+            Statement::Instruction { mnemonic, operands } => {
+                let value = |value: &Value, max: u32| {
+                    let value = match value {
+                        Value::Number(number) => *number,
+                        Value::Label(label) => {
+                            *labels.get(label).ok_or_else(|| AsmError::UnknownLabel {
+                                line,
+                                label: label.clone(),
+                            })?
+                        }
+                    };
+                    if value > max {
+                        return Err(AsmError::ValueTooLarge { line, value, max });
+                    }
+                    Ok(value as u16)
+                };
+                let words = encode(&mnemonic, &operands, value)?.ok_or_else(|| {
+                    AsmError::UnknownInstruction {
+                        line,
+                        text: text.to_string(),
+                    }
+                })?;
+                for word in words {
+                    rom.extend_from_slice(&word.to_be_bytes());
+                }
+            }
+        }
+    }
+    Ok(rom)
+}
+
+/// Words of an instruction, or None if no instruction takes these operands.
+fn encode(
+    mnemonic: &str,
+    operands: &[Operand],
+    value: impl Fn(&Value, u32) -> Result<u16, AsmError>,
+) -> Result<Option<Vec<u16>>, AsmError> {
+    use Operand::*;
+    let xy = |x: &u16, y: &u16| x << 8 | y << 4;
+    let opcode = match (mnemonic, operands) {
+        ("CLS", []) => 0x00e0,
+        ("RET", []) => 0x00ee,
+        ("SCD", [Value(n)]) => 0x00c0 | value(n, 0xf)?,
+        ("SCR", []) => 0x00fb,
+        ("SCL", []) => 0x00fc,
+        ("EXIT", []) => 0x00fd,
+        ("LOW", []) => 0x00fe,
+        ("HIGH", []) => 0x00ff,
+        ("SYS", [Value(nnn)]) => value(nnn, 0xfff)?,
+        ("JP", [Value(nnn)]) => 0x1000 | value(nnn, 0xfff)?,
+        ("CALL", [Value(nnn)]) => 0x2000 | value(nnn, 0xfff)?,
+        ("SE", [Reg(x), Value(nn)]) => 0x3000 | x << 8 | value(nn, 0xff)?,
+        ("SNE", [Reg(x), Value(nn)]) => 0x4000 | x << 8 | value(nn, 0xff)?,
+        ("SE", [Reg(x), Reg(y)]) => 0x5000 | xy(x, y),
+        ("LD", [Reg(x), Value(nn)]) => 0x6000 | x << 8 | value(nn, 0xff)?,
+        ("ADD", [Reg(x), Value(nn)]) => 0x7000 | x << 8 | value(nn, 0xff)?,
+        ("LD", [Reg(x), Reg(y)]) => 0x8000 | xy(x, y),
+        ("OR", [Reg(x), Reg(y)]) => 0x8001 | xy(x, y),
+        ("AND", [Reg(x), Reg(y)]) => 0x8002 | xy(x, y),
+        ("XOR", [Reg(x), Reg(y)]) => 0x8003 | xy(x, y),
+        ("ADD", [Reg(x), Reg(y)]) => 0x8004 | xy(x, y),
+        ("SUB", [Reg(x), Reg(y)]) => 0x8005 | xy(x, y),
+        ("SHR", [Reg(x), Reg(y)]) => 0x8006 | xy(x, y),
+        ("SUBN", [Reg(x), Reg(y)]) => 0x8007 | xy(x, y),
+        ("SHL", [Reg(x), Reg(y)]) => 0x800e | xy(x, y),
+        ("SNE", [Reg(x), Reg(y)]) => 0x9000 | xy(x, y),
+        ("LD", [I, Value(nnn)]) => 0xa000 | value(nnn, 0xfff)?,
+        ("JP", [Reg(0), Value(nnn)]) => 0xb000 | value(nnn, 0xfff)?,
+        ("RND", [Reg(x), Value(nn)]) => 0xc000 | x << 8 | value(nn, 0xff)?,
+        ("DRW", [Reg(x), Reg(y), Value(n)]) => 0xd000 | xy(x, y) | value(n, 0xf)?,
+        ("SKP", [Reg(x)]) => 0xe09e | x << 8,
+        ("SKNP", [Reg(x)]) => 0xe0a1 | x << 8,
+        ("PLANE", [Value(n)]) => 0xf001 | value(n, 0xf)? << 8,
+        ("LD", [I, Long(None)]) => 0xf000,
+        ("LD", [I, Long(Some(nnnn))]) => return Ok(Some(vec![0xf000, value(nnnn, 0xffff)?])),
+        ("AUDIO", []) => 0xf002,
+        ("PITCH", [Reg(x)]) => 0xf03a | x << 8,
+        ("LD", [Reg(x), Dt]) => 0xf007 | x << 8,
+        ("LD", [Reg(x), K]) => 0xf00a | x << 8,
+        ("LD", [Dt, Reg(x)]) => 0xf015 | x << 8,
+        ("LD", [St, Reg(x)]) => 0xf018 | x << 8,
+        ("ADD", [I, Reg(x)]) => 0xf01e | x << 8,
+        ("LD", [F, Reg(x)]) => 0xf029 | x << 8,
+        ("LD", [Hf, Reg(x)]) => 0xf030 | x << 8,
+        ("LD", [B, Reg(x)]) => 0xf033 | x << 8,
+        ("LD", [IndirectI, Reg(x)]) => 0xf055 | x << 8,
+        ("LD", [Reg(x), IndirectI]) => 0xf065 | x << 8,
+        ("LD", [R, Reg(x)]) => 0xf075 | x << 8,
+        ("LD", [Reg(x), R]) => 0xf085 | x << 8,
+        _ => return Ok(None),
+    };
+    Ok(Some(vec![opcode]))
+}
+
+fn parse_operand(text: &str) -> Option<Operand> {
+    let upper = text.to_ascii_uppercase();
+    let operand = match upper.as_str() {
+        "I" => Operand::I,
+        "[I]" => Operand::IndirectI,
+        "DT" => Operand::Dt,
+        "ST" => Operand::St,
+        "K" => Operand::K,
+        "F" => Operand::F,
+        "HF" => Operand::Hf,
+        "B" => Operand::B,
+        "R" => Operand::R,
+        "LONG" => Operand::Long(None),
+        _ if upper.starts_with("LONG ") => Operand::Long(Some(parse_value(text[5..].trim())?)),
+        // V and digits is never a label, V16 is not a register either
+        _ if upper.len() > 1
+            && upper.starts_with('V')
+            && upper[1..].chars().all(|c| c.is_ascii_digit()) =>
+        {
+            Operand::Reg(parse_register(&upper)?)
+        }
+        _ => match parse_register(&upper) {
+            Some(reg) => Operand::Reg(reg),
+            None => Operand::Value(parse_value(text)?),
+        },
+    };
+    Some(operand)
+}
+
+/// `V0` to `V15` as printed by the disassembler, or `VA` to `VF`.
+fn parse_register(upper: &str) -> Option<u16> {
+    let digits = upper.strip_prefix('V')?;
+    let reg = if digits.len() == 1 {
+        u16::from_str_radix(digits, 16).ok()?
+    } else {
+        digits.parse().ok()?
+    };
+    (reg < 16).then_some(reg)
+}
+
+fn parse_value(text: &str) -> Option<Value> {
+    if let Some((number, _)) = parse_number(text) {
+        return Some(Value::Number(number));
+    }
+    let mut chars = text.chars();
+    let is_label = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    is_label.then(|| Value::Label(text.to_string()))
+}
+
+/// A number and the bytes it takes in `DB`: one per two hexadecimal or eight binary digits, and
+/// as few as it fits in for decimals.
+fn parse_number(text: &str) -> Option<(u32, usize)> {
+    let lower = text.to_ascii_lowercase();
+    let (digits, radix, digits_per_byte) = if let Some(digits) = lower.strip_prefix("0x") {
+        (digits, 16, 2)
+    } else if let Some(digits) = lower.strip_prefix("0b") {
+        (digits, 2, 8)
+    } else {
+        let value: u32 = lower.parse().ok()?;
+        let width = (value.checked_ilog2().unwrap_or(0) / 8 + 1) as usize;
+        return Some((value, width));
+    };
+    // Rules out a sign, which from_str_radix accepts
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+    let value = u32::from_str_radix(digits, radix).ok()?;
+    Some((value, digits.len().div_ceil(digits_per_byte)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm::disassemble;
+
+    #[test]
+    fn test_disassembly_round_trip() {
+        let opcodes: [u16; 50] = [
+            0x00e0, 0x00ee, 0x00c4, 0x00fb, 0x00fc, 0x00fd, 0x00fe, 0x00ff, 0x0123, 0x1234, 0x2345,
+            0x3a12, 0x4b34, 0x5120, 0x6fff, 0x7e01, 0x8ab0, 0x8ab1, 0x8ab2, 0x8ab3, 0x8ab4, 0x8ab5,
+            0x8ab6, 0x8ab7, 0x8abe, 0x9cd0, 0xa2f0, 0xb300, 0xc50f, 0xd125, 0xe49e, 0xe5a1, 0xf201,
+            0xf000, 0xf002, 0xf43a, 0xf107, 0xf20a, 0xf315, 0xf418, 0xf51e, 0xf629, 0xf630, 0xf733,
+            0xf855, 0xf965, 0xfa75, 0xfb85, 0x5121, 0xe1ff,
+        ];
+        let src: Vec<String> = opcodes.iter().map(|op| disassemble(*op)).collect();
+        let rom: Vec<u8> = opcodes.iter().flat_map(|op| op.to_be_bytes()).collect();
+        assert_eq!(assemble(&src.join("\n")), Ok(rom));
+    }
+
+    #[test]
+    fn test_labels() {
+        let src = "
+            ; Count down from 3
+            start:  LD V0, 3
+            loop:
+                    ADD V0, 0xff ; same as minus one
+                    SE v0, 0
+                    JP loop
+                    LD I, sprite
+                    CALL start
+            sprite: DB 0b11110000, 0x90, 144, 0x00f0
+        ";
+        assert_eq!(
+            assemble(src),
+            Ok(vec![
+                0x60, 0x03, 0x70, 0xff, 0x30, 0x00, 0x12, 0x02, 0xa2, 0x0c, 0x22, 0x00, 0xf0, 0x90,
+                0x90, 0x00, 0xf0
+            ])
+        );
+        assert_eq!(
+            assemble_at("JP here\nhere: JP V0, here", 0x600),
+            Ok(vec![0x16, 0x02, 0xb6, 0x02])
+        );
+    }
+
+    #[test]
+    fn test_long_load() {
+        assert_eq!(
+            assemble("LD I, LONG data\nDB 1\ndata: DB 2"),
+            Ok(vec![0xf0, 0x00, 0x02, 0x05, 0x01, 0x02])
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(
+            assemble("CLS\nMOV V0, V1"),
+            Err(AsmError::UnknownInstruction {
+                line: 2,
+                text: "MOV V0, V1".to_string()
+            })
+        );
+        assert_eq!(
+            assemble("LD V16, 1"),
+            Err(AsmError::InvalidOperand {
+                line: 1,
+                operand: "V16".to_string()
+            })
+        );
+        assert_eq!(
+            assemble("LD V0, 0x100"),
+            Err(AsmError::ValueTooLarge {
+                line: 1,
+                value: 0x100,
+                max: 0xff
+            })
+        );
+        assert_eq!(
+            assemble("JP nowhere"),
+            Err(AsmError::UnknownLabel {
+                line: 1,
+                label: "nowhere".to_string()
+            })
+        );
+        assert_eq!(
+            assemble("a: CLS\na: RET"),
+            Err(AsmError::DuplicateLabel {
+                line: 2,
+                label: "a".to_string()
+            })
+        );
+        assert_eq!(
+            assemble("DB -1").unwrap_err().to_string(),
+            "Line 1: invalid operand -1"
+        );
+    }
+}
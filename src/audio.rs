@@ -0,0 +1,228 @@
+use sdl3::audio::{AudioCallback, AudioStream};
+
+/// Default beep frequency in Hz.
+pub const DEFAULT_PITCH: f32 = 440.0;
+
+/// Default master volume, from 0 to 1.
+pub const DEFAULT_VOLUME: f32 = 0.25;
+
+/// Change of the master volume per key press.
+pub const VOLUME_STEP: f32 = 0.05;
+
+/// Amplitude of the samples at full volume, loud enough while leaving headroom.
+const MAX_AMPLITUDE: f32 = 0.2;
+
+/// Time taken to fade the beep in or out, so that it doesn't click.
+const RAMP_MS: f32 = 5.0;
+
+/// Shape of the beep.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Waveform {
+    #[default]
+    Square,
+    Sine,
+    Triangle,
+    Noise,
+}
+
+impl Waveform {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "square" => Ok(Waveform::Square),
+            "sine" => Ok(Waveform::Sine),
+            "triangle" => Ok(Waveform::Triangle),
+            "noise" => Ok(Waveform::Noise),
+            _ => Err(format!(
+                "Unknown tone '{}', expected square, sine, triangle or noise",
+                name
+            )),
+        }
+    }
+}
+
+/// Plays a tone of the given waveform while the beep is on, with a short fade in and out.
+pub struct ToneGenerator {
+    pub waveform: Waveform,
+    /// Fraction of a period per sample, the pitch divided by the sample rate.
+    pub phase_inc: f32,
+    pub phase: f32,
+    pub volume: f32,
+    /// Xorshift state for the noise waveform.
+    noise: u32,
+    sample_rate: f32,
+    /// XO-CHIP 1-bit pattern played instead of the waveform, when a rom loaded one.
+    pattern: Option<[u8; 16]>,
+    /// Pattern bits per sample.
+    pattern_inc: f32,
+    /// Position in the pattern, in bits.
+    pattern_pos: f32,
+    /// Whether the beep should be heard, the envelope follows it.
+    playing: bool,
+    /// Current envelope level, from 0 (silent) to 1.
+    gain: f32,
+    /// Change of the envelope level per sample while fading.
+    gain_step: f32,
+}
+
+impl ToneGenerator {
+    pub fn new(waveform: Waveform, pitch: f32, sample_rate: i32, volume: f32) -> Self {
+        ToneGenerator {
+            waveform,
+            phase_inc: pitch / sample_rate as f32,
+            phase: 0.0,
+            volume,
+            noise: 0x1234_5678,
+            sample_rate: sample_rate as f32,
+            pattern: None,
+            pattern_inc: 0.0,
+            pattern_pos: 0.0,
+            playing: false,
+            gain: 0.0,
+            gain_step: 1000.0 / (RAMP_MS * sample_rate as f32),
+        }
+    }
+
+    /// Set the master volume, clamped between 0 and 1.
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = amplitude(volume);
+    }
+
+    /// Start or stop the beep, it fades in or out over a few milliseconds.
+    pub fn set_playing(&mut self, playing: bool) {
+        self.playing = playing;
+    }
+
+    /// Play `pattern` at `rate` bits per second, or the waveform if there is none.
+    pub fn set_pattern(&mut self, pattern: Option<[u8; 16]>, rate: f32) {
+        if pattern != self.pattern {
+            self.pattern_pos = 0.0;
+        }
+        self.pattern = pattern;
+        self.pattern_inc = rate / self.sample_rate;
+    }
+
+    /// Next sample, between -volume and volume.
+    fn sample(&mut self) -> f32 {
+        if let Some(pattern) = &self.pattern {
+            let bit = self.pattern_pos as usize;
+            let value = if pattern[bit / 8] >> (7 - bit % 8) & 1 == 1 {
+                1.0
+            } else {
+                -1.0
+            };
+            self.pattern_pos = (self.pattern_pos + self.pattern_inc) % 128.0;
+            return value * self.volume;
+        }
+
+        let value = match self.waveform {
+            Waveform::Square => {
+                if self.phase <= 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Sine => (self.phase * std::f32::consts::TAU).sin(),
+            Waveform::Triangle => 1.0 - 4.0 * (self.phase - 0.5).abs(),
+            Waveform::Noise => {
+                self.noise ^= self.noise << 13;
+                self.noise ^= self.noise >> 17;
+                self.noise ^= self.noise << 5;
+                self.noise as f32 / u32::MAX as f32 * 2.0 - 1.0
+            }
+        };
+        self.phase = (self.phase + self.phase_inc) % 1.0;
+        value * self.volume
+    }
+
+    /// Next sample with the envelope applied, moving it towards the target level.
+    fn envelope_sample(&mut self) -> f32 {
+        let target = if self.playing { 1.0 } else { 0.0 };
+        if self.gain == 0.0 && target == 0.0 {
+            return 0.0;
+        }
+        self.gain = if self.gain < target {
+            (self.gain + self.gain_step).min(target)
+        } else {
+            (self.gain - self.gain_step).max(target)
+        };
+        self.sample() * self.gain
+    }
+}
+
+/// Amplitude of the samples at the given master volume.
+pub fn amplitude(volume: f32) -> f32 {
+    volume.clamp(0.0, 1.0) * MAX_AMPLITUDE
+}
+
+impl AudioCallback<f32> for ToneGenerator {
+    fn callback(&mut self, stream: &mut AudioStream, requested: i32) {
+        let out: Vec<f32> = (0..requested).map(|_| self.envelope_sample()).collect();
+        stream.put_data_f32(&out).expect("no bueno");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn samples(waveform: Waveform) -> Vec<f32> {
+        // Four samples per period
+        let mut tone = ToneGenerator::new(waveform, 100.0, 400, 0.5);
+        (0..4).map(|_| tone.sample()).collect()
+    }
+
+    #[test]
+    fn test_waveforms() {
+        assert_eq!(samples(Waveform::Square), [0.5, 0.5, 0.5, -0.5]);
+        assert_eq!(samples(Waveform::Triangle), [-0.5, 0.0, 0.5, 0.0]);
+        let sine = samples(Waveform::Sine);
+        assert!((sine[1] - 0.5).abs() < 1e-6 && (sine[3] + 0.5).abs() < 1e-6);
+        let noise = samples(Waveform::Noise);
+        assert!(noise.iter().all(|sample| sample.abs() <= 0.5));
+        assert_ne!(noise[0], noise[1]);
+
+        assert_eq!(Waveform::from_name("sine"), Ok(Waveform::Sine));
+        assert!(Waveform::from_name("saw").is_err());
+    }
+
+    #[test]
+    fn test_pattern() {
+        let mut tone = ToneGenerator::new(Waveform::Sine, 440.0, 400, 1.0);
+        let mut pattern = [0; 16];
+        pattern[0] = 0b1010_0000;
+        // Half a bit per sample
+        tone.set_pattern(Some(pattern), 200.0);
+        let samples: Vec<f32> = (0..6).map(|_| tone.sample()).collect();
+        assert_eq!(samples, [1.0, 1.0, -1.0, -1.0, 1.0, 1.0]);
+        // Back to the sine wave, from where it stopped
+        tone.set_pattern(None, 200.0);
+        assert_eq!(tone.sample(), 0.0);
+    }
+
+    #[test]
+    fn test_envelope() {
+        // Fades over 5 samples
+        let mut tone = ToneGenerator::new(Waveform::Square, 1.0, 1000, 1.0);
+        assert_eq!(tone.envelope_sample(), 0.0);
+        tone.set_playing(true);
+        let samples: Vec<f32> = (0..6).map(|_| tone.envelope_sample()).collect();
+        for (sample, expected) in samples.iter().zip([0.2, 0.4, 0.6, 0.8, 1.0, 1.0]) {
+            assert!((sample - expected).abs() < 1e-6);
+        }
+        tone.set_playing(false);
+        let samples: Vec<f32> = (0..6).map(|_| tone.envelope_sample()).collect();
+        assert!((samples[0] - 0.8).abs() < 1e-6);
+        assert_eq!(samples[5], 0.0);
+    }
+
+    #[test]
+    fn test_volume() {
+        let mut tone = ToneGenerator::new(Waveform::Square, 100.0, 400, amplitude(DEFAULT_VOLUME));
+        assert_eq!(tone.sample(), 0.05);
+        tone.set_volume(2.0);
+        assert_eq!(tone.sample(), MAX_AMPLITUDE);
+        tone.set_volume(-1.0);
+        assert_eq!(tone.sample(), 0.0);
+    }
+}
@@ -0,0 +1,219 @@
+use crate::palette::Palette;
+use chip8_core::{Chip8Display, PixelPalette};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Shortest frame delay in hundredths of a second that viewers honor.
+/// Anything faster is usually slowed down to 10, so frames closer than this are dropped.
+const MIN_GIF_DELAY_CS: u64 = 2;
+
+/// Plane values (0b00 to 0b11) of the display, each pixel repeated `scale` times in both directions.
+/// Returns the width and height of the image along with the values, row by row.
+pub fn scaled_values(display: &Chip8Display, scale: usize) -> (usize, usize, Vec<u8>) {
+    let width = display.width() * scale;
+    let height = display.height() * scale;
+    let mut values = vec![0; width * height];
+    for (x, y, value) in display.iter_pixels() {
+        for row in y * scale..(y + 1) * scale {
+            values[row * width + x * scale..][..scale].fill(value);
+        }
+    }
+    (width, height, values)
+}
+
+/// Encode the display as an RGB PNG image in the palette colors.
+pub fn encode_png(
+    writer: impl Write,
+    display: &Chip8Display,
+    palette: &Palette,
+    scale: usize,
+) -> Result<(), png::EncodingError> {
+    let (width, height, values) = scaled_values(display, scale);
+    let mut encoder = png::Encoder::new(writer, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let data: Vec<u8> = values
+        .into_iter()
+        .flat_map(|value| {
+            let [r, g, b, _] = palette.rgba(value);
+            [r, g, b]
+        })
+        .collect();
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&data)
+}
+
+/// Write the display to `screenshot_<timestamp>.png` in the working directory.
+/// Returns the path of the new file.
+pub fn save_screenshot(
+    display: &Chip8Display,
+    palette: &Palette,
+    scale: usize,
+) -> io::Result<String> {
+    let path = format!("screenshot_{}.png", timestamp());
+    let file = BufWriter::new(File::create(&path)?);
+    encode_png(file, display, palette, scale)?;
+    Ok(path)
+}
+
+/// Animated GIF of the display, at the high resolution size so that both modes fit.
+pub struct GifRecorder<W: Write> {
+    encoder: gif::Encoder<W>,
+    /// When the previous frame was written, in hundredths of a second since the start.
+    last_frame_cs: Option<u64>,
+}
+
+impl<W: Write> GifRecorder<W> {
+    pub fn new(
+        writer: W,
+        display: &Chip8Display,
+        palette: &Palette,
+    ) -> Result<Self, gif::EncodingError> {
+        // Pixels are written as plane values, which index into the palette colors
+        let colors: Vec<u8> = (0..4)
+            .flat_map(|value| {
+                let [r, g, b, _] = palette.rgba(value);
+                [r, g, b]
+            })
+            .collect();
+        let (lores_width, lores_height) = display.lores_size();
+        let width = (lores_width * 2) as u16;
+        let height = (lores_height * 2) as u16;
+        let mut encoder = gif::Encoder::new(writer, width, height, &colors)?;
+        encoder.set_repeat(gif::Repeat::Infinite)?;
+        Ok(GifRecorder {
+            encoder,
+            last_frame_cs: None,
+        })
+    }
+
+    /// Append the display as it looks `elapsed` after the recording started.
+    /// Frames arriving too soon after the previous one are skipped.
+    pub fn add_frame(
+        &mut self,
+        display: &Chip8Display,
+        elapsed: Duration,
+    ) -> Result<(), gif::EncodingError> {
+        let elapsed_cs = elapsed.as_millis() as u64 / 10;
+        // The gap since the previous frame is used as the delay of this one
+        let delay = match self.last_frame_cs {
+            Some(last) if elapsed_cs < last + MIN_GIF_DELAY_CS => return Ok(()),
+            Some(last) => elapsed_cs - last,
+            None => MIN_GIF_DELAY_CS,
+        };
+        self.last_frame_cs = Some(elapsed_cs);
+
+        let scale = if display.is_hires() { 1 } else { 2 };
+        let (width, height, values) = scaled_values(display, scale);
+        let mut frame = gif::Frame::from_indexed_pixels(width as u16, height as u16, values, None);
+        frame.delay = delay as u16;
+        self.encoder.write_frame(&frame)
+    }
+
+    /// Write the end of the file.
+    pub fn finish(self) -> Result<W, gif::EncodingError> {
+        self.encoder.into_inner()
+    }
+}
+
+/// A GIF recording of the display to `recording_<timestamp>.gif` in the working directory.
+pub struct Recording {
+    recorder: GifRecorder<BufWriter<File>>,
+    pub path: String,
+    start: Instant,
+}
+
+impl Recording {
+    pub fn start(display: &Chip8Display, palette: &Palette) -> Result<Self, gif::EncodingError> {
+        let path = format!("recording_{}.gif", timestamp());
+        let file = BufWriter::new(File::create(&path)?);
+        Ok(Recording {
+            recorder: GifRecorder::new(file, display, palette)?,
+            path,
+            start: Instant::now(),
+        })
+    }
+
+    /// Append the display as it looks now.
+    pub fn add_frame(&mut self, display: &Chip8Display) -> Result<(), gif::EncodingError> {
+        self.recorder.add_frame(display, self.start.elapsed())
+    }
+
+    /// Write the end of the file.
+    pub fn finish(self) -> Result<(), gif::EncodingError> {
+        self.recorder.finish()?.flush()?;
+        Ok(())
+    }
+}
+
+/// Seconds since the unix epoch, to name capture files.
+pub fn timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_encode_png() {
+        let mut display = Chip8Display::new();
+        *display.get_mut(0, 1, 0).unwrap() = true;
+        let palette = Palette::default();
+
+        let mut bytes = vec![];
+        encode_png(&mut bytes, &display, &palette, 2).unwrap();
+
+        let mut reader = png::Decoder::new(Cursor::new(bytes)).read_info().unwrap();
+        let mut data = vec![0; reader.output_buffer_size().unwrap()];
+        let info = reader.next_frame(&mut data).unwrap();
+        assert_eq!((info.width, info.height), (128, 64));
+        // Pixel (1, 0) covers image pixels 2 and 3 of the first two rows
+        let rgb = |x: usize, y: usize| {
+            let idx = (x + y * 128) * 3;
+            (data[idx], data[idx + 1], data[idx + 2])
+        };
+        let (bg, fg) = (palette.color(0), palette.color(1));
+        assert_eq!(rgb(1, 0), (bg.r, bg.g, bg.b));
+        assert_eq!(rgb(2, 1), (fg.r, fg.g, fg.b));
+        assert_eq!(rgb(3, 0), (fg.r, fg.g, fg.b));
+        assert_eq!(rgb(4, 0), (bg.r, bg.g, bg.b));
+    }
+
+    #[test]
+    fn test_gif_recorder() {
+        let mut display = Chip8Display::new();
+        let mut recorder = GifRecorder::new(vec![], &display, &Palette::default()).unwrap();
+        recorder.add_frame(&display, Duration::ZERO).unwrap();
+        *display.get_mut(0, 0, 0).unwrap() = true;
+        // Too close to the previous frame
+        recorder
+            .add_frame(&display, Duration::from_millis(10))
+            .unwrap();
+        recorder
+            .add_frame(&display, Duration::from_millis(33))
+            .unwrap();
+        display.set_hires(true);
+        recorder
+            .add_frame(&display, Duration::from_millis(60))
+            .unwrap();
+        let bytes = recorder.finish().unwrap();
+
+        let mut decoder = gif::DecodeOptions::new();
+        decoder.set_color_output(gif::ColorOutput::Indexed);
+        let mut decoder = decoder.read_info(bytes.as_slice()).unwrap();
+        assert_eq!((decoder.width(), decoder.height()), (128, 64));
+        let mut frames = vec![];
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            frames.push((frame.delay, frame.buffer[0], frame.buffer[2]));
+        }
+        // Low resolution pixels are doubled
+        assert_eq!(frames, [(2, 0, 0), (3, 1, 0), (3, 0, 0)]);
+    }
+}
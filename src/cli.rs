@@ -0,0 +1,1037 @@
+use crate::audio::{DEFAULT_PITCH, DEFAULT_VOLUME, Waveform};
+use crate::config::{Config, DEFAULT_CONFIG_PATH};
+use crate::crt;
+use crate::gamepad::PadMap;
+use crate::keymap::KeyMap;
+use crate::palette::{Palette, parse_hex_color, parse_xo_palette};
+use chip8_core::{MAX_DISPLAY_SIZE, PROGRAM_START, Platform, Quirks, RAM_SIZE, RomProfile};
+use std::collections::HashSet;
+use std::ops::Range;
+
+/// Extensions of the files a `--playlist` directory contributes, also when followed by `.gz`.
+const ROM_EXTENSIONS: [&str; 4] = ["ch8", "c8", "sc8", "xo8"];
+
+/// Default chip8 clock speed.
+const DEFAULT_CLOCK_HZ: u32 = 700;
+
+/// Above this, a cycle would take less than a microsecond.
+const MAX_CLOCK_HZ: u32 = 1_000_000;
+
+/// Default display refresh rate, and rate of the 60Hz display interrupt.
+/// For some reason, the quirks test will not register the display interrupt wait unless the frame
+/// rate is slightly lower than 60fps.
+const DEFAULT_FPS: f64 = 59.5;
+
+/// Above this, frames would be shorter than a millisecond.
+const MAX_FPS: f64 = 1000.0;
+
+/// Default window size, as a multiple of the low resolution display.
+const DEFAULT_SCALE: usize = 12;
+
+/// Keeps the window within the largest screens.
+const MAX_SCALE: usize = 128;
+
+/// Command line options, on top of the config file.
+#[derive(Debug)]
+pub struct Options {
+    /// First rom of the playlist, run on startup.
+    pub rom_path: String,
+    /// Every rom given, switched between at runtime. Starts with `rom_path`.
+    pub playlist: Vec<String>,
+    /// Stop after this many cycles, 0 to run forever.
+    pub num_cycles: usize,
+    /// Where the rom is loaded and execution starts.
+    pub load_addr: u16,
+    /// Bytes of ram, 64KB with `--platform xochip`.
+    pub ram_size: usize,
+    /// Number of header bytes to drop from the start of the rom.
+    pub skip: usize,
+    pub palette: Palette,
+    /// Number of chip8 clock cycles per second, if set by the config or a flag.
+    pub clock_hz: Option<u32>,
+    /// The clock speed was given by `--clock`, rather than the config file or nothing.
+    pub clock_on_cli: bool,
+    /// Display refresh rate.
+    pub fps: f64,
+    /// Skip rendering frames while the interpreter is behind, instead of rendering every frame.
+    pub render_skip: bool,
+    /// Window size as a multiple of the low resolution display.
+    pub scale: usize,
+    /// Low resolution display size, if not the usual 64x32.
+    pub resolution: Option<(usize, usize)>,
+    /// Fade pixels out over a few frames to reduce flicker.
+    pub phosphor: bool,
+    /// Draw scanlines and a glow around lit pixels.
+    pub crt: bool,
+    /// Strength of the CRT effect, from 0 to 1.
+    pub crt_intensity: f32,
+    /// Show a keypad that can be clicked or touched.
+    pub touchpad: bool,
+    /// Show the keypad with the keys held down highlighted, without playing it with the mouse.
+    pub show_keys: bool,
+    /// Beep waveform.
+    pub tone: Waveform,
+    /// Beep frequency in Hz.
+    pub pitch: f32,
+    /// Master volume from 0 to 1.
+    pub volume: f32,
+    /// Start without sound, toggled with M.
+    pub mute: bool,
+    /// Config file in use, where a volume changed while running is saved.
+    pub config_path: Option<String>,
+    /// Print the display when exiting.
+    pub dump_display_on_exit: bool,
+    /// Write the whole ram to this file when exiting.
+    pub dump_ram_on_exit: Option<String>,
+    /// Print how long events, cycles and rendering took per frame when exiting.
+    pub profile_frames: bool,
+    /// Charge instructions their approximate COSMAC VIP cost instead of one cycle each.
+    pub accurate_timing: bool,
+    /// Seed for the random numbers of 0xcxnn, so that runs can be reproduced.
+    pub seed: Option<u64>,
+    /// Registers set before the first cycle, as (register, value).
+    pub set_regs: Vec<(usize, u8)>,
+    /// Bytes of ram set before the first cycle, after loading the rom, as (address, value).
+    pub pokes: Vec<(u16, u8)>,
+    /// Where execution starts instead of the load address.
+    pub entry_pc: Option<u16>,
+    /// Quirks from the platform and config file, if either sets any.
+    pub quirks: Option<Quirks>,
+    /// The quirks were picked by `--profile` or `--platform`, rather than the config file or
+    /// nothing.
+    pub quirks_on_cli: bool,
+    /// Font of 0xfx29 from the config file, in place of the built-in one.
+    pub font: Option<[u8; 80]>,
+    /// Big font of 0xfx30 from the config file, in place of the built-in one.
+    pub big_font: Option<[u8; 100]>,
+    /// Print the disassembled rom instead of running it.
+    pub disasm: bool,
+    /// Assemble the rom path as source into this file instead of running it.
+    pub assemble: Option<String>,
+    /// Report the words of the rom that aren't instructions instead of running it.
+    pub validate: bool,
+    /// Pause before the first cycle.
+    pub start_paused: bool,
+    /// Pause while the window doesn't have focus.
+    pub pause_unfocused: bool,
+    /// Record the last executed instructions, printed when exiting or on error.
+    pub trace: bool,
+    /// Warn once when the program counter drops below 0x200 (or the load address).
+    pub warn_low_pc: bool,
+    /// Halt with an error instead of executing below 0x200 (or the load address).
+    pub strict_pc: bool,
+    /// Warn once when the program counter becomes odd.
+    pub warn_odd_pc: bool,
+    /// Halt with an error instead of executing at an odd address.
+    pub strict_odd_pc: bool,
+    /// Count executed instructions by kind, printed on exit.
+    pub log_opcodes: bool,
+    /// Pause before executing the instruction at any of these addresses.
+    pub breakpoints: HashSet<u16>,
+    /// Pause when any of these registers changes.
+    pub watch_regs: Vec<usize>,
+    /// Pause when the program writes to these addresses.
+    pub protect: Option<Range<usize>>,
+    /// Read debugging commands from stdin while running.
+    pub monitor: bool,
+    /// Replay the keys held from a file instead of reading the keyboard.
+    pub input_script: Option<String>,
+    /// Write the keys held to a file, to replay them with `input_script`.
+    pub record_input: Option<String>,
+    pub keymap: KeyMap,
+    pub padmap: PadMap,
+}
+
+impl Options {
+    /// Emulated 60Hz frame during which the cycle runs, whatever the speed of the host.
+    pub fn frame_of_cycle(&self, cycle: usize) -> u64 {
+        cycle as u64 * 60 / self.clock_hz.unwrap_or(DEFAULT_CLOCK_HZ) as u64
+    }
+
+    /// Number of microseconds between two chip8 clock cycles.
+    pub fn cycle_time_us(&self) -> u128 {
+        1_000_000 / self.clock_hz.unwrap_or(DEFAULT_CLOCK_HZ) as u128
+    }
+
+    /// Use the settings of a rom found in the compatibility database, over those of the config
+    /// file but not over flags.
+    pub fn apply_rom_profile(&mut self, profile: &RomProfile) {
+        if !self.clock_on_cli {
+            self.clock_hz = Some(profile.clock_hz);
+        }
+        if !self.quirks_on_cli {
+            self.quirks = Some(profile.quirks);
+        }
+    }
+
+    /// Lowest address a program is expected to execute, below it lie the font and interpreter.
+    pub fn low_pc_limit(&self) -> u16 {
+        self.load_addr.min(PROGRAM_START as u16)
+    }
+
+    /// Number of microseconds between two rendered frames.
+    pub fn frame_time_us(&self) -> u128 {
+        (1_000_000.0 / self.fps) as u128
+    }
+}
+
+pub fn usage(cmd: &str) -> String {
+    format!(
+        "Usage: {} [options] path/to/rom.ch8 [more/roms.ch8...]\n\
+         Use - as the rom path to read it from stdin. Several roms make a playlist.\n\
+         Options:\n  \
+           --config PATH        read settings from a toml file (default chip8.toml if present)\n  \
+           --fg RRGGBB          foreground color\n  \
+           --bg RRGGBB          background color\n  \
+           --xo-palette C0,C1,C2,C3  colors of XO-CHIP plane values 0 to 3, C0 and C1 being bg and fg\n  \
+           --grid-color RRGGBB  pixel grid color\n  \
+           --help               print this message\n  \
+           --max-cycles N       stop after N cycles, 0 to run forever (default)\n  \
+           --clock HZ           cpu clock speed (default {}), also --cycles-per-second\n  \
+           --fps N              display refresh rate (default {})\n  \
+           --no-render-skip     render every frame, even when the interpreter falls behind\n  \
+           --scale N            window size as a multiple of 64x32 (default {})\n  \
+           --resolution WxH     low resolution display size, e.g. 64x64 for HiRes CHIP-8\n  \
+           --phosphor           fade pixels out slowly to reduce flicker\n  \
+           --crt                draw scanlines and glowing pixels like a CRT, toggled with F8\n  \
+           --crt-intensity X    strength of the CRT effect from 0 to 1 (default {})\n  \
+           --touchpad           show a keypad to play with the mouse or a touchscreen\n  \
+           --show-keys          show the keypad with the keys held down highlighted\n  \
+           --tone WAVEFORM      beep waveform: square, sine, triangle or noise\n  \
+           --pitch HZ           beep frequency (default {})\n  \
+           --volume X           master volume from 0 to 1 (default {}), changed with + and -\n  \
+           --mute               start without sound, toggled with M\n  \
+           --playlist DIR       add the roms of DIR to the playlist, switched with [ and ]\n  \
+           --platform NAME      machine the rom targets: cosmac, eti660, schip or xochip\n  \
+           --profile NAME       quirks of an interpreter: cosmac, schip, xochip or octo\n  \
+           --load-addr ADDR     load the rom and start at ADDR (default 0x200)\n  \
+           --skip N             drop the first N bytes of the rom\n  \
+           --dump-display-on-exit  print the display on exit\n  \
+           --dump-ram-on-exit PATH  write the ram to PATH on exit, as raw bytes\n  \
+           --profile-frames     print the time spent per frame in events, cycles and rendering\n  \
+           --accurate-timing    slow instructions take several cycles like on the COSMAC VIP\n  \
+           --seed N             draw the same random numbers on every run\n  \
+           --set-reg VX=NN      set a register before the first cycle, e.g. V5=0x20\n  \
+           --poke ADDR=NN       set a byte of ram before the first cycle, e.g. 0x300=0xab\n  \
+           --pc ADDR            start executing at ADDR instead of the load address\n  \
+           --disasm             print the disassembled rom and exit\n  \
+           --assemble OUT       assemble the rom path as source into the rom OUT and exit\n  \
+           --validate           list unknown opcodes in the rom (best-effort) and exit\n  \
+           --start-paused       pause before the first instruction\n  \
+           --pause-unfocused    pause while the window is in the background\n  \
+           --trace              print the last executed instructions on exit or error\n  \
+           --warn-low-pc        warn when the program counter drops below 0x200\n  \
+           --strict-pc          halt when the program counter drops below 0x200\n  \
+           --warn-odd-pc        warn when the program counter becomes odd\n  \
+           --strict-odd-pc      halt when the program counter becomes odd\n  \
+           --log-opcodes        count executed instructions by kind, printed on exit\n  \
+           --break ADDR         pause before executing the instruction at ADDR, e.g. 0x300\n  \
+           --watch-reg VX       pause when register VX changes, e.g. V5\n  \
+           --protect START:END  pause when the program writes from START to END excluded\n  \
+           --monitor            read debugging commands from stdin, type help to list them\n  \
+           --input-script PATH  replay the keys held per frame from a file\n  \
+           --record-input PATH  write the keys held per frame to a file\n  \
+           --keymap K=NAME,...  bind chip8 keys 0-F to SDL scancode names, e.g. 1=Q,4=A\n  \
+           --padmap K=NAME,...  bind chip8 keys 0-F to SDL gamepad button names, e.g. 5=a,6=b",
+        cmd,
+        DEFAULT_CLOCK_HZ,
+        DEFAULT_FPS,
+        DEFAULT_SCALE,
+        crt::DEFAULT_INTENSITY,
+        DEFAULT_PITCH,
+        DEFAULT_VOLUME
+    )
+}
+
+/// Parse the arguments following the command name.
+/// Settings come from the config file first, then flags override them.
+pub fn parse_args(args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let args: Vec<String> = args.collect();
+    let (config, config_path) = match args.iter().position(|arg| arg == "--config") {
+        Some(idx) => {
+            let path = args.get(idx + 1).ok_or("Missing value for --config")?;
+            (Config::load(path, true)?, Some(path.clone()))
+        }
+        None => (
+            Config::load(DEFAULT_CONFIG_PATH, false)?,
+            std::path::Path::new(DEFAULT_CONFIG_PATH)
+                .exists()
+                .then(|| DEFAULT_CONFIG_PATH.to_string()),
+        ),
+    };
+
+    let mut args = args.into_iter();
+    let mut playlist = vec![];
+    let mut num_cycles = 0;
+    let mut platform = None;
+    let mut profile = None;
+    let mut load_addr = None;
+    let mut skip = 0;
+    let mut palette = Palette::default();
+    config.apply_palette(&mut palette)?;
+    let font = config.font()?;
+    let big_font = config.big_font()?;
+    let mut clock_hz = config.clock.map(check_clock).transpose()?;
+    let mut clock_on_cli = false;
+    let mut scale = config
+        .scale
+        .map(check_scale)
+        .transpose()?
+        .unwrap_or(DEFAULT_SCALE);
+    let mut tone = match &config.tone {
+        Some(name) => Waveform::from_name(name)?,
+        None => Waveform::default(),
+    };
+    let mut pitch = config
+        .pitch
+        .map(check_pitch)
+        .transpose()?
+        .unwrap_or(DEFAULT_PITCH);
+    let mut volume = config
+        .volume
+        .map(check_volume)
+        .transpose()?
+        .unwrap_or(DEFAULT_VOLUME);
+    let mut fps = DEFAULT_FPS;
+    let mut render_skip = true;
+    let mut dump_display_on_exit = false;
+    let mut dump_ram_on_exit = None;
+    let mut profile_frames = false;
+    let mut accurate_timing = false;
+    let mut seed = None;
+    let mut set_regs = vec![];
+    let mut pokes = vec![];
+    let mut entry_pc = None;
+    let mut disasm = false;
+    let mut assemble = None;
+    let mut validate = false;
+    let mut phosphor = false;
+    let mut resolution = None;
+    let mut crt = false;
+    let mut crt_intensity = crt::DEFAULT_INTENSITY;
+    let mut mute = false;
+    let mut touchpad = false;
+    let mut show_keys = false;
+    let mut start_paused = false;
+    let mut pause_unfocused = false;
+    let mut trace = false;
+    let mut warn_low_pc = false;
+    let mut strict_pc = false;
+    let mut warn_odd_pc = false;
+    let mut strict_odd_pc = false;
+    let mut log_opcodes = false;
+    let mut breakpoints = HashSet::new();
+    let mut watch_regs = vec![];
+    let mut protect = None;
+    let mut monitor = false;
+    let mut input_script = None;
+    let mut record_input = None;
+    let mut keymap = KeyMap::default();
+    config.apply_keymap(&mut keymap)?;
+    let mut padmap = PadMap::default();
+    config.apply_padmap(&mut padmap)?;
+
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| {
+            args.next()
+                .ok_or_else(|| format!("Missing value for {}", name))
+        };
+        match arg.as_str() {
+            "--fg" => palette.colors[1] = parse_hex_color(&value("--fg")?)?,
+            "--bg" => palette.colors[0] = parse_hex_color(&value("--bg")?)?,
+            "--xo-palette" => palette.colors = parse_xo_palette(&value("--xo-palette")?)?,
+            "--grid-color" => palette.grid = parse_hex_color(&value("--grid-color")?)?,
+            "--platform" => platform = Some(Platform::from_name(&value("--platform")?)?),
+            "--profile" => profile = Some(Quirks::from_profile(&value("--profile")?)?),
+            "--load-addr" => load_addr = Some(parse_address(&value("--load-addr")?)?),
+            "--skip" => {
+                let bytes = value("--skip")?;
+                skip = bytes
+                    .parse()
+                    .map_err(|_| format!("Invalid number of bytes '{}'", bytes))?;
+            }
+            "--dump-display-on-exit" => dump_display_on_exit = true,
+            "--dump-ram-on-exit" => dump_ram_on_exit = Some(value("--dump-ram-on-exit")?),
+            "--profile-frames" => profile_frames = true,
+            "--accurate-timing" => accurate_timing = true,
+            "--seed" => {
+                let number = value("--seed")?;
+                seed = Some(
+                    number
+                        .parse()
+                        .map_err(|_| format!("Invalid seed '{}'", number))?,
+                );
+            }
+            "--set-reg" => {
+                let assignment = value("--set-reg")?;
+                let (reg, byte) = split_assignment(&assignment)?;
+                set_regs.push((parse_register(reg)?, parse_byte(byte)?));
+            }
+            "--poke" => {
+                let assignment = value("--poke")?;
+                let (addr, byte) = split_assignment(&assignment)?;
+                pokes.push((parse_address(addr)?, parse_byte(byte)?));
+            }
+            "--pc" => entry_pc = Some(parse_address(&value("--pc")?)?),
+            "--disasm" => disasm = true,
+            "--assemble" => assemble = Some(value("--assemble")?),
+            "--validate" => validate = true,
+            "--phosphor" => phosphor = true,
+            "--resolution" => resolution = Some(parse_resolution(&value("--resolution")?)?),
+            "--crt" => crt = true,
+            "--crt-intensity" => {
+                let intensity = value("--crt-intensity")?;
+                crt_intensity = intensity
+                    .parse()
+                    .ok()
+                    .filter(|intensity| (0.0..=1.0).contains(intensity))
+                    .ok_or_else(|| format!("Invalid CRT intensity '{}'", intensity))?;
+            }
+            "--touchpad" => touchpad = true,
+            "--show-keys" => show_keys = true,
+            "--start-paused" => start_paused = true,
+            "--pause-unfocused" => pause_unfocused = true,
+            "--trace" => trace = true,
+            "--warn-low-pc" => warn_low_pc = true,
+            "--strict-pc" => strict_pc = true,
+            "--warn-odd-pc" => warn_odd_pc = true,
+            "--strict-odd-pc" => strict_odd_pc = true,
+            "--log-opcodes" => log_opcodes = true,
+            "--break" => {
+                breakpoints.insert(parse_address(&value("--break")?)?);
+            }
+            "--watch-reg" => watch_regs.push(parse_register(&value("--watch-reg")?)?),
+            "--protect" => protect = Some(parse_range(&value("--protect")?)?),
+            "--monitor" => monitor = true,
+            "--playlist" => playlist.extend(list_roms(&value("--playlist")?)?),
+            "--input-script" => input_script = Some(value("--input-script")?),
+            "--record-input" => record_input = Some(value("--record-input")?),
+            "--keymap" => keymap.apply(&value("--keymap")?)?,
+            "--padmap" => padmap.apply(&value("--padmap")?)?,
+            "--scale" => {
+                let factor = value("--scale")?;
+                scale = check_scale(
+                    factor
+                        .parse()
+                        .map_err(|_| format!("Invalid scale '{}'", factor))?,
+                )?;
+            }
+            "--tone" => tone = Waveform::from_name(&value("--tone")?)?,
+            "--pitch" => {
+                let hz = value("--pitch")?;
+                pitch = check_pitch(hz.parse().map_err(|_| format!("Invalid pitch '{}'", hz))?)?;
+            }
+            "--volume" => {
+                let level = value("--volume")?;
+                volume = check_volume(
+                    level
+                        .parse()
+                        .map_err(|_| format!("Invalid volume '{}'", level))?,
+                )?;
+            }
+            "--mute" => mute = true,
+            "--config" => {
+                // Already loaded
+                value("--config")?;
+            }
+            "--max-cycles" => {
+                let cycles = value("--max-cycles")?;
+                num_cycles = cycles
+                    .parse()
+                    .map_err(|_| format!("Invalid number of cycles '{}'", cycles))?;
+            }
+            "--fps" => {
+                let rate = value("--fps")?;
+                fps = rate
+                    .parse()
+                    .ok()
+                    .filter(|rate| (1.0..=MAX_FPS).contains(rate))
+                    .ok_or_else(|| format!("Invalid frame rate '{}'", rate))?;
+            }
+            "--no-render-skip" => render_skip = false,
+            "--clock" | "--cycles-per-second" => {
+                let hz = value(&arg)?;
+                clock_hz = Some(check_clock(
+                    hz.parse()
+                        .map_err(|_| format!("Invalid clock speed '{}'", hz))?,
+                )?);
+                clock_on_cli = true;
+            }
+            _ if arg.starts_with("--") => return Err(format!("Unknown option {}", arg)),
+            // The cycle count used to be a positional argument
+            _ if arg.parse::<u64>().is_ok() => {
+                return Err(format!(
+                    "Unexpected argument '{}', use --max-cycles to stop after a number of cycles",
+                    arg
+                ));
+            }
+            _ => playlist.push(arg),
+        }
+    }
+
+    let rom_path = playlist
+        .first()
+        .cloned()
+        .ok_or("Please provide the path to a chip8 rom.")?;
+    if playlist.len() > 1 && playlist.iter().any(|path| path == "-") {
+        return Err("A rom read from stdin can't be part of a playlist".to_string());
+    }
+    if monitor && rom_path == "-" {
+        return Err(
+            "--monitor reads commands from stdin, the rom can't be read from it".to_string(),
+        );
+    }
+
+    // The platform decides the defaults, the config file and explicit flags refine them.
+    // A profile replaces the quirks of the platform.
+    let load_addr = load_addr
+        .or(platform.map(Platform::load_address))
+        .unwrap_or(PROGRAM_START as u16);
+    let ram_size = platform.map_or(RAM_SIZE, Platform::ram_size);
+    // Checked once the platform is known, XO-CHIP has more ram
+    if let Some(&(addr, _)) = pokes.iter().find(|(addr, _)| *addr as usize >= ram_size) {
+        return Err(format!("Address 0x{:x} is outside of ram", addr));
+    }
+    if let Some(addr) = entry_pc.filter(|addr| *addr as usize + 2 > ram_size) {
+        return Err(format!("Address 0x{:x} is outside of ram", addr));
+    }
+    let quirks_on_cli = profile.is_some() || platform.is_some();
+    let quirks = match profile.or(platform.map(Platform::quirks)) {
+        Some(mut quirks) => {
+            config.apply_quirks(&mut quirks);
+            Some(quirks)
+        }
+        None => config.quirks(),
+    };
+
+    Ok(Options {
+        rom_path,
+        playlist,
+        num_cycles,
+        load_addr,
+        ram_size,
+        skip,
+        palette,
+        clock_hz,
+        clock_on_cli,
+        fps,
+        render_skip,
+        scale,
+        phosphor,
+        resolution,
+        crt,
+        crt_intensity,
+        touchpad,
+        show_keys,
+        tone,
+        pitch,
+        volume,
+        mute,
+        config_path,
+        dump_display_on_exit,
+        dump_ram_on_exit,
+        profile_frames,
+        accurate_timing,
+        seed,
+        set_regs,
+        pokes,
+        entry_pc,
+        quirks,
+        quirks_on_cli,
+        font,
+        big_font,
+        disasm,
+        assemble,
+        validate,
+        start_paused,
+        pause_unfocused,
+        trace,
+        warn_low_pc,
+        strict_pc,
+        warn_odd_pc,
+        strict_odd_pc,
+        log_opcodes,
+        breakpoints,
+        watch_regs,
+        protect,
+        monitor,
+        input_script,
+        record_input,
+        keymap,
+        padmap,
+    })
+}
+
+fn check_scale(scale: usize) -> Result<usize, String> {
+    match scale {
+        1..=MAX_SCALE => Ok(scale),
+        _ => Err(format!("Invalid scale '{}'", scale)),
+    }
+}
+
+/// Between silent (0) and full volume (1).
+fn check_volume(volume: f32) -> Result<f32, String> {
+    if (0.0..=1.0).contains(&volume) {
+        Ok(volume)
+    } else {
+        Err(format!("Invalid volume '{}', expected 0 to 1", volume))
+    }
+}
+
+/// Audible and below the Nyquist frequency of the output.
+fn check_pitch(hz: f32) -> Result<f32, String> {
+    if (20.0..=20_000.0).contains(&hz) {
+        Ok(hz)
+    } else {
+        Err(format!("Invalid pitch '{}'", hz))
+    }
+}
+
+fn check_clock(hz: u32) -> Result<u32, String> {
+    match hz {
+        1..=MAX_CLOCK_HZ => Ok(hz),
+        _ => Err(format!("Invalid clock speed '{}'", hz)),
+    }
+}
+
+/// The roms in `dir`, by file name.
+fn list_roms(dir: &str) -> Result<Vec<String>, String> {
+    let entries = std::fs::read_dir(dir)
+        .map_err(|err| format!("Could not read playlist {}: {}", dir, err))?;
+    let mut roms: Vec<String> = entries
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            // game.ch8.gz counts as a ch8 rom
+            let rom_path = match path.extension() {
+                Some(ext) if ext == "gz" => path.with_extension(""),
+                _ => path.clone(),
+            };
+            path.is_file()
+                && rom_path
+                    .extension()
+                    .is_some_and(|ext| ROM_EXTENSIONS.iter().any(|rom_ext| ext == *rom_ext))
+        })
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect();
+    if roms.is_empty() {
+        return Err(format!("No roms in playlist {}", dir));
+    }
+    roms.sort();
+    Ok(roms)
+}
+
+/// Parse a hexadecimal address, with or without a `0x` prefix.
+pub fn parse_address(text: &str) -> Result<u16, String> {
+    let digits = text.strip_prefix("0x").unwrap_or(text);
+    u16::from_str_radix(digits, 16).map_err(|_| format!("Invalid address '{}'", text))
+}
+
+/// Parse a `WIDTHxHEIGHT` display size.
+fn parse_resolution(text: &str) -> Result<(usize, usize), String> {
+    let invalid = || {
+        format!(
+            "Invalid resolution '{}', expected WIDTHxHEIGHT up to {}x{}",
+            text, MAX_DISPLAY_SIZE, MAX_DISPLAY_SIZE
+        )
+    };
+    let (width, height) = text.split_once('x').ok_or_else(invalid)?;
+    let size = |text: &str| {
+        text.parse()
+            .ok()
+            .filter(|size| (1..=MAX_DISPLAY_SIZE).contains(size))
+            .ok_or_else(invalid)
+    };
+    Ok((size(width)?, size(height)?))
+}
+
+/// Parse a hexadecimal byte, with or without a `0x` prefix.
+fn parse_byte(text: &str) -> Result<u8, String> {
+    let digits = text.strip_prefix("0x").unwrap_or(text);
+    u8::from_str_radix(digits, 16).map_err(|_| format!("Invalid byte '{}'", text))
+}
+
+/// Split a `NAME=VALUE` pair.
+fn split_assignment(text: &str) -> Result<(&str, &str), String> {
+    text.split_once('=')
+        .map(|(name, value)| (name.trim(), value.trim()))
+        .ok_or_else(|| format!("Invalid assignment '{}', expected NAME=VALUE", text))
+}
+
+/// Parse a `START:END` range of addresses, END excluded.
+fn parse_range(text: &str) -> Result<Range<usize>, String> {
+    let (start, end) = text
+        .split_once(':')
+        .ok_or_else(|| format!("Invalid range '{}', expected START:END", text))?;
+    let range = parse_address(start)? as usize..parse_address(end)? as usize;
+    if range.is_empty() {
+        return Err(format!("Empty range '{}'", text));
+    }
+    Ok(range)
+}
+
+/// Parse a register name from V0 to VF.
+pub fn parse_register(text: &str) -> Result<usize, String> {
+    text.strip_prefix(['V', 'v'])
+        .filter(|digit| digit.len() == 1)
+        .and_then(|digit| usize::from_str_radix(digit, 16).ok())
+        .ok_or_else(|| format!("Invalid register '{}'", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8_core::lookup_rom;
+    use sdl3::pixels::Color;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        parse_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let options = parse(&[
+            "--fg",
+            "ffcc00",
+            "rom.ch8",
+            "--bg",
+            "000033",
+            "--max-cycles",
+            "100",
+        ])
+        .unwrap();
+        assert_eq!(options.rom_path, "rom.ch8");
+        assert_eq!(options.num_cycles, 100);
+        assert_eq!(options.palette.colors[1], Color::RGB(255, 204, 0));
+        assert_eq!(options.palette.bg(), Color::RGB(0, 0, 51));
+
+        let options = parse(&["--xo-palette", "000000,ffffff,ff0000,0000ff", "rom.ch8"]).unwrap();
+        assert_eq!(options.palette.bg(), Color::RGB(0, 0, 0));
+        assert_eq!(options.palette.color(0b11), Color::RGB(0, 0, 255));
+        assert!(parse(&["--xo-palette", "000000,ffffff", "rom.ch8"]).is_err());
+
+        assert!(parse(&["rom.ch8", "--fg", "nope"]).is_err());
+        assert!(parse(&["rom.ch8", "--fg"]).is_err());
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["rom.ch8", "100"]).is_err());
+        assert!(parse(&["rom.ch8", "--max-cycles", "lots"]).is_err());
+        assert!(parse(&["--monitor", "-"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_playlist() {
+        let options = parse(&["a.ch8", "--clock", "1000", "b.ch8"]).unwrap();
+        assert_eq!(options.rom_path, "a.ch8");
+        assert_eq!(options.playlist, ["a.ch8", "b.ch8"]);
+        assert!(parse(&["a.ch8", "-"]).is_err());
+
+        let dir = std::env::temp_dir().join("chip8_test_playlist");
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in [
+            "b.ch8",
+            "a.sc8",
+            "a.ch8.rpl",
+            "notes.txt",
+            "c.xo8.gz",
+            "notes.txt.gz",
+        ] {
+            std::fs::write(dir.join(name), []).unwrap();
+        }
+        let options = parse(&["first.ch8", "--playlist", dir.to_str().unwrap()]).unwrap();
+        let names: Vec<&str> = options
+            .playlist
+            .iter()
+            .map(|path| path.rsplit(std::path::MAIN_SEPARATOR).next().unwrap())
+            .collect();
+        assert_eq!(names, ["first.ch8", "a.sc8", "b.ch8", "c.xo8.gz"]);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(parse(&["--playlist", "missing_dir"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_clock() {
+        assert_eq!(parse(&["rom.ch8"]).unwrap().cycle_time_us(), 1428);
+        assert_eq!(
+            parse(&["--clock", "1000", "rom.ch8"])
+                .unwrap()
+                .cycle_time_us(),
+            1000
+        );
+        assert_eq!(
+            parse(&["--cycles-per-second", "500", "rom.ch8"])
+                .unwrap()
+                .cycle_time_us(),
+            2000
+        );
+        assert!(parse(&["--clock", "0", "rom.ch8"]).is_err());
+        assert!(parse(&["--clock", "fast", "rom.ch8"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_fps() {
+        let options = parse(&["rom.ch8"]).unwrap();
+        assert_eq!(options.frame_time_us(), 16806);
+        assert!(options.render_skip);
+        let options = parse(&["--fps", "30", "--no-render-skip", "rom.ch8"]).unwrap();
+        assert_eq!(options.frame_time_us(), 33333);
+        assert!(!options.render_skip);
+        assert!(parse(&["--fps", "0", "rom.ch8"]).is_err());
+        assert!(parse(&["--fps", "fast", "rom.ch8"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_profile_frames() {
+        assert!(!parse(&["rom.ch8"]).unwrap().profile_frames);
+        assert!(
+            parse(&["--profile-frames", "rom.ch8"])
+                .unwrap()
+                .profile_frames
+        );
+    }
+
+    #[test]
+    fn test_parse_dump_ram() {
+        assert_eq!(parse(&["rom.ch8"]).unwrap().dump_ram_on_exit, None);
+        let options = parse(&["--dump-ram-on-exit", "ram.bin", "rom.ch8"]).unwrap();
+        assert_eq!(options.dump_ram_on_exit.as_deref(), Some("ram.bin"));
+        assert!(parse(&["rom.ch8", "--dump-ram-on-exit"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_assemble() {
+        assert_eq!(parse(&["rom.ch8"]).unwrap().assemble, None);
+        let options = parse(&["--assemble", "game.ch8", "game.asm"]).unwrap();
+        assert_eq!(options.assemble.as_deref(), Some("game.ch8"));
+        assert_eq!(options.rom_path, "game.asm");
+        assert!(parse(&["game.asm", "--assemble"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_tone() {
+        let options = parse(&["--tone", "sine", "--pitch", "523", "rom.ch8"]).unwrap();
+        assert_eq!(options.tone, Waveform::Sine);
+        assert_eq!(options.pitch, 523.0);
+        assert!(parse(&["--tone", "saw", "rom.ch8"]).is_err());
+        assert!(parse(&["--pitch", "5", "rom.ch8"]).is_err());
+        assert!(!options.mute);
+        assert!(parse(&["--mute", "rom.ch8"]).unwrap().mute);
+        assert_eq!(options.volume, DEFAULT_VOLUME);
+        assert_eq!(parse(&["--volume", "0.8", "rom.ch8"]).unwrap().volume, 0.8);
+        assert!(parse(&["--volume", "1.5", "rom.ch8"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_load_addr() {
+        let options = parse(&["rom.ch8"]).unwrap();
+        assert_eq!((options.load_addr, options.skip), (0x200, 0));
+        let options = parse(&["--load-addr", "0x600", "--skip", "16", "rom.ch8"]).unwrap();
+        assert_eq!((options.load_addr, options.skip), (0x600, 16));
+        assert!(parse(&["--skip", "-1", "rom.ch8"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_seed() {
+        assert_eq!(parse(&["rom.ch8"]).unwrap().seed, None);
+        assert_eq!(parse(&["--seed", "42", "rom.ch8"]).unwrap().seed, Some(42));
+        assert!(parse(&["--seed", "x", "rom.ch8"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_resolution() {
+        assert_eq!(parse(&["rom.ch8"]).unwrap().resolution, None);
+        let options = parse(&["--resolution", "64x64", "rom.ch8"]).unwrap();
+        assert_eq!(options.resolution, Some((64, 64)));
+        assert!(parse(&["--resolution", "64", "rom.ch8"]).is_err());
+        assert!(parse(&["--resolution", "0x32", "rom.ch8"]).is_err());
+        assert!(parse(&["--resolution", "256x32", "rom.ch8"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_crt() {
+        let options = parse(&["rom.ch8"]).unwrap();
+        assert!(!options.crt);
+        assert_eq!(options.crt_intensity, crt::DEFAULT_INTENSITY);
+        let options = parse(&["--crt", "--crt-intensity", "0.8", "rom.ch8"]).unwrap();
+        assert!(options.crt);
+        assert_eq!(options.crt_intensity, 0.8);
+        assert!(parse(&["--crt-intensity", "2", "rom.ch8"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_show_keys() {
+        let options = parse(&["rom.ch8"]).unwrap();
+        assert!(!options.show_keys && !options.touchpad);
+        let options = parse(&["--show-keys", "rom.ch8"]).unwrap();
+        assert!(options.show_keys && !options.touchpad);
+    }
+
+    #[test]
+    fn test_parse_initial_state() {
+        let options = parse(&[
+            "--set-reg",
+            "V5=0x20",
+            "--set-reg",
+            "vf=1",
+            "--poke",
+            "0x300=0xab",
+            "--pc",
+            "0x210",
+            "rom.ch8",
+        ])
+        .unwrap();
+        assert_eq!(options.set_regs, [(5, 0x20), (15, 1)]);
+        assert_eq!(options.pokes, [(0x300, 0xab)]);
+        assert_eq!(options.entry_pc, Some(0x210));
+
+        assert!(parse(&["--set-reg", "V5", "rom.ch8"]).is_err());
+        assert!(parse(&["--set-reg", "V16=1", "rom.ch8"]).is_err());
+        assert!(parse(&["--set-reg", "V5=0x100", "rom.ch8"]).is_err());
+        assert!(parse(&["--poke", "0x1000=1", "rom.ch8"]).is_err());
+        assert!(parse(&["--pc", "0xfff", "rom.ch8"]).is_err());
+        let options = parse(&["--poke", "0xf000=1", "--platform", "xochip", "rom.ch8"]).unwrap();
+        assert_eq!(options.pokes, [(0xf000, 1)]);
+    }
+
+    #[test]
+    fn test_parse_platform() {
+        let options = parse(&["--platform", "eti660", "rom.ch8"]).unwrap();
+        assert_eq!(options.load_addr, 0x600);
+        assert_eq!(options.quirks, Some(Quirks::COSMAC));
+        let options = parse(&["--platform", "eti660", "--load-addr", "0x200", "rom.ch8"]).unwrap();
+        assert_eq!(options.load_addr, 0x200);
+        assert_eq!(options.ram_size, RAM_SIZE);
+        let options = parse(&["--platform", "xochip", "rom.ch8"]).unwrap();
+        assert_eq!(options.ram_size, 0x10000);
+        assert!(parse(&["--platform", "vip", "rom.ch8"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_profile() {
+        let options = parse(&["--profile", "octo", "rom.ch8"]).unwrap();
+        assert_eq!(options.quirks, Some(Quirks::OCTO));
+        assert_eq!(options.load_addr, 0x200);
+        let options = parse(&["--platform", "eti660", "--profile", "schip", "rom.ch8"]).unwrap();
+        assert_eq!(options.quirks, Some(Quirks::SCHIP));
+        assert_eq!(options.load_addr, 0x600);
+        assert!(parse(&["--profile", "chip48", "rom.ch8"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_scale() {
+        assert_eq!(parse(&["rom.ch8"]).unwrap().scale, DEFAULT_SCALE);
+        assert_eq!(parse(&["--scale", "4", "rom.ch8"]).unwrap().scale, 4);
+        assert!(parse(&["--scale", "0", "rom.ch8"]).is_err());
+        assert!(parse(&["--scale", "big", "rom.ch8"]).is_err());
+    }
+
+    #[test]
+    fn test_config_file() {
+        let path = std::env::temp_dir().join("chip8_test_config.toml");
+        std::fs::write(&path, "clock = 500\n[palette]\nfg = \"ffcc00\"\n").unwrap();
+        let path = path.to_str().unwrap();
+
+        let options = parse(&["--config", path, "rom.ch8"]).unwrap();
+        assert_eq!(options.clock_hz, Some(500));
+        assert_eq!(options.palette.colors[1], Color::RGB(255, 204, 0));
+        // Flags win over the file
+        let options = parse(&["--config", path, "--clock", "1000", "rom.ch8"]).unwrap();
+        assert_eq!(options.clock_hz, Some(1000));
+        assert_eq!(options.config_path.as_deref(), Some(path));
+
+        assert!(parse(&["--config", "missing.toml", "rom.ch8"]).is_err());
+    }
+
+    #[test]
+    fn test_apply_rom_profile() {
+        let path = std::env::temp_dir().join("chip8_test_config_profile.toml");
+        std::fs::write(&path, "clock = 500\n[quirks]\nvf_reset = true\n").unwrap();
+        let path = path.to_str().unwrap();
+        let profile = RomProfile {
+            title: "Some Game".to_string(),
+            quirks: Quirks::SCHIP,
+            clock_hz: 1000,
+        };
+
+        // The database wins over the config file
+        let mut options = parse(&["--config", path, "rom.ch8"]).unwrap();
+        options.apply_rom_profile(&profile);
+        assert_eq!(options.clock_hz, Some(1000));
+        assert_eq!(options.quirks, Some(Quirks::SCHIP));
+
+        // Flags win over the database
+        let mut options = parse(&[
+            "--config",
+            path,
+            "--clock",
+            "800",
+            "--profile",
+            "cosmac",
+            "rom.ch8",
+        ])
+        .unwrap();
+        options.apply_rom_profile(&profile);
+        assert_eq!(options.clock_hz, Some(800));
+        assert_eq!(options.quirks, Some(Quirks::COSMAC));
+        let mut options = parse(&["--platform", "xochip", "rom.ch8"]).unwrap();
+        options.apply_rom_profile(&profile);
+        assert_eq!(options.quirks, Some(Quirks::XOCHIP));
+        std::fs::remove_file(path).unwrap();
+
+        // A SUPER-CHIP rom from the built-in database
+        let rom = include_bytes!("../tests/roms/sctest.ch8");
+        let profile = lookup_rom(rom).unwrap();
+        let mut options = parse(&["rom.ch8"]).unwrap();
+        options.apply_rom_profile(&profile);
+        assert_eq!(options.quirks, Some(Quirks::SCHIP));
+        assert_eq!(options.clock_hz, Some(1200));
+    }
+
+    #[test]
+    fn test_parse_low_pc() {
+        let options = parse(&["rom.ch8"]).unwrap();
+        assert!(!options.warn_low_pc && !options.strict_pc);
+        let options = parse(&["--warn-low-pc", "--strict-pc", "rom.ch8"]).unwrap();
+        assert!(options.warn_low_pc && options.strict_pc);
+        assert_eq!(options.low_pc_limit(), 0x200);
+        let options = parse(&["--load-addr", "0x100", "rom.ch8"]).unwrap();
+        assert_eq!(options.low_pc_limit(), 0x100);
+    }
+
+    #[test]
+    fn test_parse_odd_pc() {
+        let options = parse(&["rom.ch8"]).unwrap();
+        assert!(!options.warn_odd_pc && !options.strict_odd_pc);
+        let options = parse(&["--warn-odd-pc", "--strict-odd-pc", "rom.ch8"]).unwrap();
+        assert!(options.warn_odd_pc && options.strict_odd_pc);
+    }
+
+    #[test]
+    fn test_parse_breakpoints() {
+        let options = parse(&[
+            "--break",
+            "0x300",
+            "--break",
+            "2a4",
+            "--watch-reg",
+            "V5",
+            "--watch-reg",
+            "vf",
+            "rom.ch8",
+        ])
+        .unwrap();
+        assert_eq!(options.breakpoints, HashSet::from([0x300, 0x2a4]));
+        assert_eq!(options.watch_regs, [0x5, 0xf]);
+
+        assert!(parse(&["--break", "0xzz", "rom.ch8"]).is_err());
+        assert!(parse(&["--watch-reg", "V10", "rom.ch8"]).is_err());
+        assert!(parse(&["--watch-reg", "I", "rom.ch8"]).is_err());
+    }
+
+    #[test]
+    fn test_parse_protect() {
+        assert_eq!(parse(&["rom.ch8"]).unwrap().protect, None);
+        let options = parse(&["--protect", "0x200:0x400", "rom.ch8"]).unwrap();
+        assert_eq!(options.protect, Some(0x200..0x400));
+        assert!(parse(&["--protect", "0x200", "rom.ch8"]).is_err());
+        assert!(parse(&["--protect", "0x400:0x200", "rom.ch8"]).is_err());
+    }
+}
@@ -0,0 +1,109 @@
+use crate::quirks::Quirks;
+use sha1::{Digest, Sha1};
+
+/// Built-in compatibility database, see the header of the file for its format.
+const DATABASE: &str = include_str!("compat.txt");
+
+/// Interpreter settings a known rom needs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomProfile {
+    pub title: String,
+    pub quirks: Quirks,
+    pub clock_hz: u32,
+}
+
+/// Hex encoded SHA-1 of the rom, as used by the community database.
+pub fn rom_sha1(rom: &[u8]) -> String {
+    Sha1::digest(rom)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+/// Settings for `rom` if it is in the built-in database.
+pub fn lookup_rom(rom: &[u8]) -> Option<RomProfile> {
+    lookup_in(DATABASE, &rom_sha1(rom))
+}
+
+/// Quirks of a preset named in the database, None for an unknown name.
+fn preset_quirks(name: &str) -> Option<Quirks> {
+    match name {
+        "chip8" => Some(Quirks::default()),
+        "cosmac" => Some(Quirks::COSMAC),
+        "schip" => Some(Quirks::SCHIP),
+        _ => None,
+    }
+}
+
+/// The sha1 and profile of a database line, None if it is malformed or has no clock.
+fn parse_line(line: &str) -> Option<(&str, RomProfile)> {
+    let mut fields = line.splitn(4, ' ');
+    let sha1 = fields.next()?;
+    let quirks = preset_quirks(fields.next()?)?;
+    let clock_hz = fields.next()?.parse().ok().filter(|hz| *hz > 0)?;
+    let title = fields.next().unwrap_or_default().trim().to_string();
+    Some((
+        sha1,
+        RomProfile {
+            title,
+            quirks,
+            clock_hz,
+        },
+    ))
+}
+
+/// Lines that are neither blank nor comments.
+fn entries(database: &str) -> impl Iterator<Item = &str> {
+    database
+        .lines()
+        .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+}
+
+/// Malformed lines, such as those with an unknown preset, are skipped.
+fn lookup_in(database: &str, sha1: &str) -> Option<RomProfile> {
+    entries(database)
+        .filter_map(parse_line)
+        .find_map(|(line_sha1, profile)| (line_sha1 == sha1).then_some(profile))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup() {
+        let rom = [0x12, 0x00];
+        let sha1 = rom_sha1(&rom);
+        assert_eq!(sha1.len(), 40);
+        assert_eq!(rom_sha1(b"abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+
+        let database = format!("# comment\n\n{} schip 1000 Some Game\n", sha1);
+        let profile = lookup_in(&database, &sha1).unwrap();
+        assert_eq!(profile.title, "Some Game");
+        assert_eq!(profile.quirks, Quirks::SCHIP);
+        assert_eq!(profile.clock_hz, 1000);
+        assert_eq!(lookup_in(&database, &rom_sha1(b"abc")), None);
+
+        // Unknown presets don't fall back to any quirks
+        let database = format!("{} schip1.1 1000 Some Game\n", sha1);
+        assert_eq!(lookup_in(&database, &sha1), None);
+        let database = format!("{} schip 0 Some Game\n", sha1);
+        assert_eq!(lookup_in(&database, &sha1), None);
+        let database = format!("{} chip8 700 Some Game\n", sha1);
+        assert_eq!(
+            lookup_in(&database, &sha1).unwrap().quirks,
+            Quirks::default()
+        );
+    }
+
+    #[test]
+    fn test_database() {
+        // Every entry of the built-in database is well formed
+        for line in entries(DATABASE) {
+            let (sha1, _) = parse_line(line).unwrap_or_else(|| panic!("Invalid entry {}", line));
+            assert_eq!(sha1.len(), 40);
+        }
+        let rom = include_bytes!("../tests/roms/ibm_logo.ch8");
+        assert_eq!(lookup_rom(rom).unwrap().title, "IBM Logo");
+    }
+}
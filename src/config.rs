@@ -0,0 +1,331 @@
+use crate::gamepad::PadMap;
+use crate::keymap::KeyMap;
+use crate::palette::{Palette, parse_hex_color};
+use chip8_core::{IndexIncrement, Quirks};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+
+/// Config file looked up in the working directory when `--config` isn't given.
+pub const DEFAULT_CONFIG_PATH: &str = "chip8.toml";
+
+/// Settings read from a toml file. Anything left out keeps its default value.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Number of chip8 clock cycles per second.
+    pub clock: Option<u32>,
+    /// Window size as a multiple of the low resolution display.
+    pub scale: Option<usize>,
+    /// Beep waveform: square, sine, triangle or noise.
+    pub tone: Option<String>,
+    /// Beep frequency in Hz.
+    pub pitch: Option<f32>,
+    /// Master volume from 0 to 1, saved when changed while running.
+    pub volume: Option<f32>,
+    /// Bytes of the 4x5 font of 0xfx29 in place of the usual one, 5 per hexadecimal digit.
+    pub font: Option<Vec<u8>>,
+    /// Bytes of the 8x10 big font of 0xfx30, 10 per decimal digit.
+    pub big_font: Option<Vec<u8>>,
+    pub palette: PaletteConfig,
+    pub quirks: QuirksConfig,
+    /// Chip8 key (0 to F) to SDL scancode name.
+    pub keymap: BTreeMap<String, String>,
+    /// Chip8 key (0 to F) to SDL gamepad button name.
+    pub padmap: BTreeMap<String, String>,
+}
+
+/// Colors as `RRGGBB` hex strings.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PaletteConfig {
+    pub fg: Option<String>,
+    pub bg: Option<String>,
+    pub grid: Option<String>,
+    pub plane2: Option<String>,
+    pub both_planes: Option<String>,
+}
+
+/// Individual quirk flags, see [`Quirks`].
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuirksConfig {
+    pub vf_reset: Option<bool>,
+    pub memory_increment: Option<IncrementConfig>,
+    pub shift_vx_in_place: Option<bool>,
+    pub jump_with_vx: Option<bool>,
+    pub display_wait: Option<bool>,
+    pub clip_sprites: Option<bool>,
+    pub index_overflow_vf: Option<bool>,
+    pub key_wait_on_press: Option<bool>,
+    pub wrap_memory: Option<bool>,
+    pub count_collided_rows: Option<bool>,
+    pub clear_on_resolution_change: Option<bool>,
+}
+
+/// `memory_increment` is either one of the [`IndexIncrement`] names, or a flag choosing between
+/// x+1 and none.
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(untagged)]
+pub enum IncrementConfig {
+    Flag(bool),
+    Amount(IncrementName),
+}
+
+/// An [`IndexIncrement`] read by its name, see [`IndexIncrement::from_name`].
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(try_from = "String")]
+pub struct IncrementName(IndexIncrement);
+
+impl TryFrom<String> for IncrementName {
+    type Error = String;
+
+    fn try_from(name: String) -> Result<Self, String> {
+        IndexIncrement::from_name(&name).map(IncrementName)
+    }
+}
+
+impl IncrementConfig {
+    fn increment(self) -> IndexIncrement {
+        match self {
+            IncrementConfig::Flag(true) => IndexIncrement::XPlusOne,
+            IncrementConfig::Flag(false) => IndexIncrement::Unchanged,
+            IncrementConfig::Amount(IncrementName(increment)) => increment,
+        }
+    }
+}
+
+impl Config {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        toml::from_str(text).map_err(|err| format!("Invalid config: {}", err))
+    }
+
+    /// Read the config file at `path`. A missing file gives the defaults unless `required`.
+    pub fn load(path: &str, required: bool) -> Result<Self, String> {
+        match std::fs::read_to_string(path) {
+            Ok(text) => Self::parse(&text).map_err(|err| format!("{}: {}", path, err)),
+            Err(err) if err.kind() == ErrorKind::NotFound && !required => Ok(Config::default()),
+            Err(err) => Err(format!("Could not read config {}: {}", path, err)),
+        }
+    }
+
+    /// Set the top level `volume` entry of the file at `path`, keeping the rest of it as is.
+    pub fn save_volume(path: &str, volume: f32) -> Result<(), String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| format!("Could not read config {}: {}", path, err))?;
+        let entry = format!("volume = {}", volume);
+        let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+        // Top level entries come before the first table
+        let top_level = lines
+            .iter()
+            .position(|line| line.trim_start().starts_with('['))
+            .unwrap_or(lines.len());
+        let existing = lines[..top_level].iter().position(|line| {
+            line.split_once('=')
+                .is_some_and(|(key, _)| key.trim() == "volume")
+        });
+        match existing {
+            Some(idx) => lines[idx] = entry,
+            None => lines.insert(0, entry),
+        }
+        let mut text = lines.join("\n");
+        text.push('\n');
+        std::fs::write(path, text).map_err(|err| format!("Could not save config {}: {}", path, err))
+    }
+
+    pub fn apply_palette(&self, palette: &mut Palette) -> Result<(), String> {
+        let [bg, fg, plane2, both_planes] = &mut palette.colors;
+        let colors = [
+            (&self.palette.bg, bg),
+            (&self.palette.fg, fg),
+            (&self.palette.plane2, plane2),
+            (&self.palette.both_planes, both_planes),
+            (&self.palette.grid, &mut palette.grid),
+        ];
+        for (hex, color) in colors {
+            if let Some(hex) = hex {
+                *color = parse_hex_color(hex)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The default quirks with the configured flags applied, if the file sets any.
+    pub fn quirks(&self) -> Option<Quirks> {
+        let mut quirks = Quirks::default();
+        self.apply_quirks(&mut quirks).then_some(quirks)
+    }
+
+    /// Returns whether any flag was set.
+    pub fn apply_quirks(&self, quirks: &mut Quirks) -> bool {
+        let flags = [
+            (self.quirks.vf_reset, &mut quirks.vf_reset),
+            (self.quirks.shift_vx_in_place, &mut quirks.shift_vx_in_place),
+            (self.quirks.jump_with_vx, &mut quirks.jump_with_vx),
+            (self.quirks.display_wait, &mut quirks.display_wait),
+            (self.quirks.clip_sprites, &mut quirks.clip_sprites),
+            (self.quirks.index_overflow_vf, &mut quirks.index_overflow_vf),
+            (self.quirks.key_wait_on_press, &mut quirks.key_wait_on_press),
+            (self.quirks.wrap_memory, &mut quirks.wrap_memory),
+            (
+                self.quirks.count_collided_rows,
+                &mut quirks.count_collided_rows,
+            ),
+            (
+                self.quirks.clear_on_resolution_change,
+                &mut quirks.clear_on_resolution_change,
+            ),
+        ];
+        let mut any = false;
+        if let Some(increment) = self.quirks.memory_increment {
+            quirks.memory_increment = increment.increment();
+            any = true;
+        }
+        for (value, flag) in flags {
+            if let Some(value) = value {
+                *flag = value;
+                any = true;
+            }
+        }
+        any
+    }
+
+    /// The custom `font`, checked to hold every digit.
+    pub fn font(&self) -> Result<Option<[u8; 80]>, String> {
+        font_bytes(&self.font, "font")
+    }
+
+    /// The custom `big_font`, checked to hold every digit.
+    pub fn big_font(&self) -> Result<Option<[u8; 100]>, String> {
+        font_bytes(&self.big_font, "big_font")
+    }
+
+    pub fn apply_keymap(&self, keymap: &mut KeyMap) -> Result<(), String> {
+        for (key, name) in &self.keymap {
+            keymap.apply(&format!("{}={}", key, name))?;
+        }
+        Ok(())
+    }
+
+    pub fn apply_padmap(&self, padmap: &mut PadMap) -> Result<(), String> {
+        for (key, name) in &self.padmap {
+            padmap.apply(&format!("{}={}", key, name))?;
+        }
+        Ok(())
+    }
+}
+
+fn font_bytes<const N: usize>(
+    bytes: &Option<Vec<u8>>,
+    name: &str,
+) -> Result<Option<[u8; N]>, String> {
+    bytes
+        .as_deref()
+        .map(|bytes| {
+            bytes
+                .try_into()
+                .map_err(|_| format!("{} must be {} bytes, not {}", name, N, bytes.len()))
+        })
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sdl3::pixels::Color;
+
+    #[test]
+    fn test_parse_config() {
+        let config = Config::parse(
+            r#"
+            clock = 1000
+            scale = 8
+            tone = "sine"
+            pitch = 330
+            volume = 0.5
+
+            [palette]
+            fg = "ffcc00"
+
+            [quirks]
+            vf_reset = true
+            clip_sprites = false
+            memory_increment = "x"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.clock, Some(1000));
+        assert_eq!(config.scale, Some(8));
+        assert_eq!(config.tone.as_deref(), Some("sine"));
+        assert_eq!(config.pitch, Some(330.0));
+        assert_eq!(config.volume, Some(0.5));
+
+        let mut palette = Palette::default();
+        config.apply_palette(&mut palette).unwrap();
+        assert_eq!(palette.colors[1], Color::RGB(255, 204, 0));
+        assert_eq!(palette.bg(), Palette::default().bg());
+
+        let quirks = config.quirks().unwrap();
+        assert!(quirks.vf_reset);
+        assert!(!quirks.clip_sprites);
+        assert_eq!(quirks.memory_increment, IndexIncrement::X);
+        assert_eq!(quirks.display_wait, Quirks::default().display_wait);
+
+        assert_eq!(config.font(), Ok(None));
+        assert_eq!(Config::default().quirks(), None);
+        assert!(Config::parse("clock = \"fast\"").is_err());
+        assert!(Config::parse("colour = 1").is_err());
+        assert!(Config::parse("[quirks]\nmemory_increment = \"x+2\"").is_err());
+        let config = Config::parse("[quirks]\nmemory_increment = \"none\"").unwrap();
+        assert_eq!(
+            config.quirks().unwrap().memory_increment,
+            IndexIncrement::Unchanged
+        );
+        let config = Config::parse("[quirks]\nmemory_increment = true").unwrap();
+        assert_eq!(
+            config.quirks().unwrap().memory_increment,
+            IndexIncrement::XPlusOne
+        );
+    }
+
+    #[test]
+    fn test_fonts() {
+        let font: Vec<String> = (0..80).map(|byte| byte.to_string()).collect();
+        let config = Config::parse(&format!(
+            "font = [{}]\nbig_font = [{}]",
+            font.join(", "),
+            ["0xff"; 100].join(", ")
+        ))
+        .unwrap();
+        assert_eq!(config.font().unwrap().unwrap()[79], 79);
+        assert_eq!(config.big_font(), Ok(Some([0xff; 100])));
+
+        let config = Config::parse("big_font = [1, 2, 3]").unwrap();
+        assert_eq!(
+            config.big_font(),
+            Err("big_font must be 100 bytes, not 3".to_string())
+        );
+        assert!(Config::parse("font = [256]").is_err());
+    }
+
+    #[test]
+    fn test_save_volume() {
+        let path = std::env::temp_dir().join("chip8_test_volume.toml");
+        let path = path.to_str().unwrap();
+        std::fs::write(
+            path,
+            "# settings\nclock = 500\n\n[palette]\nfg = \"ffcc00\"\n",
+        )
+        .unwrap();
+        Config::save_volume(path, 0.5).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(path).unwrap(),
+            "volume = 0.5\n# settings\nclock = 500\n\n[palette]\nfg = \"ffcc00\"\n"
+        );
+        Config::save_volume(path, 0.25).unwrap();
+        let config = Config::load(path, true).unwrap();
+        assert_eq!(config.volume, Some(0.25));
+        assert_eq!(config.clock, Some(500));
+        std::fs::remove_file(path).unwrap();
+    }
+}
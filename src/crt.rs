@@ -0,0 +1,68 @@
+use crate::palette::blend;
+use sdl3::pixels::Color;
+use sdl3::render::{BlendMode, FRect, WindowCanvas};
+
+/// Default strength of the effect, from 0 (none) to 1.
+pub const DEFAULT_INTENSITY: f32 = 0.5;
+
+/// How far the halo extends around a lit pixel, as a fraction of the pixel size.
+const HALO_SIZE: f32 = 0.2;
+
+/// Brightness of the halo at full intensity, relative to the pixel.
+const HALO_BRIGHTNESS: f32 = 0.4;
+
+/// Darkness of the scanlines at full intensity.
+const SCANLINE_DARKNESS: f32 = 0.6;
+
+/// Rects of the halos around lit pixels, to draw under the pixels themselves.
+pub fn halo_rects(pixels: &[FRect], pixel_size: f32) -> Vec<FRect> {
+    let spread = pixel_size * HALO_SIZE;
+    pixels
+        .iter()
+        .map(|pixel| {
+            FRect::new(
+                pixel.x - spread,
+                pixel.y - spread,
+                pixel.w + 2.0 * spread,
+                pixel.h + 2.0 * spread,
+            )
+        })
+        .collect()
+}
+
+/// Color of the halo around pixels of `color` on a `bg` background.
+pub fn halo_color(bg: Color, color: Color, intensity: f32) -> Color {
+    blend(bg, color, intensity * HALO_BRIGHTNESS)
+}
+
+/// Darken every other line of output pixels within `area`.
+pub fn draw_scanlines(canvas: &mut WindowCanvas, area: FRect, intensity: f32) {
+    let lines: Vec<FRect> = (0..area.h as u32)
+        .step_by(2)
+        .map(|row| FRect::new(area.x, area.y + row as f32 + 1.0, area.w, 1.0))
+        .collect();
+    canvas.set_blend_mode(BlendMode::Blend);
+    canvas.set_draw_color(Color::RGBA(
+        0,
+        0,
+        0,
+        (intensity * SCANLINE_DARKNESS * 255.0) as u8,
+    ));
+    canvas.fill_rects(&lines).unwrap();
+    canvas.set_blend_mode(BlendMode::None);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_halo() {
+        let halos = halo_rects(&[FRect::new(10.0, 20.0, 10.0, 10.0)], 10.0);
+        assert_eq!(halos, [FRect::new(8.0, 18.0, 14.0, 14.0)]);
+        assert_eq!(
+            halo_color(Color::RGB(0, 0, 0), Color::RGB(200, 100, 0), 0.5),
+            Color::RGB(40, 20, 0)
+        );
+    }
+}
@@ -0,0 +1,142 @@
+/// Mnemonic of an instruction, or None if the interpreter does not implement it.
+pub fn mnemonic(opcode: u16) -> Option<String> {
+    let x = (opcode & 0x0f00) >> 8;
+    let y = (opcode & 0x00f0) >> 4;
+    let n = opcode & 0x000f;
+    let nn = opcode & 0x00ff;
+    let nnn = opcode & 0x0fff;
+
+    let text = match ((opcode & 0xf000) >> 12, n) {
+        (0x0, _) if opcode == 0x00e0 => "CLS".to_string(),
+        (0x0, _) if opcode == 0x00ee => "RET".to_string(),
+        (0x0, _) if opcode & 0xfff0 == 0x00c0 => format!("SCD {}", n),
+        (0x0, _) if opcode == 0x00fb => "SCR".to_string(),
+        (0x0, _) if opcode == 0x00fc => "SCL".to_string(),
+        (0x0, _) if opcode == 0x00fd => "EXIT".to_string(),
+        (0x0, _) if opcode == 0x00fe => "LOW".to_string(),
+        (0x0, _) if opcode == 0x00ff => "HIGH".to_string(),
+        (0x0, _) => format!("SYS 0x{:03x}", nnn),
+        (0x1, _) => format!("JP 0x{:03x}", nnn),
+        (0x2, _) => format!("CALL 0x{:03x}", nnn),
+        (0x3, _) => format!("SE V{}, 0x{:02x}", x, nn),
+        (0x4, _) => format!("SNE V{}, 0x{:02x}", x, nn),
+        (0x5, 0x0) => format!("SE V{}, V{}", x, y),
+        (0x6, _) => format!("LD V{}, 0x{:02x}", x, nn),
+        (0x7, _) => format!("ADD V{}, 0x{:02x}", x, nn),
+        (0x8, 0x0) => format!("LD V{}, V{}", x, y),
+        (0x8, 0x1) => format!("OR V{}, V{}", x, y),
+        (0x8, 0x2) => format!("AND V{}, V{}", x, y),
+        (0x8, 0x3) => format!("XOR V{}, V{}", x, y),
+        (0x8, 0x4) => format!("ADD V{}, V{}", x, y),
+        (0x8, 0x5) => format!("SUB V{}, V{}", x, y),
+        (0x8, 0x6) => format!("SHR V{}, V{}", x, y),
+        (0x8, 0x7) => format!("SUBN V{}, V{}", x, y),
+        (0x8, 0xe) => format!("SHL V{}, V{}", x, y),
+        (0x9, 0x0) => format!("SNE V{}, V{}", x, y),
+        (0xa, _) => format!("LD I, 0x{:03x}", nnn),
+        // Jumps to vx + xnn instead with the jump_with_vx quirk
+        (0xb, _) => format!("JP V0, 0x{:03x}", nnn),
+        (0xc, _) => format!("RND V{}, 0x{:02x}", x, nn),
+        (0xd, _) => format!("DRW V{}, V{}, {}", x, y, n),
+        (0xe, _) if nn == 0x9e => format!("SKP V{}", x),
+        (0xe, _) if nn == 0xa1 => format!("SKNP V{}", x),
+        (0xf, _) if nn == 0x01 => format!("PLANE {}", x),
+        (0xf, _) if opcode == 0xf000 => "LD I, LONG".to_string(),
+        (0xf, _) if opcode == 0xf002 => "AUDIO".to_string(),
+        (0xf, _) if nn == 0x3a => format!("PITCH V{}", x),
+        (0xf, _) if nn == 0x07 => format!("LD V{}, DT", x),
+        (0xf, _) if nn == 0x0a => format!("LD V{}, K", x),
+        (0xf, _) if nn == 0x15 => format!("LD DT, V{}", x),
+        (0xf, _) if nn == 0x18 => format!("LD ST, V{}", x),
+        (0xf, _) if nn == 0x1e => format!("ADD I, V{}", x),
+        (0xf, _) if nn == 0x29 => format!("LD F, V{}", x),
+        (0xf, _) if nn == 0x30 => format!("LD HF, V{}", x),
+        (0xf, _) if nn == 0x33 => format!("LD B, V{}", x),
+        (0xf, _) if nn == 0x55 => format!("LD [I], V{}", x),
+        (0xf, _) if nn == 0x65 => format!("LD V{}, [I]", x),
+        (0xf, _) if nn == 0x75 => format!("LD R, V{}", x),
+        (0xf, _) if nn == 0x85 => format!("LD V{}, R", x),
+        _ => return None,
+    };
+    Some(text)
+}
+
+/// Human readable form of an instruction.
+/// Opcodes the interpreter does not implement are shown as data.
+pub fn disassemble(opcode: u16) -> String {
+    mnemonic(opcode).unwrap_or_else(|| format!("DB 0x{:04x}", opcode))
+}
+
+/// Address and opcode of `count` instructions from `pc`, starting earlier when `pc` is too close
+/// to the end of `ram` for all of them to fit.
+pub fn listing(ram: &[u8], pc: u16, count: usize) -> Vec<(u16, u16)> {
+    let count = count.min(ram.len() / 2);
+    let start = (pc as usize).min(ram.len() - count * 2);
+    (start..start + count * 2)
+        .step_by(2)
+        .map(|addr| (addr as u16, u16::from_be_bytes([ram[addr], ram[addr + 1]])))
+        .collect()
+}
+
+/// Addresses of the words of a rom loaded at `load_addr` that are not implemented instructions.
+/// Best-effort: data such as sprites is decoded as instructions too, and reported if it doesn't
+/// look like one.
+pub fn unknown_opcodes(rom: &[u8], load_addr: u16) -> Vec<u16> {
+    rom.chunks_exact(2)
+        .enumerate()
+        .filter(|(_, word)| mnemonic(u16::from_be_bytes([word[0], word[1]])).is_none())
+        .map(|(idx, _)| load_addr + idx as u16 * 2)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disassemble() {
+        assert_eq!(disassemble(0x00e0), "CLS");
+        assert_eq!(disassemble(0x00c4), "SCD 4");
+        assert_eq!(disassemble(0x0123), "SYS 0x123");
+        assert_eq!(disassemble(0x00fd), "EXIT");
+        assert_eq!(disassemble(0x6a02), "LD V10, 0x02");
+        assert_eq!(disassemble(0x8ab4), "ADD V10, V11");
+        assert_eq!(disassemble(0xa2f0), "LD I, 0x2f0");
+        assert_eq!(disassemble(0xd125), "DRW V1, V2, 5");
+        assert_eq!(disassemble(0xf365), "LD V3, [I]");
+        assert_eq!(disassemble(0xf775), "LD R, V7");
+        assert_eq!(disassemble(0xf530), "LD HF, V5");
+        assert_eq!(disassemble(0xf201), "PLANE 2");
+        assert_eq!(disassemble(0xf000), "LD I, LONG");
+        assert_eq!(disassemble(0xf002), "AUDIO");
+        assert_eq!(disassemble(0xf43a), "PITCH V4");
+    }
+
+    #[test]
+    fn test_unknown_opcodes() {
+        assert_eq!(disassemble(0x5121), "DB 0x5121");
+        assert_eq!(disassemble(0xe1ff), "DB 0xe1ff");
+        assert_eq!(mnemonic(0xf0ff), None);
+    }
+
+    #[test]
+    fn test_listing() {
+        let mut ram = [0; 0x1000];
+        ram[0x200..0x204].copy_from_slice(&[0x00, 0xe0, 0x12, 0x00]);
+        assert_eq!(
+            listing(&ram, 0x200, 3),
+            [(0x200, 0x00e0), (0x202, 0x1200), (0x204, 0x0000)]
+        );
+        // Moved back to fit in ram
+        assert_eq!(listing(&ram, 0xffe, 10)[0].0, 0xfec);
+        assert_eq!(listing(&ram, 0xffe, 10)[9].0, 0xffe);
+        assert_eq!(listing(&ram[..4], 0x000, 10).len(), 2);
+    }
+
+    #[test]
+    fn test_scan_unknown_opcodes() {
+        let rom = [0x00, 0xe0, 0x51, 0x21, 0x12, 0x00, 0xf0, 0xff, 0x5f];
+        assert_eq!(unknown_opcodes(&rom, 0x200), [0x202, 0x206]);
+        assert!(unknown_opcodes(&rom[..2], 0x600).is_empty());
+    }
+}
@@ -0,0 +1,389 @@
+/// Low resolution display size.
+pub const DISPLAY_WIDTH: usize = 64;
+pub const DISPLAY_HEIGHT: usize = 32;
+
+/// SUPER-CHIP high resolution display size.
+pub const HIRES_DISPLAY_WIDTH: usize = 128;
+pub const HIRES_DISPLAY_HEIGHT: usize = 64;
+
+/// Largest low resolution width or height, so that high resolution coordinates fit in a byte.
+pub const MAX_DISPLAY_SIZE: usize = 128;
+
+/// Number of XO-CHIP bitplanes.
+pub const NUM_PLANES: usize = 2;
+
+/// Colors of the pixels, for [`Chip8Display::framebuffer`].
+pub trait PixelPalette {
+    /// RGBA color of a pixel from its plane value (0b00 to 0b11).
+    fn rgba(&self, value: u8) -> [u8; 4];
+}
+
+/// Colors indexed by plane value.
+impl PixelPalette for [[u8; 4]; 4] {
+    fn rgba(&self, value: u8) -> [u8; 4] {
+        self[value as usize & 0b11]
+    }
+}
+
+#[derive(Debug)]
+pub struct Chip8Display {
+    /// Row-major pixels of the active resolution, one buffer per bitplane.
+    /// Classic programs only ever draw to the first plane.
+    pub planes: [Vec<bool>; NUM_PLANES],
+    width: usize,
+    height: usize,
+    /// Size of the low resolution mode, the high resolution mode is twice as large.
+    lores_width: usize,
+    lores_height: usize,
+    /// Bitmask of the planes affected by drawing, clearing and scrolling.
+    selected_planes: u8,
+    /// Set whenever pixels change, so that frontends can skip redrawing a static display.
+    dirty: bool,
+}
+
+impl Chip8Display {
+    pub fn new() -> Self {
+        Self::with_size(DISPLAY_WIDTH, DISPLAY_HEIGHT)
+    }
+
+    /// A display whose low resolution mode is `width` by `height`, for experimental variants like
+    /// the 64x64 HiRes CHIP-8. Sizes are clamped between 1 and [`MAX_DISPLAY_SIZE`].
+    pub fn with_size(width: usize, height: usize) -> Self {
+        let width = width.clamp(1, MAX_DISPLAY_SIZE);
+        let height = height.clamp(1, MAX_DISPLAY_SIZE);
+        Chip8Display {
+            planes: [vec![false; width * height], vec![false; width * height]],
+            width,
+            height,
+            lores_width: width,
+            lores_height: height,
+            selected_planes: 0b01,
+            dirty: true,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// True if pixels changed since the last [`Chip8Display::mark_clean`].
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    pub fn mark_clean(&mut self) {
+        self.dirty = false;
+    }
+
+    /// Width and height of the low resolution mode, 64x32 unless created with another size.
+    pub fn lores_size(&self) -> (usize, usize) {
+        (self.lores_width, self.lores_height)
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.width != self.lores_width
+    }
+
+    /// Switch between the low resolution mode and the high resolution one, twice as large:
+    /// 64x32 and 128x64 by default. All planes are cleared.
+    pub fn set_hires(&mut self, hires: bool) {
+        (self.width, self.height) = if hires {
+            (self.lores_width * 2, self.lores_height * 2)
+        } else {
+            (self.lores_width, self.lores_height)
+        };
+        for plane in &mut self.planes {
+            *plane = vec![false; self.width * self.height];
+        }
+        self.dirty = true;
+    }
+
+    /// Like [`Chip8Display::set_hires`], but keep the picture: each pixel becomes 2x2 in high
+    /// resolution, and the top left pixel of each 2x2 block is kept in low resolution.
+    pub fn set_hires_scaled(&mut self, hires: bool) {
+        if hires == self.is_hires() {
+            return;
+        }
+        let old_width = self.width;
+        let old_planes = self.planes.clone();
+        self.set_hires(hires);
+        let width = self.width;
+        for (plane, old) in self.planes.iter_mut().zip(&old_planes) {
+            for (i, pixel) in plane.iter_mut().enumerate() {
+                let (x, y) = (i % width, i / width);
+                *pixel = if hires {
+                    old[x / 2 + y / 2 * old_width]
+                } else {
+                    old[x * 2 + y * 2 * old_width]
+                };
+            }
+        }
+    }
+
+    pub fn selected_planes(&self) -> u8 {
+        self.selected_planes
+    }
+
+    pub fn select_planes(&mut self, mask: u8) {
+        self.selected_planes = mask & 0b11;
+    }
+
+    /// Indices of the selected planes, in drawing order.
+    pub fn selected_plane_indices(&self) -> impl Iterator<Item = usize> + use<> {
+        let mask = self.selected_planes;
+        (0..NUM_PLANES).filter(move |plane| mask & (1 << plane) != 0)
+    }
+
+    /// Plane value (0b00 to 0b11) of the pixel at index `i` of the row-major buffers.
+    pub fn value(&self, i: usize) -> u8 {
+        self.planes[0][i] as u8 | (self.planes[1][i] as u8) << 1
+    }
+
+    /// `(x, y, plane value)` of every pixel of the active resolution, row by row. The plane value
+    /// is 0 or 1 for single plane roms, up to 0b11 on XO-CHIP.
+    pub fn iter_pixels(&self) -> impl Iterator<Item = (usize, usize, u8)> + '_ {
+        (0..self.width * self.height).map(|i| (i % self.width, i / self.width, self.value(i)))
+    }
+
+    /// Row-major RGBA pixels of the active resolution, each in the color of its plane value.
+    pub fn framebuffer(&self, palette: &impl PixelPalette) -> Vec<u8> {
+        self.iter_pixels()
+            .flat_map(|(_, _, value)| palette.rgba(value))
+            .collect()
+    }
+
+    pub fn clear(&mut self) {
+        for plane in self.selected_plane_indices() {
+            self.planes[plane].fill(false);
+        }
+        self.dirty = true;
+    }
+
+    /// Shift the display down by `n` rows, blanking the rows at the top.
+    pub fn scroll_down(&mut self, n: usize) {
+        let n = n.min(self.height);
+        let shift = n * self.width;
+        for plane in self.selected_plane_indices() {
+            let pixels = &mut self.planes[plane];
+            let len = pixels.len();
+            pixels.copy_within(..len - shift, shift);
+            pixels[..shift].fill(false);
+        }
+        self.dirty = true;
+    }
+
+    /// Shift the display right by `n` columns, blanking the columns on the left.
+    pub fn scroll_right(&mut self, n: usize) {
+        let n = n.min(self.width);
+        for plane in self.selected_plane_indices() {
+            for row in self.planes[plane].chunks_mut(self.width) {
+                row.copy_within(..row.len() - n, n);
+                row[..n].fill(false);
+            }
+        }
+        self.dirty = true;
+    }
+
+    /// Shift the display left by `n` columns, blanking the columns on the right.
+    pub fn scroll_left(&mut self, n: usize) {
+        let n = n.min(self.width);
+        for plane in self.selected_plane_indices() {
+            for row in self.planes[plane].chunks_mut(self.width) {
+                row.copy_within(n.., 0);
+                let len = row.len();
+                row[len - n..].fill(false);
+            }
+        }
+        self.dirty = true;
+    }
+
+    /// One line per row of the current resolution, with `#` for lit pixels and `.` for dark ones.
+    pub fn to_ascii(&self) -> String {
+        let mut out = String::with_capacity((self.width + 1) * self.height);
+        for row in 0..self.height {
+            for col in 0..self.width {
+                out.push(if self.value(col + row * self.width) != 0 {
+                    '#'
+                } else {
+                    '.'
+                });
+            }
+            out.push('\n');
+        }
+        out
+    }
+
+    /// Flip a pixel, returning true if it was lit. Pixels outside of the display are left alone.
+    pub fn toggle(&mut self, plane: usize, x: usize, y: usize) -> bool {
+        let Some(pixel) = self.get_mut(plane, x, y) else {
+            return false;
+        };
+        let was_lit = *pixel;
+        *pixel = !was_lit;
+        was_lit
+    }
+
+    /// A pixel of the current resolution, or None outside of it.
+    pub fn get_mut(&mut self, plane: usize, x: usize, y: usize) -> Option<&mut bool> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let pixel = self.planes.get_mut(plane)?.get_mut(x + y * self.width)?;
+        self.dirty = true;
+        Some(pixel)
+    }
+}
+
+impl Default for Chip8Display {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_iter_pixels() {
+        let mut display = Chip8Display::with_size(4, 2);
+        *display.get_mut(0, 1, 0).unwrap() = true;
+        *display.get_mut(0, 3, 1).unwrap() = true;
+        *display.get_mut(1, 3, 1).unwrap() = true;
+        let lit: Vec<_> = display
+            .iter_pixels()
+            .filter(|(_, _, value)| *value != 0)
+            .collect();
+        assert_eq!(lit, [(1, 0, 0b01), (3, 1, 0b11)]);
+        assert_eq!(display.iter_pixels().count(), 8);
+
+        display.set_hires(true);
+        assert_eq!(display.iter_pixels().last(), Some((7, 3, 0)));
+    }
+
+    #[test]
+    fn test_out_of_range_pixel() {
+        let mut display = Chip8Display::new();
+        display.mark_clean();
+        assert!(display.get_mut(0, 64, 0).is_none());
+        assert!(display.get_mut(0, 0, 32).is_none());
+        assert!(display.get_mut(NUM_PLANES, 0, 0).is_none());
+        assert!(!display.toggle(0, 200, 200));
+        assert!(!display.is_dirty());
+
+        display.set_hires(true);
+        assert!(display.get_mut(0, 127, 63).is_some());
+    }
+
+    #[test]
+    fn test_scroll() {
+        let mut display = Chip8Display::new();
+        *display.get_mut(0, 0, 0).unwrap() = true;
+        *display.get_mut(0, 63, 31).unwrap() = true;
+
+        display.scroll_down(2);
+        assert!(display.planes[0][2 * DISPLAY_WIDTH]);
+        assert_eq!(display.planes[0].iter().filter(|p| **p).count(), 1);
+
+        display.scroll_right(4);
+        assert!(display.planes[0][4 + 2 * DISPLAY_WIDTH]);
+
+        display.scroll_left(6);
+        assert_eq!(display.planes[0].iter().filter(|p| **p).count(), 0);
+    }
+
+    #[test]
+    fn test_dirty() {
+        let mut display = Chip8Display::new();
+        assert!(display.is_dirty());
+        display.mark_clean();
+        assert!(!display.toggle(0, 1, 1));
+        assert!(display.is_dirty());
+
+        display.mark_clean();
+        display.scroll_left(4);
+        assert!(display.is_dirty());
+        display.mark_clean();
+        display.clear();
+        assert!(display.is_dirty());
+    }
+
+    #[test]
+    fn test_selected_planes() {
+        let mut display = Chip8Display::new();
+        *display.get_mut(0, 0, 0).unwrap() = true;
+        *display.get_mut(1, 0, 0).unwrap() = true;
+        assert_eq!(display.value(0), 0b11);
+
+        // Only the second plane is cleared
+        display.select_planes(0b10);
+        display.clear();
+        assert_eq!(display.value(0), 0b01);
+    }
+
+    #[test]
+    fn test_custom_size() {
+        let mut display = Chip8Display::with_size(64, 64);
+        assert_eq!((display.width(), display.height()), (64, 64));
+        assert!(!display.is_hires());
+        display.set_hires(true);
+        assert_eq!((display.width(), display.height()), (128, 128));
+        assert!(display.is_hires());
+        assert_eq!(display.planes[0].len(), 128 * 128);
+        display.set_hires(false);
+        assert_eq!(display.lores_size(), (64, 64));
+
+        let display = Chip8Display::with_size(1000, 0);
+        assert_eq!(display.lores_size(), (MAX_DISPLAY_SIZE, 1));
+    }
+
+    #[test]
+    fn test_set_hires_scaled() {
+        let mut display = Chip8Display::new();
+        *display.get_mut(0, 3, 1).unwrap() = true;
+        display.set_hires_scaled(true);
+        let lit: Vec<usize> = (0..display.planes[0].len())
+            .filter(|&i| display.planes[0][i])
+            .collect();
+        assert_eq!(lit, [6 + 2 * 128, 7 + 2 * 128, 6 + 3 * 128, 7 + 3 * 128]);
+        // Already in high resolution, nothing changes
+        display.set_hires_scaled(true);
+        assert!(display.planes[0][6 + 2 * 128]);
+
+        *display.get_mut(0, 0, 0).unwrap() = true;
+        *display.get_mut(0, 9, 0).unwrap() = true;
+        display.set_hires_scaled(false);
+        assert_eq!(display.planes[0].len(), 64 * 32);
+        assert!(display.planes[0][3 + 64]);
+        assert!(display.planes[0][0]);
+        // Only the top left pixel of a block counts
+        assert!(!display.planes[0][4]);
+    }
+
+    #[test]
+    fn test_framebuffer() {
+        let mut display = Chip8Display::new();
+        *display.get_mut(0, 1, 0).unwrap() = true;
+        *display.get_mut(1, 1, 0).unwrap() = true;
+        *display.get_mut(1, 2, 0).unwrap() = true;
+        let colors = [
+            [0, 0, 0, 255],
+            [1, 1, 1, 255],
+            [2, 2, 2, 255],
+            [3, 3, 3, 255],
+        ];
+        let framebuffer = display.framebuffer(&colors);
+        assert_eq!(framebuffer.len(), 64 * 32 * 4);
+        assert_eq!(
+            framebuffer[..12],
+            [0, 0, 0, 255, 3, 3, 3, 255, 2, 2, 2, 255]
+        );
+
+        display.set_hires(true);
+        assert_eq!(display.framebuffer(&colors).len(), 128 * 64 * 4);
+    }
+}
@@ -0,0 +1,56 @@
+use std::fmt;
+
+/// Errors raised by the interpreter while executing a program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chip8Error {
+    /// The instruction does not decode to any known opcode.
+    UnknownOpcode(u16),
+    /// A subroutine call was made with a full stack.
+    StackOverflow,
+    /// A return was made with an empty stack.
+    StackUnderflow,
+    /// The program counter points outside of ram.
+    PcOutOfBounds(u16),
+    /// The program counter is odd, while instructions are aligned on two bytes.
+    MisalignedPc(u16),
+    /// A memory access at this address runs past the end of ram.
+    RamOutOfBounds(u16),
+    /// There are only 16 registers, v0 to vf.
+    InvalidRegister(usize),
+    /// The rom does not fit in ram after the load address.
+    RomTooLarge { size: usize, max: usize },
+    /// Roms can't be loaded over the font or outside of ram.
+    InvalidLoadAddress(u16),
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Chip8Error::UnknownOpcode(instr) => write!(f, "Unknown instruction 0x{:04x}", instr),
+            Chip8Error::StackOverflow => write!(f, "Stack overflow"),
+            Chip8Error::StackUnderflow => write!(f, "Stack underflow"),
+            Chip8Error::RomTooLarge { size, max } => {
+                write!(
+                    f,
+                    "Rom is {} bytes, larger than the maximum {} bytes",
+                    size, max
+                )
+            }
+            Chip8Error::InvalidLoadAddress(addr) => {
+                write!(f, "Invalid load address 0x{:04x}", addr)
+            }
+            Chip8Error::PcOutOfBounds(pc) => {
+                write!(f, "Program counter out of bounds 0x{:04x}", pc)
+            }
+            Chip8Error::MisalignedPc(pc) => {
+                write!(f, "Misaligned program counter 0x{:04x}", pc)
+            }
+            Chip8Error::RamOutOfBounds(addr) => {
+                write!(f, "Memory access out of bounds 0x{:04x}", addr)
+            }
+            Chip8Error::InvalidRegister(reg) => write!(f, "Invalid register v{}", reg),
+        }
+    }
+}
+
+impl std::error::Error for Chip8Error {}
@@ -0,0 +1,83 @@
+use std::fmt::Write;
+use std::time::Duration;
+
+/// Shortest, total and longest time spent in one part of a frame.
+#[derive(Debug, Default, Clone, Copy)]
+struct Phase {
+    min: Duration,
+    total: Duration,
+    max: Duration,
+}
+
+impl Phase {
+    fn add(&mut self, time: Duration, first: bool) {
+        self.min = if first { time } else { self.min.min(time) };
+        self.max = self.max.max(time);
+        self.total += time;
+    }
+}
+
+/// Time spent per rendered frame polling events, running cycles and rendering, printed on exit
+/// by `--profile-frames`.
+#[derive(Debug, Default)]
+pub struct FrameProfile {
+    frames: u32,
+    events: Phase,
+    cycles: Phase,
+    render: Phase,
+}
+
+impl FrameProfile {
+    /// Account for one rendered frame. `events` and `cycles` add up every loop iteration since the
+    /// previous frame.
+    pub fn add_frame(&mut self, events: Duration, cycles: Duration, render: Duration) {
+        let first = self.frames == 0;
+        self.events.add(events, first);
+        self.cycles.add(cycles, first);
+        self.render.add(render, first);
+        self.frames += 1;
+    }
+
+    /// Min, average and max of each phase, in microseconds.
+    pub fn summary(&self) -> String {
+        let mut out = format!("Frame times over {} frames (min/avg/max):\n", self.frames);
+        for (name, phase) in [
+            ("events", &self.events),
+            ("cycles", &self.cycles),
+            ("render", &self.render),
+        ] {
+            let avg = phase.total.checked_div(self.frames).unwrap_or_default();
+            writeln!(
+                out,
+                "  {:<8}{:>8}us {:>8}us {:>8}us",
+                name,
+                phase.min.as_micros(),
+                avg.as_micros(),
+                phase.max.as_micros()
+            )
+            .unwrap();
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        let mut profile = FrameProfile::default();
+        let us = Duration::from_micros;
+        profile.add_frame(us(10), us(4000), us(300));
+        profile.add_frame(us(30), us(2000), us(900));
+        assert_eq!(
+            profile.summary(),
+            "Frame times over 2 frames (min/avg/max):\n  \
+             events        10us       20us       30us\n  \
+             cycles      2000us     3000us     4000us\n  \
+             render       300us      600us      900us\n"
+        );
+        assert!(FrameProfile::default().summary().contains("0 frames"));
+    }
+}
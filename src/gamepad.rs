@@ -0,0 +1,89 @@
+use crate::keymap::parse_bindings;
+use sdl3::GamepadSubsystem;
+use sdl3::gamepad::{Button, Gamepad};
+use sdl3::sys::joystick::SDL_JoystickID;
+
+/// Gamepad buttons bound to chip8 keys. Several buttons may share a key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PadMap {
+    pub buttons: Vec<(Button, usize)>,
+}
+
+impl Default for PadMap {
+    /// The d-pad acts as the 2/4/6/8 arrows most games use, face buttons cover common actions.
+    fn default() -> Self {
+        PadMap {
+            buttons: vec![
+                (Button::DPadUp, 0x2),
+                (Button::DPadLeft, 0x4),
+                (Button::DPadRight, 0x6),
+                (Button::DPadDown, 0x8),
+                (Button::South, 0x5),
+                (Button::East, 0x6),
+                (Button::West, 0x4),
+                (Button::North, 0x1),
+                (Button::Back, 0x0),
+                (Button::Start, 0xf),
+            ],
+        }
+    }
+}
+
+impl PadMap {
+    /// Override bindings from a `KEY=BUTTON,...` list, where KEY is a chip8 key from 0 to F
+    /// and BUTTON an SDL gamepad button name, e.g. `5=a,6=b,2=dpup`.
+    pub fn apply(&mut self, spec: &str) -> Result<(), String> {
+        for (key, name) in parse_bindings(spec)? {
+            let button = Button::from_string(name)
+                .ok_or_else(|| format!("Unknown gamepad button '{}'", name))?;
+            self.buttons.retain(|(bound, _)| *bound != button);
+            self.buttons.push((button, key));
+        }
+        Ok(())
+    }
+}
+
+/// The gamepads currently plugged in.
+pub struct Gamepads {
+    subsystem: GamepadSubsystem,
+    pads: Vec<Gamepad>,
+}
+
+impl Gamepads {
+    pub fn new(subsystem: GamepadSubsystem) -> Self {
+        Gamepads {
+            subsystem,
+            pads: vec![],
+        }
+    }
+
+    /// Handle a controller added event.
+    pub fn connect(&mut self, which: u32) {
+        match self.subsystem.open(SDL_JoystickID(which)) {
+            Ok(pad) => {
+                println!("Gamepad connected: {}", pad.name().unwrap_or_default());
+                self.pads.push(pad);
+            }
+            Err(err) => eprintln!("Could not open gamepad {}: {}", which, err),
+        }
+    }
+
+    /// Handle a controller removed event.
+    pub fn disconnect(&mut self, which: u32) {
+        self.pads.retain(|pad| match pad.id() {
+            Ok(id) => id.0 != which,
+            Err(_) => false,
+        });
+    }
+
+    /// Mark the keys whose buttons are held on any gamepad.
+    pub fn press_keys(&self, padmap: &PadMap, pressed: &mut [bool; 16]) {
+        for pad in &self.pads {
+            for (button, key) in &padmap.buttons {
+                if pad.button(*button) {
+                    pressed[*key] = true;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,143 @@
+use crate::error::Chip8Error;
+use crate::keypad::Chip8Keypad;
+use crate::quirks::Quirks;
+use crate::state::Chip8State;
+use std::fmt::Write;
+use std::time::Duration;
+
+/// Simulated time between two cycles (700Hz).
+const CYCLE_TIME: Duration = Duration::from_micros(1429);
+
+/// Simulated time between two display refreshes (60Hz).
+const FRAME_TIME: Duration = Duration::from_micros(16667);
+
+/// Run `cycles` cycles of `rom` without any frontend, with no key pressed.
+/// Time advances by a fixed amount per cycle and the display refreshes at 60Hz.
+/// Execution stops early when the program exits, or on the first error, which is returned.
+pub fn run_headless(rom: &[u8], cycles: usize, quirks: Quirks) -> Result<Chip8State, Chip8Error> {
+    let mut state = Chip8State::new(rom, quirks)?;
+    run_cycles(&mut state, cycles)?;
+    Ok(state)
+}
+
+/// Like [`run_headless`], on an already powered on machine. After an error, `pc` points at the
+/// offending instruction.
+pub fn run_cycles(state: &mut Chip8State, cycles: usize) -> Result<(), Chip8Error> {
+    let keypad = Chip8Keypad::default();
+    let mut since_frame = Duration::ZERO;
+
+    for _ in 0..cycles {
+        state.update(CYCLE_TIME, &keypad)?;
+        if state.halted {
+            break;
+        }
+        since_frame += CYCLE_TIME;
+        if since_frame >= FRAME_TIME {
+            since_frame -= FRAME_TIME;
+            state.vblank();
+        }
+    }
+    Ok(())
+}
+
+/// Registers, timers and display of the machine in a diffable text form.
+pub fn dump_state(state: &Chip8State) -> String {
+    let mut out = dump_registers(state);
+    out.push_str(&state.display.to_ascii());
+    out
+}
+
+/// Registers and timers of the machine, without the display.
+pub fn dump_registers(state: &Chip8State) -> String {
+    let mut out = String::new();
+    writeln!(out, "pc=0x{:04x} i=0x{:04x}", state.pc, state.i).unwrap();
+    for (idx, value) in state.v.iter().enumerate() {
+        write!(out, "v{:x}=0x{:02x}", idx, value).unwrap();
+        out.push(if idx % 8 == 7 { '\n' } else { ' ' });
+    }
+    writeln!(
+        out,
+        "delay_timer={} sound_timer={} stack={}",
+        state.delay_timer,
+        state.sound_timer,
+        state.stack.len()
+    )
+    .unwrap();
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_headless() {
+        // Draw the "0" glyph then loop forever
+        let rom = [0x60, 0x00, 0xa0, 0x50, 0xd0, 0x05, 0x12, 0x06];
+        let state = run_headless(&rom, 100, Quirks::default()).unwrap();
+        assert_eq!(state.pc, 0x206);
+
+        let dump = dump_state(&state);
+        let lines: Vec<&str> = dump.lines().collect();
+        assert_eq!(lines[0], "pc=0x0206 i=0x0050");
+        assert!(lines[4].starts_with("####...."));
+        assert!(lines[5].starts_with("#..#...."));
+    }
+
+    #[test]
+    fn test_run_headless_error() {
+        // v0 = 1, then an unknown instruction
+        let rom = [0x60, 0x01, 0x51, 0x21];
+        assert_eq!(
+            run_headless(&rom, 100, Quirks::default()).unwrap_err(),
+            Chip8Error::UnknownOpcode(0x5121)
+        );
+        let mut state = Chip8State::new(&rom, Quirks::default()).unwrap();
+        assert!(run_cycles(&mut state, 100).is_err());
+        assert_eq!((state.pc, state.v[0]), (0x202, 1));
+    }
+
+    #[test]
+    fn test_ibm_logo() {
+        let rom = include_bytes!("../tests/roms/ibm_logo.ch8");
+        let state = run_headless(rom, 100, Quirks::default()).unwrap();
+        let expected = [
+            "................................................................",
+            "................................................................",
+            "................................................................",
+            "................................................................",
+            "................................................................",
+            "................................................................",
+            "................................................................",
+            "................................................................",
+            "............########.#########...#####.........#####............",
+            "................................................................",
+            "............########.###########.######.......######............",
+            "................................................................",
+            "..............####.....###...###...#####.....#####..............",
+            "................................................................",
+            "..............####.....#######.....#######.#######..............",
+            "................................................................",
+            "..............####.....#######.....###.#######.###..............",
+            "................................................................",
+            "..............####.....###...###...###..#####..###..............",
+            "................................................................",
+            "............########.###########.#####...###...#####............",
+            "................................................................",
+            "............########.#########...#####....#....#####............",
+            "................................................................",
+            "................................................................",
+            "................................................................",
+            "................................................................",
+            "................................................................",
+            "................................................................",
+            "................................................................",
+            "................................................................",
+            "................................................................",
+        ];
+        assert_eq!(
+            state.display.to_ascii(),
+            expected.map(|row| format!("{}\n", row)).concat()
+        );
+    }
+}
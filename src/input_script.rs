@@ -0,0 +1,144 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+/// Keys held from given frames on, replayed instead of reading the keyboard.
+///
+/// Each line of a script is `frame key_bitmask`, where bit k of the bitmask is set while key k is
+/// held. The keys stay the same until the frame of the next line.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputScript {
+    /// (frame, keys) in increasing frame order.
+    events: Vec<(u64, u16)>,
+    /// Index of the next event to apply.
+    next: usize,
+    keys: u16,
+}
+
+impl InputScript {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|err| format!("Could not read {}: {}", path, err))?;
+        Self::parse(&text).map_err(|err| format!("{}: {}", path, err))
+    }
+
+    /// Parse a script, blank lines and lines starting with `#` are ignored.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut events: Vec<(u64, u16)> = vec![];
+        for (idx, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid = || format!("line {}: expected 'frame key_bitmask'", idx + 1);
+            let (frame, keys) = line.split_once(char::is_whitespace).ok_or_else(invalid)?;
+            let frame: u64 = frame.parse().map_err(|_| invalid())?;
+            let keys = keys.trim();
+            let keys = match keys.strip_prefix("0x") {
+                Some(digits) => u16::from_str_radix(digits, 16),
+                None => keys.parse(),
+            }
+            .map_err(|_| invalid())?;
+            if events.last().is_some_and(|(last, _)| *last > frame) {
+                return Err(format!("line {}: frames must be in order", idx + 1));
+            }
+            events.push((frame, keys));
+        }
+        Ok(InputScript {
+            events,
+            next: 0,
+            keys: 0,
+        })
+    }
+
+    /// Keys held during `frame`. Frames must not go backwards between calls.
+    pub fn keys_at(&mut self, frame: u64) -> u16 {
+        while let Some(&(event_frame, keys)) = self.events.get(self.next) {
+            if event_frame > frame {
+                break;
+            }
+            self.keys = keys;
+            self.next += 1;
+        }
+        self.keys
+    }
+
+    /// Start over, e.g. when the machine is reset.
+    pub fn rewind(&mut self) {
+        self.next = 0;
+        self.keys = 0;
+    }
+}
+
+/// Writes the keys held to a file in the [`InputScript`] format, a line whenever they change.
+pub struct InputRecorder {
+    pub path: String,
+    writer: BufWriter<File>,
+    keys: u16,
+}
+
+impl InputRecorder {
+    pub fn create(path: &str) -> io::Result<Self> {
+        Ok(InputRecorder {
+            path: path.to_string(),
+            writer: BufWriter::new(File::create(path)?),
+            keys: 0,
+        })
+    }
+
+    /// Record the keys held during `frame`.
+    pub fn record(&mut self, frame: u64, keys: u16) -> io::Result<()> {
+        if keys != self.keys {
+            self.keys = keys;
+            writeln!(self.writer, "{} 0x{:04x}", frame, keys)?;
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_input_script() {
+        let mut script = InputScript::parse("# replay\n10 0x0020\n\n12 0\n12 3\n").unwrap();
+        assert_eq!(script.keys_at(0), 0);
+        assert_eq!(script.keys_at(10), 0x20);
+        assert_eq!(script.keys_at(11), 0x20);
+        // The last line of a frame wins
+        assert_eq!(script.keys_at(12), 3);
+        assert_eq!(script.keys_at(1000), 3);
+        script.rewind();
+        assert_eq!(script.keys_at(0), 0);
+
+        assert!(InputScript::parse("10").is_err());
+        assert!(InputScript::parse("10 0x10000").is_err());
+        assert!(InputScript::parse("10 1\n5 0").is_err());
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let path = std::env::temp_dir().join("chip8_test_input.txt");
+        let path = path.to_str().unwrap();
+        let mut recorder = InputRecorder::create(path).unwrap();
+        for (frame, keys) in [(0, 0), (1, 0x10), (2, 0x10), (3, 0)] {
+            recorder.record(frame, keys).unwrap();
+        }
+        recorder.finish().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(path).unwrap(),
+            "1 0x0010\n3 0x0000\n"
+        );
+
+        let mut script = InputScript::load(path).unwrap();
+        assert_eq!(
+            [0, 1, 2, 3].map(|frame| script.keys_at(frame)),
+            [0, 0x10, 0x10, 0]
+        );
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,82 @@
+use sdl3::keyboard::Scancode;
+
+/// Keyboard key bound to each of the 16 chip8 keys.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyMap {
+    pub keys: [Scancode; 16],
+}
+
+impl Default for KeyMap {
+    /// The left side of a QWERTY keyboard, laid out like the COSMAC VIP keypad:
+    /// ```text
+    /// 1 2 3 4      1 2 3 C
+    /// Q W E R  ->  4 5 6 D
+    /// A S D F      7 8 9 E
+    /// Z X C V      A 0 B F
+    /// ```
+    fn default() -> Self {
+        KeyMap {
+            keys: [
+                Scancode::X,
+                Scancode::_1,
+                Scancode::_2,
+                Scancode::_3,
+                Scancode::Q,
+                Scancode::W,
+                Scancode::E,
+                Scancode::A,
+                Scancode::S,
+                Scancode::D,
+                Scancode::Z,
+                Scancode::C,
+                Scancode::_4,
+                Scancode::R,
+                Scancode::F,
+                Scancode::V,
+            ],
+        }
+    }
+}
+
+impl KeyMap {
+    /// Override bindings from a `KEY=SCANCODE,...` list, where KEY is a chip8 key from 0 to F
+    /// and SCANCODE an SDL scancode name, e.g. `1=Q,4=A`.
+    pub fn apply(&mut self, spec: &str) -> Result<(), String> {
+        for (key, name) in parse_bindings(spec)? {
+            self.keys[key] =
+                Scancode::from_name(name).ok_or_else(|| format!("Unknown key name '{}'", name))?;
+        }
+        Ok(())
+    }
+}
+
+/// Split a `KEY=NAME,...` list into (chip8 key, key name) pairs.
+pub fn parse_bindings(spec: &str) -> Result<Vec<(usize, &str)>, String> {
+    spec.split(',')
+        .map(|binding| {
+            let (key, name) = binding
+                .split_once('=')
+                .ok_or_else(|| format!("Invalid key binding '{}', expected KEY=NAME", binding))?;
+            match usize::from_str_radix(key.trim(), 16) {
+                Ok(key) if key < 16 => Ok((key, name.trim())),
+                _ => Err(format!("Invalid chip8 key '{}', expected 0 to F", key)),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_bindings() {
+        assert_eq!(
+            parse_bindings("1=Q, a=Space,F=Right").unwrap(),
+            [(1, "Q"), (10, "Space"), (15, "Right")]
+        );
+        assert!(parse_bindings("1").is_err());
+        assert!(parse_bindings("10=Q").is_err());
+        assert!(parse_bindings("G=Q").is_err());
+    }
+}
@@ -0,0 +1,68 @@
+#[derive(Debug, Default)]
+pub struct Chip8Keypad {
+    /// Keys held down during the current cycle.
+    pub pressed: [bool; 16],
+    /// Keys held down during the previous cycle.
+    pub pressed_last: [bool; 16],
+}
+
+impl Chip8Keypad {
+    /// A keypad with bit k of `mask` set for each key k held down.
+    pub fn from_bitmask(mask: u16) -> Self {
+        let mut keypad = Chip8Keypad::default();
+        keypad.set_bitmask(mask);
+        keypad
+    }
+
+    /// Bit k is set for each key k held down.
+    pub fn to_bitmask(&self) -> u16 {
+        self.pressed
+            .iter()
+            .enumerate()
+            .filter(|(_, held)| **held)
+            .fold(0, |mask, (key, _)| mask | 1 << key)
+    }
+
+    /// Hold down the keys of `mask`, and release the others.
+    pub fn set_bitmask(&mut self, mask: u16) {
+        self.pressed = std::array::from_fn(|key| mask & (1 << key) != 0);
+    }
+
+    /// Hold down key 0 to F.
+    pub fn press(&mut self, key: usize) {
+        self.pressed[key] = true;
+    }
+
+    /// Release key 0 to F.
+    pub fn release(&mut self, key: usize) {
+        self.pressed[key] = false;
+    }
+
+    /// Start a new cycle: the keys held so far become those of the previous cycle, and stay held
+    /// until changed.
+    pub fn advance(&mut self) {
+        self.pressed_last = self.pressed;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bitmask() {
+        let mut keypad = Chip8Keypad::from_bitmask(0x8020);
+        assert!(keypad.pressed[0x5] && keypad.pressed[0xf]);
+        assert_eq!(keypad.pressed.iter().filter(|held| **held).count(), 2);
+        assert_eq!(keypad.to_bitmask(), 0x8020);
+
+        keypad.advance();
+        keypad.release(0x5);
+        keypad.press(0x0);
+        assert_eq!(keypad.to_bitmask(), 0x8001);
+        assert_eq!(
+            keypad.pressed_last,
+            Chip8Keypad::from_bitmask(0x8020).pressed
+        );
+    }
+}
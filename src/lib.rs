@@ -0,0 +1,46 @@
+//! Emulator core of the chip8 interpreter.
+//!
+//! Nothing in here depends on SDL: a frontend feeds the keypad state and the elapsed time into
+//! [`Chip8State::update`] and reads the display, timers and registers back.
+
+mod asm;
+mod compat;
+mod disasm;
+mod display;
+mod error;
+mod font;
+mod headless;
+mod keypad;
+mod opcode_stats;
+mod platform;
+mod quirks;
+mod rewind;
+mod savestate;
+mod stack;
+mod state;
+mod timing;
+mod trace;
+mod web;
+
+pub use asm::{AsmError, assemble, assemble_at};
+pub use compat::{RomProfile, lookup_rom, rom_sha1};
+pub use disasm::{disassemble, listing, mnemonic, unknown_opcodes};
+pub use display::{
+    Chip8Display, DISPLAY_HEIGHT, DISPLAY_WIDTH, HIRES_DISPLAY_HEIGHT, HIRES_DISPLAY_WIDTH,
+    MAX_DISPLAY_SIZE, NUM_PLANES, PixelPalette,
+};
+pub use error::Chip8Error;
+pub use headless::{dump_registers, dump_state, run_cycles, run_headless};
+pub use keypad::Chip8Keypad;
+pub use opcode_stats::OpcodeStats;
+pub use platform::Platform;
+pub use quirks::{IndexIncrement, Quirks};
+pub use rewind::Rewind;
+pub use stack::{Chip8Stack, STACK_CAPACITY};
+pub use state::{
+    Chip8State, ExecHook, FIXED_CLOCK_HZ, MAX_ROM_SIZE, PROGRAM_START, ProtectedWrite, RAM_SIZE,
+    RPL_FLAGS, XO_RAM_SIZE,
+};
+pub use timing::cycle_cost;
+pub use trace::Trace;
+pub use web::WebChip8;
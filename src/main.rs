@@ -1,507 +1,270 @@
 extern crate sdl3;
 
-mod font;
-
-use core::panic;
-use rand::RngExt;
-use rand::rngs::ThreadRng;
-use sdl3::audio::{AudioCallback, AudioFormat, AudioSpec, AudioStream};
+mod audio;
+mod capture;
+mod cli;
+mod config;
+mod crt;
+mod frame_profile;
+mod gamepad;
+mod input_script;
+mod keymap;
+mod monitor;
+mod overlay;
+mod palette;
+mod phosphor;
+mod rom_file;
+mod touchpad;
+
+use audio::ToneGenerator;
+use capture::Recording;
+use chip8_core::{
+    Chip8Display, Chip8Error, Chip8Keypad, Chip8Stack, Chip8State, DISPLAY_HEIGHT, DISPLAY_WIDTH,
+    Quirks, RPL_FLAGS, Rewind, STACK_CAPACITY, Trace, assemble_at, cycle_cost, disassemble,
+    dump_registers, dump_state, lookup_rom, run_cycles, unknown_opcodes,
+};
+use config::Config;
+use frame_profile::FrameProfile;
+use gamepad::Gamepads;
+use input_script::{InputRecorder, InputScript};
+use palette::Palette;
+use phosphor::Phosphor;
+use rom_file::load_rom;
+use sdl3::audio::{AudioFormat, AudioSpec};
 use sdl3::keyboard::{Keycode, Scancode};
 use sdl3::pixels::Color;
-use sdl3::rect::Point;
-use sdl3::render::{FRect, WindowCanvas};
+use sdl3::rect::{Point, Rect};
+use sdl3::render::{FRect, Texture, TextureCreator, WindowCanvas};
+use sdl3::video::WindowContext;
+use std::cell::RefCell;
+use std::rc::Rc;
 use std::time::{Duration, Instant};
+use touchpad::TouchPad;
 
-/// Display scale factor.
-const SCALE_FACTOR: usize = 12;
+/// Frames in a row whose rendering can be skipped while the interpreter is behind, so that the
+/// display still updates a few times per second.
+const MAX_SKIPPED_FRAMES: usize = 4;
 
-/// Display scale factor.
-const DISPLAY_WIDTH: usize = 64;
-const DISPLAY_HEIGHT: usize = 32;
+/// Speed multiplier while the fast-forward key is held.
+const TURBO_SPEED: u32 = 8;
 
-/// Target frame time.
-/// For some reason, the quirks test will not register my display interrupt wait unless the frame rate is slightly lower than 60fps.
-const FRAMETIME_US: u128 = 16800;
+/// Speed divisor while the slow motion key is held.
+const SLOW_MOTION_SPEED: u32 = 8;
 
-const TIMER_DECREMENT_INTERVAL_US: u128 = 16667;
+/// How long the name of a rom stays on screen after switching to it.
+const ROM_NAME_DURATION: Duration = Duration::from_secs(2);
 
-/// Number of microseconds between two chip8 clock cycles.
-const CHIP8_UPDATE_TIME_US: u128 = 1429; // 1429 = 1000000 / 700 (700Hz)
+/// Number of instructions kept by `--trace`.
+const TRACE_LENGTH: usize = 32;
 
-/// LIFO stack
-const STACK_CAPACITY: usize = 64;
+/// Frames between two rewind snapshots.
+const REWIND_INTERVAL: usize = 4;
 
-#[derive(Debug)]
-#[allow(unused)]
-struct Chip8Stack {
-    buffer: [u16; STACK_CAPACITY],
-    top: usize,
-}
+/// Rewind snapshots kept, 10 seconds at 60fps or about 3MB.
+const REWIND_SNAPSHOTS: usize = 10 * 60 / REWIND_INTERVAL;
 
-#[allow(unused)]
-impl Chip8Stack {
-    fn new() -> Self {
-        return Chip8Stack {
-            buffer: [0; _],
-            top: 0,
-        };
-    }
-    fn push(&mut self, value: u16) {
-        if self.top == STACK_CAPACITY {
-            panic!();
-        }
-        self.buffer[self.top] = value;
-        self.top += 1;
-    }
-    fn pop(&mut self) -> u16 {
-        if self.top == 0 {
-            panic!();
-        }
-        self.top -= 1;
-        return self.buffer[self.top];
+/// True if the environment variable is defined and non-empty.
+fn env_flag(name: &str) -> bool {
+    match std::env::var(name) {
+        Ok(value) => !value.is_empty(),
+        Err(_) => false,
     }
 }
 
-#[derive(Debug)]
-struct Chip8Display {
-    pixels: [bool; DISPLAY_WIDTH * DISPLAY_HEIGHT],
-}
-
-impl Chip8Display {
-    fn clear(&mut self) -> () {
-        self.pixels.fill(false);
-    }
-
-    fn get_mut(&mut self, x: u8, y: u8) -> &mut bool {
-        return self
-            .pixels
-            .get_mut(x as usize + (y as usize) * DISPLAY_WIDTH)
-            .unwrap();
+/// Subroutine depth set by CHIP8_STACK_SIZE, between 1 and STACK_CAPACITY.
+fn env_stack_size() -> Result<usize, String> {
+    let Ok(value) = std::env::var("CHIP8_STACK_SIZE") else {
+        return Ok(STACK_CAPACITY);
+    };
+    match value.parse() {
+        Ok(size @ 1..=STACK_CAPACITY) => Ok(size),
+        _ => Err(format!(
+            "CHIP8_STACK_SIZE must be a number between 1 and {}, got {}",
+            STACK_CAPACITY, value
+        )),
     }
 }
 
-#[derive(Debug)]
-struct Chip8Keypad {
-    pressed: [bool; 16],
-    pressed_last: [bool; 16],
-}
-
-#[allow(unused)]
-#[derive(Debug)]
-struct Chip8State {
-    ram: [u8; 4096],
-    /// Program counter.
-    pc: u16,
-    /// Index register.
-    i: u16,
-    /// General purpose registers.
-    v: [u8; 16],
-    delay_timer: u8,
-    sound_timer: u8,
-    stack: Chip8Stack,
-    display: Chip8Display,
-    rng: ThreadRng,
-    /// If true, stick to the cosmac quirks
-    cosmac_quirks: bool,
-    /// Used to update timers
-    elapsed_us: u128,
-}
-
-impl Chip8State {
-    fn new(rom: &[u8], cosmac: bool) -> Self {
-        let mut ram: [u8; _] = [0; 4096];
-
-        // Copy font into ram
-        ram[0x50..=0x9F].copy_from_slice(&font::FONT);
-        ram[0x200..0x200 + rom.len()].copy_from_slice(rom);
-
-        Chip8State {
-            ram: ram,
-            pc: 0x200,
-            i: 0,
-            v: [0; 16],
-            delay_timer: 0,
-            sound_timer: 0,
-            stack: Chip8Stack::new(),
-            display: Chip8Display { pixels: [false; _] },
-            rng: rand::rng(),
-            cosmac_quirks: cosmac,
-            elapsed_us: 0,
-        }
-    }
-
-    fn update(&mut self, delta: Duration, keypad: &Chip8Keypad, blank_interrupt: bool) {
-        // Update timers
-        self.elapsed_us += delta.as_micros();
-        while self.elapsed_us >= TIMER_DECREMENT_INTERVAL_US {
-            // println!("decrement timers");
-            if self.delay_timer > 0 {
-                self.delay_timer -= 1;
-            }
-            if self.sound_timer > 0 {
-                self.sound_timer -= 1;
-            }
-            self.elapsed_us -= TIMER_DECREMENT_INTERVAL_US;
+pub fn main() {
+    let fps = env_flag("CHIP8_FPS");
+    println!("CHIP8_FPS={}", fps);
+    let grid = env_flag("CHIP8_GRID");
+    println!("CHIP8_GRID={}", grid);
+    let cosmac_quirks = env_flag("CHIP8_COSMAC_QUIRKS");
+    println!("CHIP8_COSMAC_QUIRKS={}", cosmac_quirks);
+    let no_display_wait = env_flag("CHIP8_NO_DISPLAY_WAIT");
+    println!("CHIP8_NO_DISPLAY_WAIT={}", no_display_wait);
+    let stack_size = match env_stack_size() {
+        Ok(stack_size) => stack_size,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
         }
+    };
+    println!("CHIP8_STACK_SIZE={}", stack_size);
 
-        // Fetch
-
-        let instr_bytes: [u8; 2] = self.ram[self.pc as usize..]
-            .chunks(2)
-            .next()
-            .expect("Tried to fetch beyond end of ram")
-            .try_into()
-            .unwrap();
-
-        // dbg!(instr_bytes);
-
-        // Big endian
-        let instr = u16::from_be_bytes(instr_bytes);
-
-        // println!("0x{:04x}", instr);
-
-        self.pc += 2;
-
-        // Decode + execute
-
-        let x = ((instr & 0x0f00) >> 8) as usize;
-        let y = ((instr & 0x00f0) >> 4) as usize;
-        let n = instr & 0x000f;
-        let nn = (instr & 0x00ff) as u8;
-        let nnn = instr & 0x0fff;
+    // Load rom into ram
+    let mut args = std::env::args();
+    let cmd = args.next().unwrap();
+    let args: Vec<String> = args.collect();
+    if args.iter().any(|arg| arg == "--help" || arg == "-h") {
+        println!("{}", cli::usage(&cmd));
+        return;
+    }
 
-        match (instr & 0xf000) >> 12 {
-            0x0 => {
-                if instr == 0x00e0 {
-                    // 0x00e0: clear display
-                    self.display.clear();
-                } else if instr == 0x00ee {
-                    // 0x00ee: return from subroutine
-                    self.pc = self.stack.pop();
-                } else {
-                    panic!("Unknown instruction 0x{:02x}", instr);
-                }
-            }
-            0x1 => {
-                // 0x1nnn: jump
-                self.pc = nnn;
-            }
-            0x2 => {
-                // 0x2nnn: call subroutine
-                self.stack.push(self.pc);
-                self.pc = nnn;
-            }
-            0x3 => {
-                // 0x3xnn: skip if vx == nn
-                if self.v[x] == nn {
-                    self.pc += 2;
-                }
-            }
-            0x4 => {
-                // 0x4xnn: skip if vx != nn
-                if self.v[x] != nn {
-                    self.pc += 2;
-                }
-            }
-            0x5 => {
-                // 0x5xy0: skip if vx == vy
-                if n == 0x0 {
-                    if self.v[x] == self.v[y] {
-                        self.pc += 2;
-                    }
-                } else {
-                    panic!("Unknown instruction 0x{:02x}", instr);
-                }
-            }
-            0x6 => {
-                // 0x6xnn: load vx with immediate value
-                self.v[x] = nn;
-            }
-            0x7 => {
-                // 0x7xnn: add value to register vx
-                self.v[x] = self.v[x].wrapping_add(nn);
-            }
-            0x8 => {
-                // arithmetic
-                if n == 0x0 {
-                    // 0x8xy0: set
-                    self.v[x] = self.v[y];
-                } else if n == 0x1 {
-                    // 0x8xy1: binary or
-                    self.v[x] = self.v[x] | self.v[y];
-                    if self.cosmac_quirks {
-                        self.v[0xf] = 0;
-                    }
-                } else if n == 0x2 {
-                    // 0x8xy2: binary and
-                    self.v[x] = self.v[x] & self.v[y];
-                    if self.cosmac_quirks {
-                        self.v[0xf] = 0;
-                    }
-                } else if n == 0x3 {
-                    // 0x8xy3: binary xor
-                    self.v[x] = self.v[x] ^ self.v[y];
-                    // dbg!(self.cosmac_quirks);
-                    if self.cosmac_quirks {
-                        self.v[0xf] = 0;
-                    }
-                } else if n == 0x4 {
-                    // 0x8xy4: add
-                    let (value, overflow) = self.v[x].overflowing_add(self.v[y]);
-                    self.v[x] = value;
-                    self.v[0xf] = if overflow { 1 } else { 0 };
-                } else if n == 0x5 {
-                    // 0x8xy5: subtract vx - vy
-                    let (value, overflow) = self.v[x].overflowing_sub(self.v[y]);
-                    self.v[x] = value;
-                    self.v[0xf] = if overflow { 0 } else { 1 };
-                } else if n == 0x6 {
-                    // 0x8xy6: shift right
-                    if self.cosmac_quirks {
-                        self.v[x] = self.v[y];
-                    }
-                    let bit = self.v[x] & 0b1;
-                    self.v[x] = self.v[x] >> 1;
-                    self.v[0xf] = bit;
-                } else if n == 0x7 {
-                    // 0x8xy7: subtract vy - vx
-                    let (value, overflow) = self.v[y].overflowing_sub(self.v[x]);
-                    self.v[x] = value;
-                    self.v[0xf] = if overflow { 0 } else { 1 };
-                } else if n == 0xe {
-                    // 0x8xye: shift left
-                    if self.cosmac_quirks {
-                        self.v[x] = self.v[y];
-                    }
-                    let bit = (self.v[x] & 0b10000000) >> 7;
-                    self.v[x] = self.v[x] << 1;
-                    self.v[0xf] = bit;
-                } else {
-                    panic!("Unknown instruction 0x{:02x}", instr);
-                }
-            }
-            0x9 => {
-                // 0x9xy0: skip if vx != vy
-                if n == 0x0 {
-                    if self.v[x] != self.v[y] {
-                        self.pc += 2;
-                    }
-                } else {
-                    panic!("Unknown instruction 0x{:02x}", instr);
-                }
-            }
-            0xa => {
-                // 0xannn: load index register with immediate value
-                self.i = nnn;
-            }
-            0xb => {
-                // 0xbnnn: jump to v0 + nnn
-                self.pc = nnn + self.v[0x0] as u16;
-            }
-            0xc => {
-                // 0xcxnn: rng
-                self.v[x] = self.rng.random::<u8>() & nn;
-            }
-            0xd => {
-                // 0xdxyn: draw sprite
+    let mut options = match cli::parse_args(args.into_iter()) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}", err);
+            eprintln!("{}", cli::usage(&cmd));
+            std::process::exit(1);
+        }
+    };
+    let rom_data = match load_rom(&options.rom_path) {
+        Ok(rom_data) => rom_data,
+        Err(err) => {
+            eprintln!("Could not read rom {}: {}", options.rom_path, err);
+            std::process::exit(1);
+        }
+    };
+    if let Some(out) = &options.assemble {
+        assemble_rom(&rom_data, options.load_addr, out);
+        return;
+    }
+    let mut rom_data = match prepare_rom(rom_data, &options) {
+        Ok(rom_data) => rom_data,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+    warn_if_empty(&rom_data, &options.rom_path);
+    let num_cycles = options.num_cycles;
 
-                if !blank_interrupt {
-                    // Block on this instruction until the next render
-                    self.pc -= 2;
-                } else {
-                    self.v[0xf] = 0;
-
-                    let sprite_addr = self.i;
-                    let mut posy = self.v[y] % (DISPLAY_HEIGHT as u8);
-
-                    'yloop: for row in 0..n {
-                        let mut posx = self.v[x] % (DISPLAY_WIDTH as u8);
-                        let data = self.ram[(sprite_addr + row) as usize];
-
-                        'xloop: for bit_idx in (0..8).rev() {
-                            let value = (data >> bit_idx) & 0b1;
-                            let pixel = self.display.get_mut(posx, posy);
-
-                            if value == 0b1 {
-                                if *pixel {
-                                    self.v[0xf] = 1;
-                                }
-                                *pixel = !*pixel;
-                            }
-                            posx += 1;
-                            if posx as usize >= DISPLAY_WIDTH {
-                                break 'xloop;
-                            }
-                        }
+    // Known roms get their settings from the compatibility database, unless given by flags
+    if let Some(profile) = lookup_rom(&rom_data) {
+        println!("Detected {}", profile.title);
+        options.apply_rom_profile(&profile);
+    }
+    // Environment variables take precedence over the config file and database
+    let mut quirks = if cosmac_quirks {
+        Quirks::COSMAC
+    } else {
+        options.quirks.unwrap_or_default()
+    };
+    if no_display_wait {
+        quirks.display_wait = false;
+    }
 
-                        posy += 1;
-                        if posy as usize >= DISPLAY_HEIGHT {
-                            break 'yloop;
-                        }
-                    }
-                }
-            }
-            0xe => {
-                if nn == 0x9e {
-                    // 0xex9e: skip if key in vx is pressed
-                    if keypad.pressed[self.v[x] as usize] {
-                        self.pc += 2;
-                    }
-                } else if nn == 0xa1 {
-                    // 0xexa1: skip if key in vx is not pressed
-                    if !keypad.pressed[self.v[x] as usize] {
-                        self.pc += 2;
-                    }
-                } else {
-                    panic!("Unknown instruction 0x{:02x}", instr);
-                }
-            }
-            0xf => {
-                if nn == 0x07 {
-                    // 0xfx15: get delay timer
-                    self.v[x] = self.delay_timer;
-                } else if nn == 0x15 {
-                    // 0xfx15: set delay timer
-                    self.delay_timer = self.v[x];
-                } else if nn == 0x18 {
-                    // 0xfx18: set sound timer
-                    self.sound_timer = self.v[x];
-                } else if nn == 0x1e {
-                    // 0xfx15: add to index
-                    self.i += self.v[x] as u16;
-                    if self.i >= 0x1000 {
-                        self.v[0xf] = 1;
-                        self.i = self.i % 0x1000;
-                    }
-                } else if nn == 0x0a {
-                    // 0xfx0a: get key
-                    let mut k: u8 = 16;
-                    for i in 0..16 {
-                        if keypad.pressed_last[i as usize] && !keypad.pressed[i as usize] {
-                            k = i;
-                            break;
-                        }
-                    }
-                    if k > 15 {
-                        // Keep executing this instruction until some key is pressed
-                        self.pc -= 2;
-                    } else {
-                        self.v[x] = k;
-                    }
-                } else if nn == 0x29 {
-                    // 0xfx29: set index to a font sprite
-                    self.i = 0x50 + self.v[x] as u16 * 5;
-                } else if nn == 0x33 {
-                    // 0xfx33: vx to decimal
-                    let mut vx = self.v[x];
-                    self.ram[self.i as usize] = vx / 100;
-                    vx = vx % 100;
-                    self.ram[(self.i + 1) as usize] = vx / 10;
-                    vx = vx % 10;
-                    self.ram[(self.i + 2) as usize] = vx;
-                    // println!(
-                    //     "{} -> {} {} {}",
-                    //     self.v[x],
-                    //     self.ram[(self.i + 0) as usize],
-                    //     self.ram[(self.i + 1) as usize],
-                    //     self.ram[(self.i + 2) as usize]
-                    // );
-                } else if nn == 0x55 {
-                    // 0xfx55: store to ram
-                    // dbg!(self.i, self.v[x], self.ram[self.i as usize]);
-                    if self.cosmac_quirks {
-                        for i in 0..=x {
-                            self.ram[self.i as usize] = self.v[i];
-                            self.i += 1;
-                        }
-                    } else {
-                        for i in 0..=x {
-                            self.ram[self.i as usize + i] = self.v[i];
-                        }
-                    }
-                } else if nn == 0x65 {
-                    // 0xfx65: load from ram
-                    if self.cosmac_quirks {
-                        for i in 0..=x {
-                            self.v[i] = self.ram[self.i as usize];
-                            self.i += 1;
-                        }
-                    } else {
-                        for i in 0..=x {
-                            self.v[i] = self.ram[self.i as usize + i];
-                        }
-                    }
-                } else {
-                    panic!("Unknown instruction 0x{:02x}", instr);
-                }
-            }
-            _ => {
-                println!("Unknown instruction 0x{:02x}", instr);
-            } //panic!("Invalid instruction 0x{:02x}", instr),
+    let low_pc_limit = options.low_pc_limit();
+    // Shared with the hook of every machine booted, so that it outlives them
+    let trace = options
+        .trace
+        .then(|| Rc::new(RefCell::new(Trace::new(TRACE_LENGTH))));
+
+    // Power on a fresh machine with the rom loaded
+    let boot = |rom: &[u8]| {
+        let mut chip8_state =
+            Chip8State::with_ram_size(rom, quirks, options.load_addr, options.ram_size)
+                .expect("rom size was checked");
+        chip8_state.stack = Chip8Stack::with_capacity(stack_size);
+        if let Some((width, height)) = options.resolution {
+            chip8_state.display = Chip8Display::with_size(width, height);
         }
-    }
-}
+        if let Some(seed) = options.seed {
+            chip8_state.seed_rng(seed);
+        }
+        if let Some(font) = &options.font {
+            chip8_state.load_font(font);
+        }
+        if let Some(font) = &options.big_font {
+            chip8_state.load_big_font(font);
+        }
+        // Forced state to reproduce a bug, ranges were checked when parsing
+        for &(reg, value) in &options.set_regs {
+            chip8_state.set_reg(reg, value).unwrap();
+        }
+        for &(addr, value) in &options.pokes {
+            chip8_state.write_ram(addr, &[value]).unwrap();
+        }
+        if let Some(addr) = options.entry_pc {
+            chip8_state.set_pc(addr).unwrap();
+        }
+        if let Some(trace) = &trace {
+            let trace = trace.clone();
+            chip8_state.set_exec_hook(move |pc, opcode| trace.borrow_mut().push(pc, opcode));
+        }
+        if options.log_opcodes {
+            chip8_state.enable_opcode_stats();
+        }
+        if options.strict_pc {
+            chip8_state.min_pc = Some(low_pc_limit);
+        }
+        chip8_state.aligned_pc = options.strict_odd_pc;
+        chip8_state.write_protect = options.protect.clone();
+        chip8_state
+    };
 
-struct SquareWave {
-    phase_inc: f32,
-    phase: f32,
-    volume: f32,
-}
+    if options.disasm {
+        print_disassembly(&rom_data, options.load_addr);
+        return;
+    }
 
-impl AudioCallback<f32> for SquareWave {
-    fn callback(&mut self, stream: &mut AudioStream, requested: i32) {
-        let mut out = Vec::<f32>::with_capacity(requested as usize);
-        // Generate a square wave
-        for _ in 0..requested {
-            out.push(if self.phase <= 0.5 {
-                self.volume
-            } else {
-                -self.volume
-            });
-            self.phase = (self.phase + self.phase_inc) % 1.0;
+    if options.validate {
+        let unknown = unknown_opcodes(&rom_data, options.load_addr);
+        if unknown.is_empty() {
+            println!("No unknown opcodes");
+            return;
         }
-        stream.put_data_f32(&out).expect("no bueno");
+        let addresses: Vec<String> = unknown
+            .iter()
+            .map(|addr| format!("0x{:03x}", addr))
+            .collect();
+        println!(
+            "{} unknown opcodes at {} (best-effort, data is scanned as instructions too)",
+            unknown.len(),
+            addresses.join(", ")
+        );
+        std::process::exit(1);
     }
-}
 
-pub fn main() {
-    let fps = match std::env::var("CHIP8_FPS") {
-        Ok(value) => {
-            if value == "" {
-                false
-            } else {
-                true
-            }
+    if env_flag("CHIP8_HEADLESS") {
+        if num_cycles == 0 {
+            println!("Headless mode needs a number of cycles to run, given with --max-cycles.");
+            println!("{}", cli::usage(&cmd));
+            return;
         }
-        Err(_) => false,
-    };
-    println!("CHIP8_FPS={}", fps);
-    let grid = match std::env::var("CHIP8_GRID") {
-        Ok(value) => {
-            if value == "" {
-                false
-            } else {
-                true
-            }
+        let mut chip8_state = boot(&rom_data);
+        let result = run_cycles(&mut chip8_state, num_cycles);
+        if let Err(err) = &result {
+            eprintln!("Halting at pc=0x{:04x}: {}", chip8_state.pc, err);
+            print_trace(&trace);
         }
-        Err(_) => false,
-    };
-    println!("CHIP8_GRID={}", grid);
-    let cosmac_quirks = match std::env::var("CHIP8_COSMAC_QUIRKS") {
-        Ok(value) => {
-            if value == "" {
-                false
-            } else {
-                true
-            }
+        if options.dump_display_on_exit {
+            print!("{}", dump_state(&chip8_state));
+        } else {
+            print!("{}", dump_registers(&chip8_state));
         }
-        Err(_) => false,
-    };
-    println!("CHIP8_COSMAC_QUIRKS={}", cosmac_quirks);
+        if let Some(path) = &options.dump_ram_on_exit {
+            dump_ram(&chip8_state, path);
+        }
+        if result.is_err() {
+            std::process::exit(1);
+        }
+        return;
+    }
 
     let sdl_context = sdl3::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
     let audio_subsystem = sdl_context.audio().unwrap();
+    // Gamepads are optional, the keyboard always works
+    let mut gamepads = match sdl_context.gamepad() {
+        Ok(subsystem) => Some(Gamepads::new(subsystem)),
+        Err(err) => {
+            eprintln!("Gamepad support unavailable: {}", err);
+            None
+        }
+    };
 
     let source_freq = 44100;
     let source_spec = AudioSpec {
@@ -510,26 +273,36 @@ pub fn main() {
         format: Some(AudioFormat::f32_sys()),
     };
 
-    let dev = audio_subsystem
+    let mut dev = audio_subsystem
         .open_playback_stream(
             &source_spec,
-            SquareWave {
-                phase_inc: 440.0 / source_freq as f32,
-                phase: 0.0,
-                volume: 0.05,
-            },
+            ToneGenerator::new(
+                options.tone,
+                options.pitch,
+                source_freq,
+                audio::amplitude(options.volume),
+            ),
         )
         .unwrap();
+    // Always running, the tone generator fades the beep in and out instead of clicking
+    dev.resume().unwrap();
     let mut beeping = false;
-
+    let mut muted = options.mute;
+    let mut volume = options.volume;
+    // XO-CHIP audio pattern and pitch last handed to the audio callback
+    let mut playing_pattern = (None, 0);
+
+    let (display_width, display_height) = options
+        .resolution
+        .unwrap_or((DISPLAY_WIDTH, DISPLAY_HEIGHT));
     let window = video_subsystem
         .window(
             "chip8 interpreter",
-            (DISPLAY_WIDTH * SCALE_FACTOR) as u32,
-            (DISPLAY_HEIGHT * SCALE_FACTOR) as u32,
+            (display_width * options.scale) as u32,
+            (display_height * options.scale) as u32,
         )
         .position_centered()
-        .borderless()
+        .resizable()
         .build()
         .expect("no bueno");
 
@@ -539,192 +312,1017 @@ pub fn main() {
     canvas.clear();
     canvas.present();
 
+    let texture_creator = canvas.texture_creator();
+    let mut frame_cache = FrameCache::new(&texture_creator);
+
     let mut event_pump = sdl_context.event_pump().unwrap();
 
     let mut prev_update = Instant::now();
     let mut lag_us = 0;
     let mut prev_render = Instant::now();
-
-    // Load rom into ram
-    let mut args = std::env::args();
-    let cmd = args.next().unwrap();
-
-    let rom_path: String = match args.next() {
-        Some(path) => path,
-        None => {
-            println!("Please provide the path to a chip8 rom.");
-            println!("Usage: {} path/to/rom.ch8", cmd);
-            return;
-        }
-    };
-    let rom_data = std::fs::read(rom_path).unwrap();
-
-    let num_cycles: usize = match args.next() {
-        Some(cycles) => cycles.parse().unwrap(),
-        None => 0,
-    };
-
-    let mut chip8_state = Chip8State::new(&rom_data, cosmac_quirks);
+    let mut speed_meter = SpeedMeter::new(Instant::now());
+    let mut skipped_frames = 0;
+    let mut frame_profile = options.profile_frames.then(FrameProfile::default);
+    // Time spent since the last rendered frame
+    let mut frame_events = Duration::ZERO;
+    let mut frame_cycles = Duration::ZERO;
+
+    let mut chip8_state = boot(&rom_data);
+    // Save state slot 0, next to the rom
+    let mut save_path = format!("{}.state0", options.rom_path);
+    // High scores saved by SUPER-CHIP games, also next to the rom
+    let mut rpl_path = format!("{}.rpl", options.rom_path);
+    chip8_state.rpl = load_rpl(&rpl_path);
+    let mut saved_rpl = chip8_state.rpl;
 
     let mut cycle_idx = 0;
-
-    let mut keypad = Chip8Keypad {
-        pressed: [false; 16],
-        pressed_last: [false; 16],
-    };
-
-    let mut just_rendered = false;
+    // Cycles still taken by the last instruction with --accurate-timing
+    let mut busy_cycles = 0;
+    let mut halted = false;
+    let mut paused = options.start_paused;
+    if paused {
+        println!("Paused");
+    }
+    // Set when losing focus paused the interpreter, so that regaining it resumes
+    let mut paused_unfocused = false;
+    // Instructions to execute one at a time while paused
+    let mut steps_requested = 0;
+    // Set when pausing on a breakpoint, so that resuming executes the instruction under it
+    let mut at_breakpoint = false;
+    // Subroutine call to pause after, once it returns
+    let mut step_over = None;
+    let mut warned_low_pc = false;
+    let mut warned_odd_pc = false;
+
+    let mut keypad = Chip8Keypad::default();
+    let mut recording: Option<Recording> = None;
+    let mut phosphor = options.phosphor.then(Phosphor::default);
+    let mut crt = options.crt;
+    let mut touchpad = options.touchpad.then(TouchPad::default);
+    let mut hud = false;
+    let mut memory_viewer = false;
+    let mut disassembly_viewer = false;
+    let mut rewind = Rewind::new(REWIND_SNAPSHOTS, REWIND_INTERVAL);
+    let monitor = options.monitor.then(monitor::spawn);
+    let mut input_script = options.input_script.as_deref().map(|path| {
+        InputScript::load(path).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        })
+    });
+    let mut input_recorder = options.record_input.as_deref().map(|path| {
+        InputRecorder::create(path).unwrap_or_else(|err| {
+            eprintln!("Could not record input to {}: {}", path, err);
+            std::process::exit(1);
+        })
+    });
+
+    let mut playlist_idx = 0;
+    // Name of the rom switched to and when, shown for a moment
+    let mut rom_banner: Option<(String, Instant)> = None;
 
     'running: loop {
+        // Rom to load instead of the running one, dropped on the window or from the playlist
+        let mut switch_to = None;
+
         // Handle events
+        let events_started = Instant::now();
         for event in event_pump.poll_iter() {
-            use sdl3::event::Event;
+            use sdl3::event::{Event, WindowEvent};
             match event {
                 Event::Quit { .. }
                 | Event::KeyDown {
                     keycode: Some(Keycode::Escape),
                     ..
-                } => break 'running,
+                } => {
+                    print_trace(&trace);
+                    break 'running;
+                }
+                Event::ControllerDeviceAdded { which, .. } => {
+                    if let Some(gamepads) = &mut gamepads {
+                        gamepads.connect(which);
+                    }
+                }
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    if let Some(gamepads) = &mut gamepads {
+                        gamepads.disconnect(which);
+                    }
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::P),
+                    repeat: false,
+                    ..
+                } => {
+                    paused = !paused;
+                    paused_unfocused = false;
+                    step_over = None;
+                    println!("{}", if paused { "Paused" } else { "Resumed" });
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::M),
+                    repeat: false,
+                    ..
+                } => {
+                    muted = !muted;
+                    println!("{}", if muted { "Muted" } else { "Unmuted" });
+                }
+                Event::KeyDown {
+                    keycode:
+                        Some(
+                            key @ (Keycode::Plus
+                            | Keycode::Equals
+                            | Keycode::KpPlus
+                            | Keycode::Minus
+                            | Keycode::KpMinus),
+                        ),
+                    ..
+                } => {
+                    let step = if matches!(key, Keycode::Minus | Keycode::KpMinus) {
+                        -audio::VOLUME_STEP
+                    } else {
+                        audio::VOLUME_STEP
+                    };
+                    // Rounded so that repeated steps land back on the same values
+                    volume = ((volume + step).clamp(0.0, 1.0) * 100.0).round() / 100.0;
+                    if let Some(mut tone) = dev.lock() {
+                        tone.set_volume(volume);
+                    }
+                    println!("Volume {}%", (volume * 100.0).round());
+                }
+                Event::Window {
+                    win_event: WindowEvent::FocusLost,
+                    ..
+                } if options.pause_unfocused && !paused => {
+                    paused = true;
+                    paused_unfocused = true;
+                    println!("Paused");
+                }
+                Event::Window {
+                    win_event: WindowEvent::FocusGained,
+                    ..
+                } if paused_unfocused => {
+                    paused = false;
+                    paused_unfocused = false;
+                    println!("Resumed");
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F3),
+                    repeat: false,
+                    ..
+                } => hud = !hud,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F4),
+                    repeat: false,
+                    ..
+                } => memory_viewer = !memory_viewer,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F2),
+                    repeat: false,
+                    ..
+                } => disassembly_viewer = !disassembly_viewer,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F5),
+                    repeat: false,
+                    ..
+                } => {
+                    chip8_state = boot(&rom_data);
+                    chip8_state.rpl = saved_rpl;
+                    cycle_idx = 0;
+                    halted = false;
+                    rewind.clear();
+                    if let Some(script) = &mut input_script {
+                        script.rewind();
+                    }
+                    println!("Reset");
+                }
+                Event::DropFile { filename, .. } => switch_to = Some(filename),
+                Event::KeyDown {
+                    keycode: Some(key @ (Keycode::LeftBracket | Keycode::RightBracket)),
+                    repeat: false,
+                    ..
+                } if options.playlist.len() > 1 => {
+                    let len = options.playlist.len();
+                    playlist_idx = if key == Keycode::RightBracket {
+                        (playlist_idx + 1) % len
+                    } else {
+                        (playlist_idx + len - 1) % len
+                    };
+                    switch_to = Some(options.playlist[playlist_idx].clone());
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F6),
+                    repeat: false,
+                    ..
+                } => match chip8_state.save(&save_path) {
+                    Ok(()) => println!("Saved state to {}", save_path),
+                    Err(err) => eprintln!("Could not save state to {}: {}", save_path, err),
+                },
+                Event::KeyDown {
+                    keycode: Some(Keycode::F7),
+                    repeat: false,
+                    ..
+                } => match Chip8State::load(&save_path) {
+                    Ok(mut state) => {
+                        // Keep tracing and counting across the jump
+                        state.exec_hook = chip8_state.exec_hook.take();
+                        state.opcode_stats = chip8_state.opcode_stats.take();
+                        state.min_pc = chip8_state.min_pc;
+                        state.aligned_pc = chip8_state.aligned_pc;
+                        state.write_protect = chip8_state.write_protect.take();
+                        chip8_state = state;
+                        halted = false;
+                        println!("Loaded state from {}", save_path);
+                    }
+                    Err(err) => eprintln!("Could not load state from {}: {}", save_path, err),
+                },
+                Event::KeyDown {
+                    keycode: Some(Keycode::F8),
+                    repeat: false,
+                    ..
+                } => {
+                    crt = !crt;
+                    frame_cache.invalidate();
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F9),
+                    repeat: false,
+                    ..
+                } => match recording.take() {
+                    Some(recording) => stop_recording(recording),
+                    None => match Recording::start(&chip8_state.display, &options.palette) {
+                        Ok(new_recording) => {
+                            println!("Recording to {}", new_recording.path);
+                            recording = Some(new_recording);
+                        }
+                        Err(err) => eprintln!("Could not start recording: {}", err),
+                    },
+                },
+                Event::KeyDown {
+                    keycode: Some(Keycode::F12),
+                    repeat: false,
+                    ..
+                } => match capture::save_screenshot(
+                    &chip8_state.display,
+                    &options.palette,
+                    options.scale,
+                ) {
+                    Ok(path) => println!("Saved screenshot to {}", path),
+                    Err(err) => eprintln!("Could not save screenshot: {}", err),
+                },
+                Event::KeyDown {
+                    keycode: Some(Keycode::Period),
+                    ..
+                } if paused => steps_requested += 1,
+                Event::KeyDown {
+                    keycode: Some(Keycode::Comma),
+                    ..
+                } if paused => {
+                    step_over = monitor::step_over(&chip8_state, &mut paused, &mut steps_requested);
+                }
+                Event::MouseButtonDown { which, x, y, .. } => {
+                    if let Some(touchpad) = &mut touchpad {
+                        let (width, height) = canvas.window().size();
+                        touchpad.mouse_down(which, x, y, width as f32, height as f32);
+                    }
+                }
+                Event::MouseButtonUp { which, .. } => {
+                    if let Some(touchpad) = &mut touchpad {
+                        touchpad.mouse_up(which);
+                    }
+                }
+                // Finger positions are normalized to the window size
+                Event::FingerDown {
+                    finger_id, x, y, ..
+                } => {
+                    if let Some(touchpad) = &mut touchpad {
+                        let (width, height) = canvas.window().size();
+                        let (width, height) = (width as f32, height as f32);
+                        touchpad.touch_down(finger_id, x * width, y * height, width, height);
+                    }
+                }
+                Event::FingerUp { finger_id, .. } => {
+                    if let Some(touchpad) = &mut touchpad {
+                        touchpad.touch_up(finger_id);
+                    }
+                }
                 _ => {}
             }
         }
 
-        // Update in as many fixed steps
-        lag_us += prev_update.elapsed().as_micros();
+        if let Some(path) = switch_to {
+            // Swap roms, the old one keeps running if the new one can't be loaded
+            match load_rom(&path)
+                .map_err(|err| err.to_string())
+                .and_then(|rom| prepare_rom(rom, &options).map_err(|err| err.to_string()))
+            {
+                Ok(rom) => {
+                    warn_if_empty(&rom, &path);
+                    rom_data = rom;
+                    chip8_state = boot(&rom_data);
+                    cycle_idx = 0;
+                    halted = false;
+                    rewind.clear();
+                    if let Some(script) = &mut input_script {
+                        script.rewind();
+                    }
+                    save_path = format!("{}.state0", path);
+                    rpl_path = format!("{}.rpl", path);
+                    chip8_state.rpl = load_rpl(&rpl_path);
+                    saved_rpl = chip8_state.rpl;
+                    println!("Loaded {}", path);
+                    let name = std::path::Path::new(&path)
+                        .file_name()
+                        .map_or(path.clone(), |name| name.to_string_lossy().into_owned());
+                    rom_banner = Some((name, Instant::now()));
+                }
+                Err(err) => eprintln!("Could not load {}: {}", path, err),
+            }
+        }
+
+        if let Some(commands) = &monitor {
+            for command in commands.try_iter() {
+                let controls = monitor::Controls {
+                    paused: &mut paused,
+                    steps: &mut steps_requested,
+                    breakpoints: &mut options.breakpoints,
+                    step_over: &mut step_over,
+                };
+                match command.and_then(|command| command.run(&mut chip8_state, controls)) {
+                    Ok(output) => print!("{}", output),
+                    Err(err) => println!("{}", err),
+                }
+            }
+        }
+
+        // Fast-forward while Tab is held and slow down while Shift is, time passes faster or
+        // slower for the emulated machine, timers included
+        frame_events += events_started.elapsed();
+
+        let keyboard = event_pump.keyboard_state();
+        let (speed_up, slow_down) = if keyboard.is_scancode_pressed(Scancode::Tab) {
+            (TURBO_SPEED, 1)
+        } else if keyboard.is_scancode_pressed(Scancode::LShift) {
+            (1, SLOW_MOTION_SPEED)
+        } else {
+            (1, 1)
+        };
+        // Go back in time while Backspace is held, the machine stands still meanwhile
+        let rewinding = event_pump
+            .keyboard_state()
+            .is_scancode_pressed(Scancode::Backspace);
+
+        let elapsed = prev_update.elapsed() * speed_up / slow_down;
+        prev_update = Instant::now();
+        let running = !halted && (cycle_idx < num_cycles || num_cycles == 0);
+        if paused || rewinding {
+            // Don't let time accumulate, so that timers and cycles resume where they stopped
+            lag_us = 0;
+        } else {
+            // Update in as many fixed steps
+            lag_us += elapsed.as_micros();
+            // Timers run on their own 60Hz clock, whatever the cpu clock
+            if running {
+                chip8_state.tick_timers(elapsed);
+            }
+        }
+        let cycles_started = Instant::now();
         // Number of cycles to simulate.
-        while lag_us >= CHIP8_UPDATE_TIME_US {
-            // println!("lag_us={} us_per_update={}", lag_us, CHIP8_UPDATE_TIME_US);
-            let delta = prev_update.elapsed();
-            prev_update = Instant::now();
+        while lag_us >= options.cycle_time_us() || steps_requested > 0 {
+            let step_requested = steps_requested > 0;
+            if busy_cycles > 0 && !step_requested {
+                busy_cycles -= 1;
+                lag_us -= options.cycle_time_us();
+                speed_meter.add_cycle();
+                continue;
+            }
+            if !step_requested && !at_breakpoint && options.breakpoints.contains(&chip8_state.pc) {
+                paused = true;
+                at_breakpoint = true;
+                step_over = None;
+                println!("Breakpoint at 0x{:04x}", chip8_state.pc);
+                print!("{}", dump_registers(&chip8_state));
+                break;
+            }
+
+            if step_requested {
+                match chip8_state.instruction_at(chip8_state.pc) {
+                    Some(instr) => println!(
+                        "0x{:04x}: {:04X}    {}",
+                        chip8_state.pc,
+                        instr,
+                        disassemble(instr)
+                    ),
+                    None => println!("0x{:04x}: ????", chip8_state.pc),
+                }
+            }
 
             let kb = event_pump.keyboard_state();
 
-            keypad.pressed_last = keypad.pressed;
-            keypad.pressed = [
-                kb.is_scancode_pressed(Scancode::X),
-                kb.is_scancode_pressed(Scancode::_1),
-                kb.is_scancode_pressed(Scancode::_2),
-                kb.is_scancode_pressed(Scancode::_3),
-                kb.is_scancode_pressed(Scancode::Q),
-                kb.is_scancode_pressed(Scancode::W),
-                kb.is_scancode_pressed(Scancode::E),
-                kb.is_scancode_pressed(Scancode::A),
-                kb.is_scancode_pressed(Scancode::S),
-                kb.is_scancode_pressed(Scancode::D),
-                kb.is_scancode_pressed(Scancode::Z),
-                kb.is_scancode_pressed(Scancode::C),
-                kb.is_scancode_pressed(Scancode::_4),
-                kb.is_scancode_pressed(Scancode::R),
-                kb.is_scancode_pressed(Scancode::F),
-                kb.is_scancode_pressed(Scancode::V),
-            ];
-
-            if cycle_idx < num_cycles || num_cycles == 0 {
-                chip8_state.update(delta, &keypad, just_rendered);
-                just_rendered = false;
+            keypad.advance();
+            let frame = options.frame_of_cycle(cycle_idx);
+            if let Some(script) = &mut input_script {
+                keypad.set_bitmask(script.keys_at(frame));
+            } else {
+                keypad.pressed = options
+                    .keymap
+                    .keys
+                    .map(|scancode| kb.is_scancode_pressed(scancode));
+                if let Some(gamepads) = &gamepads {
+                    gamepads.press_keys(&options.padmap, &mut keypad.pressed);
+                }
+                if let Some(touchpad) = &touchpad {
+                    touchpad.press_keys(&mut keypad.pressed);
+                }
+            }
+            if let Some(recorder) = &mut input_recorder
+                && let Err(err) = recorder.record(frame, keypad.to_bitmask())
+            {
+                eprintln!("Stopped recording input to {}: {}", recorder.path, err);
+                input_recorder = None;
+            }
+
+            if !halted && (cycle_idx < num_cycles || num_cycles == 0) {
+                if options.warn_low_pc && !warned_low_pc && chip8_state.pc < low_pc_limit {
+                    warned_low_pc = true;
+                    eprintln!(
+                        "Warning: executing below 0x{:03x} at pc=0x{:04x}, probably font data",
+                        low_pc_limit, chip8_state.pc
+                    );
+                    print_trace(&trace);
+                }
+                if options.warn_odd_pc && !warned_odd_pc && !chip8_state.pc.is_multiple_of(2) {
+                    warned_odd_pc = true;
+                    eprintln!(
+                        "Warning: executing at odd address pc=0x{:04x}, probably a bad jump",
+                        chip8_state.pc
+                    );
+                    print_trace(&trace);
+                }
+                let v_before = chip8_state.v;
+                if options.accurate_timing
+                    && let Some(instr) = chip8_state.instruction_at(chip8_state.pc)
+                {
+                    busy_cycles = cycle_cost(instr) - 1;
+                }
+                let result = if step_requested {
+                    // A single step runs exactly one instruction, even a sprite waiting for the
+                    // display, and advances the timers by one cycle
+                    chip8_state.tick_timers(Duration::from_micros(options.cycle_time_us() as u64));
+                    chip8_state.step(&keypad).map(|_| ())
+                } else {
+                    // The timers were already serviced
+                    chip8_state.update(Duration::ZERO, &keypad)
+                };
+                if let Err(err) = result {
+                    // Freeze the machine, but keep the window open to inspect the display
+                    eprintln!("Halting at pc=0x{:04x}: {}", chip8_state.pc, err);
+                    print_trace(&trace);
+                    halted = true;
+                } else if chip8_state.halted {
+                    println!("Program exited");
+                    halted = true;
+                }
+                at_breakpoint = false;
                 cycle_idx += 1;
+                if step_over.is_some_and(|call| call.returned(&chip8_state)) {
+                    step_over = None;
+                    paused = true;
+                    println!("Returned to 0x{:04x}", chip8_state.pc);
+                }
+
+                let mut watch_hit = false;
+                for &reg in &options.watch_regs {
+                    if chip8_state.v[reg] != v_before[reg] {
+                        println!(
+                            "V{:X} changed from 0x{:02x} to 0x{:02x}",
+                            reg, v_before[reg], chip8_state.v[reg]
+                        );
+                        watch_hit = true;
+                    }
+                }
+                for write in chip8_state.protected_writes.drain(..) {
+                    println!(
+                        "Write to protected 0x{:04x} at pc=0x{:04x}: 0x{:02x} to 0x{:02x}",
+                        write.addr, write.pc, write.old, write.new
+                    );
+                    watch_hit = true;
+                }
+                if watch_hit && !paused {
+                    paused = true;
+                    print!("{}", dump_registers(&chip8_state));
+                }
                 if cycle_idx == num_cycles {
                     println!("Stopping interpreter after {} cycles", num_cycles);
-                }
-                if chip8_state.sound_timer > 0 && !beeping {
-                    beeping = true;
-                    dev.resume().unwrap();
-                } else if beeping && chip8_state.sound_timer == 0 {
-                    beeping = false;
-                    dev.pause().unwrap();
+                    break 'running;
                 }
             }
 
-            // println!("update time: {} us", update_start.elapsed().as_micros());
-            lag_us -= CHIP8_UPDATE_TIME_US;
+            if step_requested {
+                steps_requested -= 1;
+            } else {
+                lag_us -= options.cycle_time_us();
+                speed_meter.add_cycle();
+            }
+            if paused && steps_requested == 0 {
+                break;
+            }
+        }
+        frame_cycles += cycles_started.elapsed();
+
+        if chip8_state.rpl != saved_rpl {
+            saved_rpl = chip8_state.rpl;
+            if let Err(err) = std::fs::write(&rpl_path, saved_rpl) {
+                eprintln!("Could not save flags to {}: {}", rpl_path, err);
+            }
+        }
+
+        let silent = halted || paused || muted;
+        let pattern = (chip8_state.audio_pattern, chip8_state.pitch);
+        if pattern != playing_pattern
+            && let Some(mut tone) = dev.lock()
+        {
+            tone.set_pattern(pattern.0, chip8_state.pattern_rate());
+            playing_pattern = pattern;
+        }
+        let beep = chip8_state.sound_timer > 0 && !silent;
+        if beep != beeping
+            && let Some(mut tone) = dev.lock()
+        {
+            tone.set_playing(beep);
+            beeping = beep;
         }
 
-        if prev_render.elapsed().as_micros() > FRAMETIME_US {
-            let framerate = if fps {
-                Some(1.0 / prev_render.elapsed().as_secs_f64())
+        if prev_render.elapsed().as_micros() > options.frame_time_us() {
+            let speed = if fps {
+                Some((
+                    1.0 / prev_render.elapsed().as_secs_f64(),
+                    speed_meter.rate(Instant::now()),
+                ))
             } else {
                 None
             };
+            // The cycles took a whole frame, rendering as well would only fall further behind
+            let behind = cycles_started.elapsed().as_micros() > options.frame_time_us();
+            let skip_render = options.render_skip && behind && skipped_frames < MAX_SKIPPED_FRAMES;
 
             prev_render = Instant::now();
-            render(&mut canvas, &chip8_state.display, framerate, grid);
-            just_rendered = true;
+            if rewinding {
+                if let Some(mut state) = rewind.step_back() {
+                    state.exec_hook = chip8_state.exec_hook.take();
+                    state.opcode_stats = chip8_state.opcode_stats.take();
+                    state.min_pc = chip8_state.min_pc;
+                    state.aligned_pc = chip8_state.aligned_pc;
+                    state.write_protect = chip8_state.write_protect.take();
+                    chip8_state = state;
+                    halted = false;
+                }
+            } else if !paused && !halted {
+                // Keep the states leading to an error
+                rewind.record(&chip8_state);
+            }
+            if skip_render {
+                skipped_frames += 1;
+            } else {
+                skipped_frames = 0;
+                let render_started = Instant::now();
+                let effects = Effects {
+                    grid,
+                    phosphor: phosphor.as_mut(),
+                    crt: crt.then_some(options.crt_intensity),
+                };
+                render(
+                    &mut canvas,
+                    &mut frame_cache,
+                    &chip8_state.display,
+                    &options.palette,
+                    speed,
+                    effects,
+                );
+                chip8_state.display.mark_clean();
+                if touchpad.is_some() || options.show_keys {
+                    touchpad::draw(&mut canvas, &keypad.pressed, options.palette.color(1));
+                }
+                let (width, height) = canvas.output_size().unwrap();
+                if hud {
+                    overlay::draw_text_box(
+                        &mut canvas,
+                        &overlay::hud_lines(&chip8_state),
+                        &[],
+                        0.0,
+                        height as f32,
+                    );
+                }
+                if let Some((name, shown)) = &rom_banner
+                    && shown.elapsed() < ROM_NAME_DURATION
+                {
+                    let lines = [name.clone()];
+                    let (_, box_height) = overlay::text_box_size(&lines);
+                    overlay::draw_text_box(&mut canvas, &lines, &[], 0.0, box_height);
+                }
+                if disassembly_viewer {
+                    let (lines, highlights) = overlay::disassembly_view(&chip8_state);
+                    let (box_width, box_height) = overlay::text_box_size(&lines);
+                    overlay::draw_text_box(
+                        &mut canvas,
+                        &lines,
+                        &highlights,
+                        width as f32 - box_width,
+                        box_height,
+                    );
+                }
+                if memory_viewer {
+                    // Stacked in the bottom right corner, pc above i
+                    let mut bottom = height as f32;
+                    for (name, addr, len) in [("I", chip8_state.i, 1), ("PC", chip8_state.pc, 2)] {
+                        let (lines, highlights) =
+                            overlay::memory_view(&chip8_state.ram, name, addr, len);
+                        let (box_width, box_height) = overlay::text_box_size(&lines);
+                        overlay::draw_text_box(
+                            &mut canvas,
+                            &lines,
+                            &highlights,
+                            width as f32 - box_width,
+                            bottom,
+                        );
+                        bottom -= box_height;
+                    }
+                }
+                canvas.present();
+                if let Some(profile) = &mut frame_profile {
+                    profile.add_frame(frame_events, frame_cycles, render_started.elapsed());
+                }
+                frame_events = Duration::ZERO;
+                frame_cycles = Duration::ZERO;
+            }
+            chip8_state.vblank();
+
+            if let Some(active) = &mut recording
+                && let Err(err) = active.add_frame(&chip8_state.display)
+            {
+                eprintln!("Stopped recording to {}: {}", active.path, err);
+                recording = None;
+            }
+        }
+    }
+
+    if let Some(recording) = recording {
+        stop_recording(recording);
+    }
+    if let Some(recorder) = input_recorder {
+        let path = recorder.path.clone();
+        match recorder.finish() {
+            Ok(()) => println!("Saved input to {}", path),
+            Err(err) => eprintln!("Could not save input to {}: {}", path, err),
+        }
+    }
+    if volume != options.volume
+        && let Some(path) = &options.config_path
+    {
+        match Config::save_volume(path, volume) {
+            Ok(()) => println!("Saved volume to {}", path),
+            Err(err) => eprintln!("{}", err),
+        }
+    }
+    if let Some(stats) = &chip8_state.opcode_stats {
+        print!("Executed instructions:\n{}", stats.summary());
+    }
+    if let Some(profile) = &frame_profile {
+        print!("{}", profile.summary());
+    }
+    if options.dump_display_on_exit {
+        print!("{}", chip8_state.display.to_ascii());
+    }
+    if let Some(path) = &options.dump_ram_on_exit {
+        dump_ram(&chip8_state, path);
+    }
+}
+
+/// Write the whole ram to `path` as raw bytes, to compare memory across runs.
+fn dump_ram(state: &Chip8State, path: &str) {
+    match std::fs::write(path, &state.ram) {
+        Ok(()) => println!("Saved ram to {}", path),
+        Err(err) => eprintln!("Could not save ram to {}: {}", path, err),
+    }
+}
+
+/// Finalize the GIF file.
+fn stop_recording(recording: Recording) {
+    let path = recording.path.clone();
+    match recording.finish() {
+        Ok(()) => println!("Saved recording to {}", path),
+        Err(err) => eprintln!("Could not save recording to {}: {}", path, err),
+    }
+}
+
+/// Print the recorded instructions to stderr, if tracing is enabled.
+fn print_trace(trace: &Option<Rc<RefCell<Trace>>>) {
+    if let Some(trace) = trace {
+        eprint!("Last executed instructions:\n{}", trace.borrow().dump());
+    }
+}
+
+/// RPL user flags kept next to the rom by a previous run, or all zeros.
+fn load_rpl(path: &str) -> [u8; RPL_FLAGS] {
+    let mut rpl = [0; RPL_FLAGS];
+    if let Ok(bytes) = std::fs::read(path) {
+        let len = bytes.len().min(RPL_FLAGS);
+        rpl[..len].copy_from_slice(&bytes[..len]);
+    }
+    rpl
+}
+
+/// Drop the header some tools put before the program, and check that the rest fits in ram.
+fn prepare_rom(mut rom: Vec<u8>, options: &cli::Options) -> Result<Vec<u8>, Chip8Error> {
+    rom.drain(..options.skip.min(rom.len()));
+    Chip8State::with_ram_size(&rom, Quirks::default(), options.load_addr, options.ram_size)?;
+    Ok(rom)
+}
+
+/// Nothing stops an empty rom from running, but the machine would only execute zeros.
+fn warn_if_empty(rom: &[u8], path: &str) {
+    if rom.is_empty() {
+        eprintln!("Warning: rom {} is empty, there is nothing to run", path);
+    }
+}
+
+/// Assemble the source read from the rom path into the rom `out`.
+fn assemble_rom(src: &[u8], load_addr: u16, out: &str) {
+    let Ok(src) = std::str::from_utf8(src) else {
+        eprintln!("Assembly source must be text");
+        std::process::exit(1);
+    };
+    let rom = match assemble_at(src, load_addr) {
+        Ok(rom) => rom,
+        Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+    };
+    match std::fs::write(out, &rom) {
+        Ok(()) => println!("Assembled {} bytes into {}", rom.len(), out),
+        Err(err) => {
+            eprintln!("Could not write rom {}: {}", out, err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Print every word of the rom as an instruction, from the load address.
+fn print_disassembly(rom: &[u8], load_addr: u16) {
+    for (idx, word) in rom.chunks(2).enumerate() {
+        let addr = load_addr as usize + idx * 2;
+        match word {
+            [hi, lo] => {
+                let opcode = u16::from_be_bytes([*hi, *lo]);
+                println!("0x{:04x}: {:04X}    {}", addr, opcode, disassemble(opcode));
+            }
+            // Odd trailing byte
+            _ => println!("0x{:04x}: {:02X}      DB 0x{:02x}", addr, word[0], word[0]),
         }
     }
 }
 
-fn render(canvas: &mut WindowCanvas, display: &Chip8Display, framerate: Option<f64>, grid: bool) {
-    canvas.set_draw_color(Color::RGB(10, 10, 10));
+/// The last drawn display, kept in a texture so that a static screen isn't redrawn every frame.
+struct FrameCache<'a> {
+    texture_creator: &'a TextureCreator<WindowContext>,
+    texture: Option<Texture<'a>>,
+}
+
+impl<'a> FrameCache<'a> {
+    fn new(texture_creator: &'a TextureCreator<WindowContext>) -> Self {
+        FrameCache {
+            texture_creator,
+            texture: None,
+        }
+    }
+
+    /// Forget the last drawn display, so that it is redrawn on the next frame.
+    fn invalidate(&mut self) {
+        self.texture = None;
+    }
+
+    /// A texture of the given size, and whether it was just created and holds nothing yet.
+    fn texture(&mut self, width: u32, height: u32) -> (&mut Texture<'a>, bool) {
+        let stale = self
+            .texture
+            .as_ref()
+            .is_none_or(|texture| (texture.width(), texture.height()) != (width, height));
+        if stale {
+            self.texture = Some(
+                self.texture_creator
+                    .create_texture_target(None, width, height)
+                    .unwrap(),
+            );
+        }
+        (self.texture.as_mut().unwrap(), stale)
+    }
+}
+
+/// Clock cycles run over the last second, to check the machine keeps up with `--clock`.
+struct SpeedMeter {
+    window_start: Instant,
+    /// Cycles run since `window_start`.
+    cycles: u64,
+    /// Cycles per second over the last full window.
+    rate: f64,
+}
+
+impl SpeedMeter {
+    fn new(now: Instant) -> Self {
+        SpeedMeter {
+            window_start: now,
+            cycles: 0,
+            rate: 0.0,
+        }
+    }
+
+    fn add_cycle(&mut self) {
+        self.cycles += 1;
+    }
+
+    /// Cycles per second, measured again once a second has passed since the last time.
+    fn rate(&mut self, now: Instant) -> f64 {
+        let elapsed = now - self.window_start;
+        if elapsed >= Duration::from_secs(1) {
+            self.rate = self.cycles as f64 / elapsed.as_secs_f64();
+            self.cycles = 0;
+            self.window_start = now;
+        }
+        self.rate
+    }
+}
+
+/// Optional ways to draw the display.
+struct Effects<'a> {
+    grid: bool,
+    phosphor: Option<&'a mut Phosphor>,
+    /// Intensity of the scanlines and glow, when enabled.
+    crt: Option<f32>,
+}
+
+/// Draw the display, without presenting it so that overlays can be added.
+/// The display is only redrawn into the cache when it changed or the window was resized.
+fn render(
+    canvas: &mut WindowCanvas,
+    frame_cache: &mut FrameCache,
+    display: &Chip8Display,
+    palette: &Palette,
+    speed: Option<(f64, f64)>,
+    effects: Effects,
+) {
+    let (output_width, output_height) = canvas.output_size().unwrap();
+    let (texture, stale) = frame_cache.texture(output_width, output_height);
+    // Fading pixels change every frame
+    if stale || display.is_dirty() || effects.phosphor.is_some() {
+        canvas
+            .with_texture_canvas(texture, |canvas| {
+                draw_display(canvas, display, palette, effects)
+            })
+            .unwrap();
+    }
+    canvas.copy(texture, None, None).unwrap();
+
+    if let Some((fps, hz)) = speed {
+        canvas.set_draw_color(Color::RGB(165, 165, 165));
+        canvas
+            .draw_debug_text(&format!("{:.1} fps / {:.0} Hz", fps, hz), Point::new(5, 5))
+            .unwrap();
+    }
+}
+
+/// Draw the letterboxed display over the whole output.
+fn draw_display(
+    canvas: &mut WindowCanvas,
+    display: &Chip8Display,
+    palette: &Palette,
+    effects: Effects,
+) {
+    // Letterbox bars
+    canvas.set_draw_color(Color::RGB(0, 0, 0));
     canvas.clear();
 
-    // Draw each pixel as a separate square of SCALE_FACTOR x SCALE_FACTOR
-    let mut rects = vec![];
-    canvas.set_draw_color(Color::RGB(255, 255, 190));
-    for (i, pixel) in display.pixels.iter().enumerate() {
-        if *pixel {
-            let x = i % DISPLAY_WIDTH * SCALE_FACTOR;
-            let y = i / DISPLAY_WIDTH * SCALE_FACTOR;
-            rects.push(FRect::new(
-                x as f32,
-                y as f32,
-                SCALE_FACTOR as f32,
-                SCALE_FACTOR as f32,
-            ));
-        }
-    }
-    canvas.fill_rects(&rects).expect("?");
-
-    if grid {
-        canvas.set_draw_color(Color::RGB(50, 50, 50));
-        for i in 0..DISPLAY_WIDTH {
+    // Size of a chip8 pixel on screen, so that both resolutions fill the window
+    let (output_width, output_height) = canvas.output_size().unwrap();
+    let (origin_x, origin_y, pixel_size) = fit_display(
+        output_width as f32,
+        output_height as f32,
+        display.width(),
+        display.height(),
+    );
+    let display_width = display.width() as f32 * pixel_size;
+    let display_height = display.height() as f32 * pixel_size;
+    let display_area = FRect::new(origin_x, origin_y, display_width, display_height);
+    canvas.set_draw_color(palette.bg());
+    canvas.fill_rect(display_area).unwrap();
+
+    if let Some(phosphor) = effects.phosphor {
+        // Pixels that recently turned off, each in its own shade
+        phosphor.update(display);
+        for (i, (x, y, _)) in display.iter_pixels().enumerate() {
+            if let Some(color) = phosphor.fading_color(i, palette) {
+                let x = origin_x + x as f32 * pixel_size;
+                let y = origin_y + y as f32 * pixel_size;
+                canvas.set_draw_color(color);
+                canvas
+                    .fill_rect(FRect::new(x, y, pixel_size, pixel_size))
+                    .unwrap();
+            }
+        }
+    }
+
+    // Draw each pixel as a separate square of pixel_size x pixel_size, batched by plane value
+    let mut rects = [vec![], vec![], vec![]];
+    for (x, y, value) in display.iter_pixels() {
+        if value != 0 {
+            let x = origin_x + x as f32 * pixel_size;
+            let y = origin_y + y as f32 * pixel_size;
+            rects[value as usize - 1].push(FRect::new(x, y, pixel_size, pixel_size));
+        }
+    }
+    if let Some(intensity) = effects.crt {
+        // Halos first, so that the pixels they surround stay sharp
+        canvas.set_clip_rect(Rect::new(
+            origin_x as i32,
+            origin_y as i32,
+            display_width as u32,
+            display_height as u32,
+        ));
+        for (value, rects) in rects.iter().enumerate() {
+            let color = palette.color(value as u8 + 1);
+            canvas.set_draw_color(crt::halo_color(palette.bg(), color, intensity));
             canvas
-                .draw_line(
-                    ((i * SCALE_FACTOR) as f32 - 1.0, 0.0),
-                    (
-                        (i * SCALE_FACTOR) as f32 - 1.0,
-                        (DISPLAY_HEIGHT * SCALE_FACTOR) as f32,
-                    ),
-                )
+                .fill_rects(&crt::halo_rects(rects, pixel_size))
                 .unwrap();
         }
+        canvas.set_clip_rect(None::<Rect>);
+    }
+    for (value, rects) in rects.iter().enumerate() {
+        canvas.set_draw_color(palette.color(value as u8 + 1));
+        canvas.fill_rects(rects).expect("?");
+    }
+    if let Some(intensity) = effects.crt {
+        crt::draw_scanlines(canvas, display_area, intensity);
+    }
 
-        for i in 0..DISPLAY_HEIGHT {
+    if effects.grid {
+        canvas.set_draw_color(palette.grid);
+        for i in 0..display.width() {
+            let x = origin_x + i as f32 * pixel_size - 1.0;
             canvas
-                .draw_line(
-                    (0.0, (i * SCALE_FACTOR) as f32 - 1.0),
-                    (
-                        (DISPLAY_WIDTH * SCALE_FACTOR) as f32,
-                        (i * SCALE_FACTOR) as f32 - 1.0,
-                    ),
-                )
+                .draw_line((x, origin_y), (x, origin_y + display_height))
                 .unwrap();
         }
-    }
 
-    if let Some(fps) = framerate {
-        canvas.set_draw_color(Color::RGB(165, 165, 165));
-        canvas
-            .draw_debug_text(&format!("{:.1}", fps), Point::new(5, 5))
-            .unwrap();
+        for i in 0..display.height() {
+            let y = origin_y + i as f32 * pixel_size - 1.0;
+            canvas
+                .draw_line((origin_x, y), (origin_x + display_width, y))
+                .unwrap();
+        }
     }
-    canvas.present();
+}
+
+/// Largest pixel size at which the display fits in the output, and where the display starts so
+/// that it is centered. Returns (x, y, pixel size).
+fn fit_display(
+    output_width: f32,
+    output_height: f32,
+    display_width: usize,
+    display_height: usize,
+) -> (f32, f32, f32) {
+    let pixel_size =
+        (output_width / display_width as f32).min(output_height / display_height as f32);
+    let x = (output_width - display_width as f32 * pixel_size) / 2.0;
+    let y = (output_height - display_height as f32 * pixel_size) / 2.0;
+    (x, y, pixel_size)
 }
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+
     #[test]
-    fn test_0() {
-        // let input = 254;
-        // let input = 33;
-        let input = 9;
-
-        let mut vx = input;
-
-        let d0 = vx / 100;
-        vx = vx % 100;
-        let d1 = vx / 10;
-        vx = vx % 10;
-        let d2 = vx;
-        println!("{} -> {} {} {}", input, d0, d1, d2,);
+    fn test_fit_display() {
+        assert_eq!(fit_display(768.0, 384.0, 64, 32), (0.0, 0.0, 12.0));
+        assert_eq!(fit_display(768.0, 384.0, 128, 64), (0.0, 0.0, 6.0));
+        // Too wide, bars on the sides
+        assert_eq!(fit_display(1000.0, 320.0, 64, 32), (180.0, 0.0, 10.0));
+        // Too tall, bars at the top and bottom
+        assert_eq!(fit_display(640.0, 500.0, 64, 32), (0.0, 90.0, 10.0));
+    }
+
+    #[test]
+    fn test_speed_meter() {
+        let start = Instant::now();
+        let mut meter = SpeedMeter::new(start);
+        for _ in 0..700 {
+            meter.add_cycle();
+        }
+        // Nothing measured before a whole second
+        assert_eq!(meter.rate(start + Duration::from_millis(500)), 0.0);
+        assert_eq!(meter.rate(start + Duration::from_secs(1)), 700.0);
+        meter.add_cycle();
+        assert_eq!(meter.rate(start + Duration::from_millis(1500)), 700.0);
+        assert_eq!(meter.rate(start + Duration::from_secs(3)), 0.5);
     }
 }
@@ -0,0 +1,395 @@
+use crate::cli::{parse_address, parse_register};
+use chip8_core::{Chip8State, disassemble, dump_registers, listing};
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::io::BufRead;
+use std::sync::mpsc::{self, Receiver};
+
+/// Bytes printed by `mem` when no length is given.
+const DEFAULT_MEM_LEN: usize = 16;
+
+/// Instructions printed by `list` when no count is given.
+const DEFAULT_LIST_LEN: usize = 10;
+
+/// Bytes per line printed by `mem`.
+const MEM_COLUMNS: usize = 16;
+
+pub const HELP: &str = "Commands:\n  \
+    reg                  print the registers\n  \
+    mem ADDR [LEN]       print LEN bytes of ram from ADDR (default 16)\n  \
+    list [N]             disassemble N instructions from PC (default 10)\n  \
+    set REG VALUE        set V0-VF, I or PC, e.g. set V3 0xff\n  \
+    step [N]             pause and execute N instructions (default 1)\n  \
+    next                 like step, but run a subroutine call until it returns\n  \
+    break ADDR           pause before executing the instruction at ADDR\n  \
+    delete ADDR          remove the breakpoint at ADDR\n  \
+    pause                pause the interpreter\n  \
+    continue             resume the interpreter\n  \
+    help                 print this message";
+
+/// A monitor command typed on stdin.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Registers,
+    Memory { addr: u16, len: usize },
+    List(usize),
+    SetRegister { reg: usize, value: u8 },
+    SetIndex(u16),
+    SetPc(u16),
+    Step(usize),
+    StepOver,
+    Break(u16),
+    Delete(u16),
+    Pause,
+    Continue,
+    Help,
+}
+
+/// What a command needs to change outside the machine.
+pub struct Controls<'a> {
+    pub paused: &'a mut bool,
+    /// Instructions still to execute one by one.
+    pub steps: &'a mut usize,
+    pub breakpoints: &'a mut HashSet<u16>,
+    /// Call being stepped over, if any.
+    pub step_over: &'a mut Option<StepOver>,
+}
+
+/// A subroutine call run at full speed until it returns, after which the interpreter pauses.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StepOver {
+    /// Address of the instruction after the call.
+    return_addr: u16,
+    /// Stack depth before the call, to tell its return from that of a recursive call.
+    depth: usize,
+}
+
+impl StepOver {
+    /// Step over the instruction at pc if it's a 0x2nnn call.
+    pub fn at(state: &Chip8State) -> Option<Self> {
+        let instr = state.instruction_at(state.pc)?;
+        (instr & 0xf000 == 0x2000).then(|| StepOver {
+            return_addr: state.pc.wrapping_add(2),
+            depth: state.stack.len(),
+        })
+    }
+
+    /// Whether the call returned, so that the next instruction is the one after it.
+    pub fn returned(&self, state: &Chip8State) -> bool {
+        state.pc == self.return_addr && state.stack.len() == self.depth
+    }
+}
+
+/// Pause after the instruction at pc, or after the subroutine it calls has returned.
+pub fn step_over(state: &Chip8State, paused: &mut bool, steps: &mut usize) -> Option<StepOver> {
+    let step_over = StepOver::at(state);
+    if step_over.is_some() {
+        *paused = false;
+    } else {
+        *paused = true;
+        *steps = steps.saturating_add(1);
+    }
+    step_over
+}
+
+/// Parse a number, hexadecimal with a `0x` prefix, otherwise decimal.
+fn parse_number(text: &str) -> Result<usize, String> {
+    match text.strip_prefix("0x") {
+        Some(digits) => usize::from_str_radix(digits, 16),
+        None => text.parse(),
+    }
+    .map_err(|_| format!("Invalid number '{}'", text))
+}
+
+/// Parse a line typed on stdin.
+pub fn parse_command(line: &str) -> Result<Command, String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    let command = match words.as_slice() {
+        ["reg" | "r"] => Command::Registers,
+        ["mem" | "m", addr] => Command::Memory {
+            addr: parse_address(addr)?,
+            len: DEFAULT_MEM_LEN,
+        },
+        ["mem" | "m", addr, len] => Command::Memory {
+            addr: parse_address(addr)?,
+            len: parse_number(len)?,
+        },
+        ["list" | "l"] => Command::List(DEFAULT_LIST_LEN),
+        ["list" | "l", count] => Command::List(parse_number(count)?),
+        ["set", reg, value] => {
+            let value = parse_number(value)?;
+            let address =
+                || u16::try_from(value).map_err(|_| format!("Invalid address 0x{:x}", value));
+            match reg.to_ascii_uppercase().as_str() {
+                "I" => Command::SetIndex(address()?),
+                "PC" => Command::SetPc(address()?),
+                _ => Command::SetRegister {
+                    reg: parse_register(reg)?,
+                    value: u8::try_from(value)
+                        .map_err(|_| format!("Invalid register value 0x{:x}", value))?,
+                },
+            }
+        }
+        ["step" | "s"] => Command::Step(1),
+        ["step" | "s", count] => Command::Step(parse_number(count)?),
+        ["next" | "n"] => Command::StepOver,
+        ["break" | "b", addr] => Command::Break(parse_address(addr)?),
+        ["delete" | "d", addr] => Command::Delete(parse_address(addr)?),
+        ["pause"] => Command::Pause,
+        ["continue" | "c"] => Command::Continue,
+        ["help" | "h"] => Command::Help,
+        [] => return Err("Empty command".to_string()),
+        _ => return Err(format!("Unknown command '{}', try help", line.trim())),
+    };
+    Ok(command)
+}
+
+impl Command {
+    /// Apply the command, and return what to print.
+    pub fn run(&self, state: &mut Chip8State, controls: Controls) -> Result<String, String> {
+        let output = match *self {
+            Command::Registers => dump_registers(state),
+            Command::Memory { addr, len } => {
+                let bytes = state.read_ram(addr, len).map_err(|err| err.to_string())?;
+                hex_dump(addr, bytes)
+            }
+            Command::List(count) => disassembly(state, count),
+            Command::SetRegister { reg, value } => {
+                state.set_reg(reg, value).map_err(|err| err.to_string())?;
+                format!("V{:X}=0x{:02x}\n", reg, value)
+            }
+            Command::SetIndex(value) => {
+                state.i = value;
+                format!("I=0x{:04x}\n", value)
+            }
+            Command::SetPc(addr) => {
+                state.set_pc(addr).map_err(|err| err.to_string())?;
+                format!("PC=0x{:04x}\n", addr)
+            }
+            Command::Step(count) => {
+                *controls.paused = true;
+                *controls.steps = controls.steps.saturating_add(count);
+                String::new()
+            }
+            Command::StepOver => {
+                *controls.step_over = step_over(state, controls.paused, controls.steps);
+                String::new()
+            }
+            Command::Break(addr) => {
+                controls.breakpoints.insert(addr);
+                format!("Breakpoint at 0x{:04x}\n", addr)
+            }
+            Command::Delete(addr) => {
+                if !controls.breakpoints.remove(&addr) {
+                    return Err(format!("No breakpoint at 0x{:04x}", addr));
+                }
+                format!("Deleted breakpoint at 0x{:04x}\n", addr)
+            }
+            Command::Pause => {
+                *controls.paused = true;
+                "Paused\n".to_string()
+            }
+            Command::Continue => {
+                *controls.paused = false;
+                "Resumed\n".to_string()
+            }
+            Command::Help => format!("{}\n", HELP),
+        };
+        Ok(output)
+    }
+}
+
+/// Bytes in lines of 16, each starting with its address.
+fn hex_dump(addr: u16, bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for (idx, line) in bytes.chunks(MEM_COLUMNS).enumerate() {
+        let bytes: Vec<String> = line.iter().map(|byte| format!("{:02X}", byte)).collect();
+        writeln!(
+            out,
+            "{:04X} {}",
+            addr as usize + idx * MEM_COLUMNS,
+            bytes.join(" ")
+        )
+        .unwrap();
+    }
+    out
+}
+
+/// `count` instructions from pc, with an arrow on the next one to execute.
+fn disassembly(state: &Chip8State, count: usize) -> String {
+    let mut out = String::new();
+    for (addr, opcode) in listing(&state.ram, state.pc, count) {
+        let marker = if addr == state.pc { "->" } else { "  " };
+        writeln!(
+            out,
+            "{} {:04X} {:04X}  {}",
+            marker,
+            addr,
+            opcode,
+            disassemble(opcode)
+        )
+        .unwrap();
+    }
+    out
+}
+
+/// Read commands from stdin on a separate thread, so that the interpreter never waits for them.
+/// Lines that aren't commands are sent as errors to report.
+pub fn spawn() -> Receiver<Result<Command, String>> {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            if sender.send(parse_command(&line)).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8_core::Quirks;
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(parse_command("reg"), Ok(Command::Registers));
+        assert_eq!(
+            parse_command("mem 0x200 32"),
+            Ok(Command::Memory {
+                addr: 0x200,
+                len: 32
+            })
+        );
+        assert_eq!(
+            parse_command("  set V3 0xFF "),
+            Ok(Command::SetRegister { reg: 3, value: 255 })
+        );
+        assert_eq!(parse_command("set pc 0x300"), Ok(Command::SetPc(0x300)));
+        assert_eq!(parse_command("step 5"), Ok(Command::Step(5)));
+        assert_eq!(
+            parse_command("step 18446744073709551615"),
+            Ok(Command::Step(usize::MAX))
+        );
+        assert_eq!(parse_command("list"), Ok(Command::List(10)));
+        assert_eq!(parse_command("l 0x20"), Ok(Command::List(32)));
+        assert_eq!(parse_command("next"), Ok(Command::StepOver));
+        assert_eq!(parse_command("break 0x300"), Ok(Command::Break(0x300)));
+        assert_eq!(parse_command("continue"), Ok(Command::Continue));
+
+        assert!(parse_command("set V3 256").is_err());
+        assert!(parse_command("set VG 1").is_err());
+        assert!(parse_command("mem").is_err());
+        assert!(parse_command("jump 0x200").is_err());
+    }
+
+    #[test]
+    fn test_run_command() {
+        let mut state = Chip8State::new(&[0x00, 0xe0, 0x12, 0x00], Quirks::default()).unwrap();
+        let mut paused = false;
+        let mut steps = 0;
+        let mut breakpoints = HashSet::new();
+        let mut step_over = None;
+        let mut run = |command: Command, state: &mut Chip8State| {
+            command.run(
+                state,
+                Controls {
+                    paused: &mut paused,
+                    steps: &mut steps,
+                    breakpoints: &mut breakpoints,
+                    step_over: &mut step_over,
+                },
+            )
+        };
+
+        assert_eq!(
+            run(
+                Command::Memory {
+                    addr: 0x200,
+                    len: 4
+                },
+                &mut state
+            ),
+            Ok("0200 00 E0 12 00\n".to_string())
+        );
+        assert_eq!(
+            run(Command::List(2), &mut state),
+            Ok("-> 0200 00E0  CLS\n   0202 1200  JP 0x200\n".to_string())
+        );
+        run(Command::SetRegister { reg: 0xa, value: 7 }, &mut state).unwrap();
+        assert_eq!(state.v[0xa], 7);
+        assert!(run(Command::SetPc(0xfff), &mut state).is_err());
+        assert!(
+            run(
+                Command::Memory {
+                    addr: 0xff0,
+                    len: 32
+                },
+                &mut state
+            )
+            .is_err()
+        );
+        run(Command::Break(0x202), &mut state).unwrap();
+        run(Command::Step(3), &mut state).unwrap();
+        assert!(run(Command::Delete(0x300), &mut state).is_err());
+
+        assert!(paused);
+        assert_eq!(steps, 3);
+        assert!(breakpoints.contains(&0x202));
+    }
+
+    #[test]
+    fn test_run_step_overflow() {
+        let mut state = Chip8State::new(&[0x12, 0x00], Quirks::default()).unwrap();
+        let (mut paused, mut steps) = (false, 0);
+        for count in [usize::MAX, 1] {
+            Command::Step(count)
+                .run(
+                    &mut state,
+                    Controls {
+                        paused: &mut paused,
+                        steps: &mut steps,
+                        breakpoints: &mut HashSet::new(),
+                        step_over: &mut None,
+                    },
+                )
+                .unwrap();
+        }
+        assert_eq!(steps, usize::MAX);
+    }
+
+    #[test]
+    fn test_step_over() {
+        // call 0x206, v1 = 1, loop, then the subroutine: call itself while v0 < 2, then return
+        let rom = [
+            0x22, 0x06, 0x61, 0x01, 0x12, 0x04, 0x70, 0x01, 0x30, 0x02, 0x22, 0x06, 0x00, 0xee,
+        ];
+        let mut state = Chip8State::new(&rom, Quirks::default()).unwrap();
+        let (mut paused, mut steps) = (true, 0);
+        let call = step_over(&state, &mut paused, &mut steps).unwrap();
+        assert!(!paused);
+        assert_eq!(steps, 0);
+
+        let keypad = chip8_core::Chip8Keypad::default();
+        let mut executed = 0;
+        while !call.returned(&state) {
+            state.step(&keypad).unwrap();
+            executed += 1;
+        }
+        // The recursive calls return to 0x20c first, then to 0x202
+        assert_eq!(state.pc, 0x202);
+        assert_eq!(executed, 8);
+
+        // Anything else is a single step
+        assert_eq!(step_over(&state, &mut paused, &mut steps), None);
+        assert!(paused);
+        assert_eq!(steps, 1);
+    }
+}
@@ -0,0 +1,86 @@
+use crate::disasm::mnemonic;
+use std::collections::HashMap;
+use std::fmt::Write;
+
+/// How many times each kind of instruction was executed.
+#[derive(Debug, Clone, Default)]
+pub struct OpcodeStats {
+    /// Counts by the top nibble of the opcode.
+    pub by_nibble: [u64; 16],
+    /// Counts by opcode with the operands masked out, see [`pattern_key`].
+    pub by_pattern: HashMap<u16, u64>,
+}
+
+impl OpcodeStats {
+    pub fn record(&mut self, opcode: u16) {
+        self.by_nibble[(opcode >> 12) as usize] += 1;
+        *self.by_pattern.entry(pattern_key(opcode)).or_default() += 1;
+    }
+
+    /// One line per instruction kind, most executed first, e.g. `8XY4 (add): 12034 times`.
+    pub fn summary(&self) -> String {
+        let mut counts: Vec<(u16, u64)> = self
+            .by_pattern
+            .iter()
+            .map(|(key, count)| (*key, *count))
+            .collect();
+        counts.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        let mut out = String::new();
+        for (key, count) in counts {
+            let name = mnemonic(key)
+                .and_then(|text| text.split(' ').next().map(str::to_lowercase))
+                .unwrap_or_else(|| "unknown".to_string());
+            writeln!(out, "{} ({}): {} times", pattern_text(key), name, count).unwrap();
+        }
+        out
+    }
+}
+
+/// The opcode with its operands zeroed, so that all instructions of a kind share a key.
+fn pattern_key(opcode: u16) -> u16 {
+    match opcode >> 12 {
+        0x0 if opcode & 0xfff0 == 0x00c0 => 0x00c0,
+        0x0 if opcode & 0xff00 == 0x0000 => opcode,
+        0x0 => 0x0000,
+        0x5 | 0x8 | 0x9 => opcode & 0xf00f,
+        0xe | 0xf => opcode & 0xf0ff,
+        _ => opcode & 0xf000,
+    }
+}
+
+/// The usual notation of an instruction kind, e.g. `8XY4` or `FX1E`.
+fn pattern_text(key: u16) -> String {
+    match key >> 12 {
+        0x0 if key == 0x00c0 => "00CN".to_string(),
+        0x0 if key == 0x0000 => "0NNN".to_string(),
+        0x0 => format!("{:04X}", key),
+        0x1 | 0x2 | 0xa | 0xb => format!("{:X}NNN", key >> 12),
+        0x3 | 0x4 | 0x6 | 0x7 | 0xc => format!("{:X}XNN", key >> 12),
+        0x5 | 0x8 | 0x9 => format!("{:X}XY{:X}", key >> 12, key & 0xf),
+        0xd => "DXYN".to_string(),
+        _ => format!("{:X}X{:02X}", key >> 12, key & 0xff),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary() {
+        let mut stats = OpcodeStats::default();
+        for opcode in [0x8124, 0x8ab4, 0x8016, 0x00e0, 0xf21e, 0xd015, 0x8564] {
+            stats.record(opcode);
+        }
+        assert_eq!(stats.by_nibble[0x8], 4);
+        assert_eq!(
+            stats.summary(),
+            "8XY4 (add): 3 times\n\
+             00E0 (cls): 1 times\n\
+             8XY6 (shr): 1 times\n\
+             DXYN (drw): 1 times\n\
+             FX1E (add): 1 times\n"
+        );
+    }
+}
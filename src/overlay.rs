@@ -0,0 +1,192 @@
+use chip8_core::{Chip8State, disassemble, listing};
+use sdl3::pixels::Color;
+use sdl3::render::{FRect, WindowCanvas};
+
+/// Size in pixels of a character drawn by `draw_debug_text`.
+const CHAR_SIZE: f32 = 8.0;
+
+/// Space around the text of a box.
+const PADDING: f32 = 4.0;
+
+/// Bytes shown by a memory view.
+const MEMORY_VIEW_SIZE: usize = 64;
+
+/// Bytes per line of a memory view.
+const MEMORY_VIEW_COLUMNS: usize = 16;
+
+/// Characters before the first byte of a memory view line.
+const ADDRESS_COLUMNS: usize = 5;
+
+/// Instructions shown by the disassembly view.
+const DISASSEMBLY_VIEW_LEN: usize = 10;
+
+/// Registers, timers and stack depth in a few short lines.
+pub fn hud_lines(state: &Chip8State) -> Vec<String> {
+    let regs = |range: std::ops::Range<usize>| {
+        state.v[range]
+            .iter()
+            .map(|value| format!("{:02X}", value))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    vec![
+        format!("PC {:04X}  I {:04X}", state.pc, state.i),
+        format!("V0 {}", regs(0..8)),
+        format!("V8 {}", regs(8..16)),
+        format!(
+            "DT {:02X}  ST {:02X}  SP {}",
+            state.delay_timer,
+            state.sound_timer,
+            state.stack.len()
+        ),
+    ]
+}
+
+/// Part of a text box drawn in a different color: row, first column and number of characters.
+pub type Highlight = (usize, usize, usize);
+
+/// Width and height of a box holding `lines`.
+pub fn text_box_size(lines: &[String]) -> (f32, f32) {
+    let columns = lines.iter().map(|line| line.len()).max().unwrap_or(0);
+    (
+        columns as f32 * CHAR_SIZE + 2.0 * PADDING,
+        lines.len() as f32 * CHAR_SIZE + 2.0 * PADDING,
+    )
+}
+
+/// Draw lines of text on a dark box whose bottom left corner is at (x, y).
+pub fn draw_text_box(
+    canvas: &mut WindowCanvas,
+    lines: &[String],
+    highlights: &[Highlight],
+    x: f32,
+    y: f32,
+) {
+    let (width, height) = text_box_size(lines);
+    let top = y - height;
+
+    canvas.set_draw_color(Color::RGB(0, 0, 0));
+    canvas.fill_rect(FRect::new(x, top, width, height)).unwrap();
+    canvas.set_draw_color(Color::RGB(90, 90, 220));
+    for (row, column, len) in highlights {
+        canvas
+            .fill_rect(FRect::new(
+                x + PADDING + *column as f32 * CHAR_SIZE,
+                top + PADDING + *row as f32 * CHAR_SIZE,
+                *len as f32 * CHAR_SIZE,
+                CHAR_SIZE,
+            ))
+            .unwrap();
+    }
+    canvas.set_draw_color(Color::RGB(165, 165, 165));
+    for (row, line) in lines.iter().enumerate() {
+        canvas
+            .draw_debug_text(line, (x + PADDING, top + PADDING + row as f32 * CHAR_SIZE))
+            .unwrap();
+    }
+}
+
+/// Hex dump of the ram around `addr`, titled with `name`, with the `len` bytes at `addr`
+/// highlighted.
+pub fn memory_view(ram: &[u8], name: &str, addr: u16, len: usize) -> (Vec<String>, Vec<Highlight>) {
+    let addr = addr as usize % ram.len();
+    // Whole lines, roughly centered on addr
+    let start = (addr.saturating_sub(MEMORY_VIEW_SIZE / 2) / MEMORY_VIEW_COLUMNS
+        * MEMORY_VIEW_COLUMNS)
+        .min(ram.len() - MEMORY_VIEW_SIZE);
+
+    let mut lines = vec![format!("{} {:04X}", name, addr)];
+    for line_start in (start..start + MEMORY_VIEW_SIZE).step_by(MEMORY_VIEW_COLUMNS) {
+        let bytes: Vec<String> = ram[line_start..line_start + MEMORY_VIEW_COLUMNS]
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect();
+        lines.push(format!("{:04X} {}", line_start, bytes.join(" ")));
+    }
+
+    let highlights = (addr..(addr + len).min(start + MEMORY_VIEW_SIZE))
+        .map(|byte_addr| {
+            let offset = byte_addr - start;
+            (
+                1 + offset / MEMORY_VIEW_COLUMNS,
+                ADDRESS_COLUMNS + offset % MEMORY_VIEW_COLUMNS * 3,
+                2,
+            )
+        })
+        .collect();
+    (lines, highlights)
+}
+
+/// The instructions from pc, with the next one to execute highlighted.
+pub fn disassembly_view(state: &Chip8State) -> (Vec<String>, Vec<Highlight>) {
+    let mut lines = vec![];
+    let mut highlights = vec![];
+    for (row, (addr, opcode)) in listing(&state.ram, state.pc, DISASSEMBLY_VIEW_LEN)
+        .into_iter()
+        .enumerate()
+    {
+        let line = format!("{:04X} {:04X} {}", addr, opcode, disassemble(opcode));
+        if addr == state.pc {
+            highlights.push((row, 0, line.len()));
+        }
+        lines.push(line);
+    }
+    (lines, highlights)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8_core::Quirks;
+
+    #[test]
+    fn test_hud_lines() {
+        let mut state = Chip8State::new(&[], Quirks::default()).unwrap();
+        state.v[0x1] = 0xab;
+        state.v[0xf] = 0x01;
+        state.sound_timer = 0x10;
+        let lines = hud_lines(&state);
+        assert_eq!(lines[0], "PC 0200  I 0000");
+        assert_eq!(lines[1], "V0 00 AB 00 00 00 00 00 00");
+        assert_eq!(lines[2], "V8 00 00 00 00 00 00 00 01");
+        assert_eq!(lines[3], "DT 00  ST 10  SP 0");
+    }
+
+    #[test]
+    fn test_disassembly_view() {
+        let state = Chip8State::new(&[0x60, 0x12, 0x12, 0x00], Quirks::default()).unwrap();
+        let (lines, highlights) = disassembly_view(&state);
+        assert_eq!(lines.len(), DISASSEMBLY_VIEW_LEN);
+        assert_eq!(lines[0], "0200 6012 LD V0, 0x12");
+        assert_eq!(lines[1], "0202 1200 JP 0x200");
+        assert_eq!(highlights, [(0, 0, lines[0].len())]);
+
+        // Near the end of ram, pc is further down
+        let mut state = state;
+        state.set_pc(0xffe).unwrap();
+        let (lines, highlights) = disassembly_view(&state);
+        assert!(lines[9].starts_with("0FFE"));
+        assert_eq!(highlights[0].0, 9);
+    }
+
+    #[test]
+    fn test_memory_view() {
+        use chip8_core::RAM_SIZE;
+
+        let mut ram = [0; RAM_SIZE];
+        ram[0x250] = 0xab;
+        let (lines, highlights) = memory_view(&ram, "I", 0x250, 1);
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0], "I 0250");
+        assert!(lines[1].starts_with("0230 00 00"));
+        assert_eq!(&lines[3][..8], "0250 AB ");
+        assert_eq!(highlights, [(3, 5, 2)]);
+
+        // Stays within ram
+        let (lines, highlights) = memory_view(&ram, "PC", 0xffe, 2);
+        assert!(lines[1].starts_with("0FC0"));
+        assert_eq!(highlights, [(4, 5 + 14 * 3, 2), (4, 5 + 15 * 3, 2)]);
+        let (lines, _) = memory_view(&ram, "PC", 0x002, 2);
+        assert!(lines[1].starts_with("0000"));
+    }
+}
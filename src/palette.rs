@@ -0,0 +1,102 @@
+use chip8_core::PixelPalette;
+use sdl3::pixels::Color;
+
+/// Colors used to draw the display.
+#[derive(Debug, Clone, Copy)]
+pub struct Palette {
+    /// Indexed by plane value: background, first plane (the foreground of single plane roms),
+    /// second plane only and both planes (XO-CHIP).
+    pub colors: [Color; 4],
+    pub grid: Color,
+}
+
+impl Palette {
+    /// Color of a pixel from its plane value (0b00 to 0b11).
+    pub fn color(&self, value: u8) -> Color {
+        self.colors[value as usize & 0b11]
+    }
+
+    pub fn bg(&self) -> Color {
+        self.colors[0]
+    }
+}
+
+impl PixelPalette for Palette {
+    fn rgba(&self, value: u8) -> [u8; 4] {
+        let color = self.color(value);
+        [color.r, color.g, color.b, color.a]
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Palette {
+            colors: [
+                Color::RGB(10, 10, 10),
+                Color::RGB(255, 255, 190),
+                Color::RGB(200, 60, 40),
+                Color::RGB(90, 90, 220),
+            ],
+            grid: Color::RGB(50, 50, 50),
+        }
+    }
+}
+
+/// Mix from `from` at t = 0 to `to` at t = 1.
+pub fn blend(from: Color, to: Color, t: f32) -> Color {
+    let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+    Color::RGB(mix(from.r, to.r), mix(from.g, to.g), mix(from.b, to.b))
+}
+
+/// Parse a `RRGGBB` or `#RRGGBB` hex string.
+pub fn parse_hex_color(hex: &str) -> Result<Color, String> {
+    let digits = hex.strip_prefix('#').unwrap_or(hex);
+    if digits.len() != 6 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("Invalid color '{}', expected RRGGBB hex", hex));
+    }
+    let channel = |idx: usize| u8::from_str_radix(&digits[idx..idx + 2], 16).unwrap();
+    Ok(Color::RGB(channel(0), channel(2), channel(4)))
+}
+
+/// Parse the four comma separated colors of `--xo-palette`, in plane value order.
+pub fn parse_xo_palette(text: &str) -> Result<[Color; 4], String> {
+    let colors = text
+        .split(',')
+        .map(|hex| parse_hex_color(hex.trim()))
+        .collect::<Result<Vec<_>, _>>()?;
+    colors.try_into().map_err(|colors: Vec<Color>| {
+        format!(
+            "Expected 4 colors in the XO-CHIP palette, got {}",
+            colors.len()
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_hex_color() {
+        assert_eq!(parse_hex_color("ffcc00"), Ok(Color::RGB(255, 204, 0)));
+        assert_eq!(parse_hex_color("#000033"), Ok(Color::RGB(0, 0, 51)));
+        assert!(parse_hex_color("fc0").is_err());
+        assert!(parse_hex_color("gg0000").is_err());
+    }
+
+    #[test]
+    fn test_parse_xo_palette() {
+        let colors = parse_xo_palette("000000,ffffff,#ff0000, 0000ff").unwrap();
+        assert_eq!(colors[2], Color::RGB(255, 0, 0));
+        assert_eq!(colors[3], Color::RGB(0, 0, 255));
+        assert!(parse_xo_palette("000000,ffffff,ff0000").is_err());
+        assert!(parse_xo_palette("000000,ffffff,ff0000,0000ff,00ff00").is_err());
+        assert!(parse_xo_palette("000000,ffffff,red,0000ff").is_err());
+
+        let palette = Palette {
+            colors,
+            ..Palette::default()
+        };
+        assert_eq!(palette.color(0b01), Color::RGB(255, 255, 255));
+    }
+}
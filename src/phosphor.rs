@@ -0,0 +1,90 @@
+use crate::palette::{Palette, blend};
+use chip8_core::Chip8Display;
+use sdl3::pixels::Color;
+
+/// Brightness kept by an unlit pixel from one frame to the next.
+const DECAY: f32 = 0.5;
+
+/// Below this brightness a pixel is drawn as background.
+const THRESHOLD: f32 = 0.05;
+
+/// Fades pixels out over a few frames after they turn off, like the phosphor of a CRT,
+/// which hides most of the flicker of erase-then-redraw sprite movement.
+#[derive(Debug, Default)]
+pub struct Phosphor {
+    /// Per pixel, 1 when lit and decaying towards 0 after.
+    brightness: Vec<f32>,
+    /// Plane value of each pixel the last time it was lit.
+    values: Vec<u8>,
+}
+
+impl Phosphor {
+    /// Advance by one rendered frame.
+    pub fn update(&mut self, display: &Chip8Display) {
+        let len = display.width() * display.height();
+        if self.brightness.len() != len {
+            // Resolution changed, nothing to fade from
+            self.brightness = vec![0.0; len];
+            self.values = vec![0; len];
+        }
+        for i in 0..len {
+            let value = display.value(i);
+            if value != 0 {
+                self.brightness[i] = 1.0;
+                self.values[i] = value;
+            } else {
+                self.brightness[i] *= DECAY;
+            }
+        }
+    }
+
+    /// Color of the unlit pixel `i` while it fades, or None once it has faded out.
+    pub fn fading_color(&self, i: usize, palette: &Palette) -> Option<Color> {
+        let brightness = *self.brightness.get(i)?;
+        if !(THRESHOLD..1.0).contains(&brightness) {
+            return None;
+        }
+        Some(blend(
+            palette.bg(),
+            palette.color(self.values[i]),
+            brightness,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fade() {
+        let mut palette = Palette::default();
+        palette.colors[0] = Color::RGB(0, 0, 0);
+        palette.colors[1] = Color::RGB(200, 100, 0);
+        let mut display = Chip8Display::new();
+        let mut phosphor = Phosphor::default();
+
+        *display.get_mut(0, 0, 0).unwrap() = true;
+        phosphor.update(&display);
+        // Lit pixels are drawn normally
+        assert_eq!(phosphor.fading_color(0, &palette), None);
+
+        *display.get_mut(0, 0, 0).unwrap() = false;
+        phosphor.update(&display);
+        assert_eq!(
+            phosphor.fading_color(0, &palette),
+            Some(Color::RGB(100, 50, 0))
+        );
+        phosphor.update(&display);
+        assert_eq!(
+            phosphor.fading_color(0, &palette),
+            Some(Color::RGB(50, 25, 0))
+        );
+        for _ in 0..3 {
+            phosphor.update(&display);
+        }
+        assert_eq!(phosphor.fading_color(0, &palette), None);
+        // Never lit
+        assert_eq!(phosphor.fading_color(1, &palette), None);
+    }
+}
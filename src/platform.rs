@@ -0,0 +1,74 @@
+use crate::quirks::Quirks;
+use crate::state::{PROGRAM_START, RAM_SIZE, XO_RAM_SIZE};
+
+/// Machine a rom was written for, which decides where it is loaded and how it behaves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Platform {
+    /// The original interpreter on the RCA COSMAC VIP.
+    #[default]
+    Cosmac,
+    /// The ETI-660 loads programs at 0x600.
+    Eti660,
+    /// SUPER-CHIP 1.1 on the HP48 calculators.
+    Schip,
+    /// XO-CHIP, as implemented by Octo.
+    XoChip,
+}
+
+impl Platform {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "cosmac" => Ok(Platform::Cosmac),
+            "eti660" => Ok(Platform::Eti660),
+            "schip" => Ok(Platform::Schip),
+            "xochip" => Ok(Platform::XoChip),
+            _ => Err(format!(
+                "Unknown platform '{}', expected cosmac, eti660, schip or xochip",
+                name
+            )),
+        }
+    }
+
+    /// Address where roms are loaded and execution starts.
+    pub fn load_address(self) -> u16 {
+        match self {
+            Platform::Eti660 => 0x600,
+            _ => PROGRAM_START as u16,
+        }
+    }
+
+    /// Bytes of ram, 64KB on XO-CHIP.
+    pub fn ram_size(self) -> usize {
+        match self {
+            Platform::XoChip => XO_RAM_SIZE,
+            _ => RAM_SIZE,
+        }
+    }
+
+    /// Behavior of the platform's interpreter.
+    pub fn quirks(self) -> Quirks {
+        match self {
+            // The ETI-660 interpreter is a port of the VIP one
+            Platform::Cosmac | Platform::Eti660 => Quirks::COSMAC,
+            Platform::Schip => Quirks::SCHIP,
+            Platform::XoChip => Quirks::XOCHIP,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_platforms() {
+        assert_eq!(Platform::from_name("eti660"), Ok(Platform::Eti660));
+        assert!(Platform::from_name("vip").is_err());
+        assert_eq!(Platform::Cosmac.load_address(), 0x200);
+        assert_eq!(Platform::Eti660.load_address(), 0x600);
+        assert_eq!(Platform::Eti660.quirks(), Quirks::COSMAC);
+        assert_eq!(Platform::XoChip.quirks(), Quirks::XOCHIP);
+        assert_eq!(Platform::Schip.ram_size(), 4096);
+        assert_eq!(Platform::XoChip.ram_size(), 65536);
+    }
+}
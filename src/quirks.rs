@@ -0,0 +1,179 @@
+/// Behaviors that differ between chip8 interpreters.
+/// See the [quirks test](https://github.com/Timendus/chip8-test-suite?tab=readme-ov-file#quirks-test).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// 0x8xy1, 0x8xy2 and 0x8xy3 reset vf to 0.
+    pub vf_reset: bool,
+    /// How 0xfx55 and 0xfx65 move the index register once done.
+    pub memory_increment: IndexIncrement,
+    /// 0x8xy6 and 0x8xye shift vx in place instead of shifting vy into vx.
+    pub shift_vx_in_place: bool,
+    /// 0xbnnn jumps to xnn + vx instead of nnn + v0.
+    pub jump_with_vx: bool,
+    /// 0xdxyn waits for the next display refresh before drawing.
+    pub display_wait: bool,
+    /// Sprites are clipped at the edges of the display instead of wrapping around.
+    pub clip_sprites: bool,
+    /// 0xfx1e sets vf to 1 when the index register overflows past 0x0fff (Amiga interpreter).
+    pub index_overflow_vf: bool,
+    /// 0xfx0a returns as soon as a key goes down, instead of waiting for its release.
+    pub key_wait_on_press: bool,
+    /// 0xdxyn, 0xfx33, 0xfx55 and 0xfx65 wrap around to the start of ram past its end, instead
+    /// of halting with an error.
+    pub wrap_memory: bool,
+    /// In high resolution, 0xdxyn sets vf to the number of sprite rows that collided or were
+    /// clipped off the bottom of the display, instead of 1 on any collision (SUPER-CHIP).
+    pub count_collided_rows: bool,
+    /// 0x00fe and 0x00ff clear the display, instead of scaling the picture to the new resolution.
+    pub clear_on_resolution_change: bool,
+}
+
+/// Amount added to the index register by 0xfx55 and 0xfx65.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexIncrement {
+    /// I is left unchanged (SUPER-CHIP 1.1).
+    Unchanged,
+    /// I += x, pointing at the last register copied (SUPER-CHIP 1.0).
+    X,
+    /// I += x + 1, pointing past the last register copied (COSMAC VIP).
+    XPlusOne,
+}
+
+impl IndexIncrement {
+    /// Variant by name: none, x or x+1.
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "none" => Ok(IndexIncrement::Unchanged),
+            "x" => Ok(IndexIncrement::X),
+            "x+1" => Ok(IndexIncrement::XPlusOne),
+            _ => Err(format!(
+                "Unknown index increment '{}', expected none, x or x+1",
+                name
+            )),
+        }
+    }
+
+    /// Amount added to the index register after copying v0 to vx.
+    pub fn amount(self, x: usize) -> u16 {
+        match self {
+            IndexIncrement::Unchanged => 0,
+            IndexIncrement::X => x as u16,
+            IndexIncrement::XPlusOne => x as u16 + 1,
+        }
+    }
+}
+
+impl Quirks {
+    /// Behavior of the original COSMAC VIP interpreter.
+    pub const COSMAC: Quirks = Quirks {
+        vf_reset: true,
+        memory_increment: IndexIncrement::XPlusOne,
+        shift_vx_in_place: false,
+        jump_with_vx: false,
+        display_wait: true,
+        clip_sprites: true,
+        index_overflow_vf: false,
+        key_wait_on_press: false,
+        wrap_memory: false,
+        count_collided_rows: false,
+        clear_on_resolution_change: true,
+    };
+
+    /// Behavior of the SUPER-CHIP 1.1 interpreter on the HP48.
+    pub const SCHIP: Quirks = Quirks {
+        vf_reset: false,
+        memory_increment: IndexIncrement::Unchanged,
+        shift_vx_in_place: true,
+        jump_with_vx: true,
+        display_wait: false,
+        clip_sprites: true,
+        index_overflow_vf: false,
+        key_wait_on_press: false,
+        wrap_memory: false,
+        count_collided_rows: true,
+        clear_on_resolution_change: true,
+    };
+
+    /// Behavior of XO-CHIP, as implemented by Octo.
+    pub const XOCHIP: Quirks = Quirks {
+        vf_reset: false,
+        memory_increment: IndexIncrement::XPlusOne,
+        shift_vx_in_place: false,
+        jump_with_vx: false,
+        display_wait: false,
+        clip_sprites: false,
+        index_overflow_vf: false,
+        key_wait_on_press: false,
+        wrap_memory: false,
+        count_collided_rows: false,
+        clear_on_resolution_change: true,
+    };
+
+    /// The combination used by roms written in Octo with its default settings.
+    pub const OCTO: Quirks = Quirks {
+        vf_reset: false,
+        memory_increment: IndexIncrement::Unchanged,
+        shift_vx_in_place: true,
+        jump_with_vx: false,
+        display_wait: false,
+        clip_sprites: true,
+        index_overflow_vf: false,
+        key_wait_on_press: false,
+        wrap_memory: false,
+        count_collided_rows: false,
+        clear_on_resolution_change: true,
+    };
+
+    /// Preset by name: cosmac, schip, xochip or octo.
+    pub fn from_profile(name: &str) -> Result<Self, String> {
+        match name {
+            "cosmac" => Ok(Quirks::COSMAC),
+            "schip" => Ok(Quirks::SCHIP),
+            "xochip" => Ok(Quirks::XOCHIP),
+            "octo" => Ok(Quirks::OCTO),
+            _ => Err(format!(
+                "Unknown profile '{}', expected cosmac, schip, xochip or octo",
+                name
+            )),
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks {
+            vf_reset: false,
+            memory_increment: IndexIncrement::Unchanged,
+            shift_vx_in_place: true,
+            jump_with_vx: false,
+            display_wait: true,
+            clip_sprites: true,
+            index_overflow_vf: false,
+            key_wait_on_press: false,
+            wrap_memory: false,
+            count_collided_rows: true,
+            clear_on_resolution_change: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_profiles() {
+        assert_eq!(Quirks::from_profile("octo"), Ok(Quirks::OCTO));
+        assert_eq!(Quirks::from_profile("schip"), Ok(Quirks::SCHIP));
+        assert!(Quirks::from_profile("vip").is_err());
+    }
+
+    #[test]
+    fn test_index_increment() {
+        assert_eq!(IndexIncrement::from_name("x"), Ok(IndexIncrement::X));
+        assert!(IndexIncrement::from_name("2").is_err());
+        assert_eq!(IndexIncrement::Unchanged.amount(0xf), 0);
+        assert_eq!(IndexIncrement::X.amount(0xf), 0xf);
+        assert_eq!(IndexIncrement::XPlusOne.amount(0xf), 0x10);
+    }
+}
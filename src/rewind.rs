@@ -0,0 +1,95 @@
+use crate::state::Chip8State;
+use std::collections::VecDeque;
+
+/// Recent states of the machine, serialized every few frames, to go back in time.
+///
+/// A snapshot is a save state, around 20KB with the hires display, so memory use is bounded by
+/// `capacity` times that.
+#[derive(Debug, Clone)]
+pub struct Rewind {
+    snapshots: VecDeque<Vec<u8>>,
+    capacity: usize,
+    /// Frames between two snapshots.
+    interval: usize,
+    /// Frames since the last snapshot.
+    frames: usize,
+}
+
+impl Rewind {
+    pub fn new(capacity: usize, interval: usize) -> Self {
+        Rewind {
+            snapshots: VecDeque::with_capacity(capacity),
+            capacity,
+            interval: interval.max(1),
+            frames: 0,
+        }
+    }
+
+    /// Call once per frame, takes a snapshot every `interval` frames and forgets the oldest one
+    /// when full.
+    pub fn record(&mut self, state: &Chip8State) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.frames += 1;
+        if self.frames < self.interval {
+            return;
+        }
+        self.frames = 0;
+        if self.snapshots.len() == self.capacity {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(state.to_bytes());
+    }
+
+    /// The most recent snapshot, removed from the buffer so that the next call goes further back.
+    /// None once the buffer is empty.
+    pub fn step_back(&mut self) -> Option<Chip8State> {
+        self.frames = 0;
+        let snapshot = self.snapshots.pop_back()?;
+        Some(Chip8State::from_bytes(&snapshot).expect("snapshots are valid save states"))
+    }
+
+    /// Forget every snapshot, e.g. when another rom is loaded.
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.frames = 0;
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quirks::Quirks;
+
+    #[test]
+    fn test_rewind() {
+        let mut state = Chip8State::new(&[], Quirks::default()).unwrap();
+        let mut rewind = Rewind::new(3, 2);
+        for frame in 0..10 {
+            state.v[0] = frame;
+            rewind.record(&state);
+        }
+        // Every other frame, only the last three are kept
+        assert_eq!(rewind.len(), 3);
+        assert_eq!(rewind.step_back().unwrap().v[0], 9);
+        assert_eq!(rewind.step_back().unwrap().v[0], 7);
+        assert_eq!(rewind.step_back().unwrap().v[0], 5);
+        assert!(rewind.step_back().is_none());
+
+        // Recording resumes from the rewound point
+        rewind.record(&state);
+        rewind.record(&state);
+        assert_eq!(rewind.len(), 1);
+        rewind.clear();
+        assert!(rewind.is_empty());
+    }
+}
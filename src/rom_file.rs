@@ -0,0 +1,53 @@
+use flate2::read::GzDecoder;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// First bytes of every gzip file.
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Read a rom file, decompressing it if it is gzipped, or stdin for `-`.
+pub fn load_rom(path: &str) -> io::Result<Vec<u8>> {
+    let bytes = if path == "-" {
+        let mut bytes = vec![];
+        io::stdin().read_to_end(&mut bytes)?;
+        bytes
+    } else {
+        std::fs::read(path)?
+    };
+    let gzipped = Path::new(path).extension().is_some_and(|ext| ext == "gz");
+    decompress(bytes, gzipped)
+}
+
+/// The bytes of a rom, decompressed if `gzipped` says so or they start like a gzip file.
+/// Anything else passes through unchanged.
+fn decompress(bytes: Vec<u8>, gzipped: bool) -> io::Result<Vec<u8>> {
+    if !gzipped && !bytes.starts_with(&GZIP_MAGIC) {
+        return Ok(bytes);
+    }
+    let mut rom = vec![];
+    GzDecoder::new(bytes.as_slice()).read_to_end(&mut rom)?;
+    Ok(rom)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+
+    #[test]
+    fn test_decompress() {
+        let rom = vec![0x00, 0xe0, 0x12, 0x00];
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(&rom).unwrap();
+        let gzipped = encoder.finish().unwrap();
+
+        assert_eq!(decompress(gzipped.clone(), true).unwrap(), rom);
+        // Detected from the magic bytes without the extension
+        assert_eq!(decompress(gzipped, false).unwrap(), rom);
+        assert_eq!(decompress(rom.clone(), false).unwrap(), rom);
+        // A .gz file that isn't gzipped
+        assert!(decompress(rom, true).is_err());
+    }
+}
@@ -0,0 +1,301 @@
+use crate::display::{Chip8Display, NUM_PLANES};
+use crate::quirks::{IndexIncrement, Quirks};
+use crate::stack::Chip8Stack;
+use crate::state::{Chip8State, RAM_SIZE, RPL_FLAGS, XO_RAM_SIZE};
+use std::io;
+use std::path::Path;
+
+/// Identifies save state files.
+const MAGIC: &[u8; 4] = b"CH8S";
+
+/// Bumped whenever the layout below changes; older states are rejected.
+const VERSION: u8 = 15;
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Reads the fields back in the order they were written.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.bytes.len() < len {
+            return Err(invalid("Truncated save state"));
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn bool(&mut self) -> io::Result<bool> {
+        Ok(self.u8()? != 0)
+    }
+
+    fn u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+}
+
+impl Chip8State {
+    /// Serialize the whole machine except the rng.
+    ///
+    /// Layout (little endian): magic, version, ram size, ram, pc, i, v, delay timer, sound timer,
+    /// stack capacity, stack length, stack values, low resolution display width and height,
+    /// display width, display height,
+    /// selected planes, planes (one byte per pixel), quirks, audio pattern flag, audio pattern,
+    /// pitch, timer accumulator, rpl flags, halted, blank interrupt, keys seen, number of keys
+    /// queued by 0xfx0a, queued keys.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+
+        out.extend_from_slice(&(self.ram.len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.ram);
+        out.extend_from_slice(&self.pc.to_le_bytes());
+        out.extend_from_slice(&self.i.to_le_bytes());
+        out.extend_from_slice(&self.v);
+        out.push(self.delay_timer);
+        out.push(self.sound_timer);
+
+        out.extend_from_slice(&(self.stack.capacity() as u16).to_le_bytes());
+        out.extend_from_slice(&(self.stack.len() as u16).to_le_bytes());
+        for value in self.stack.values() {
+            out.extend_from_slice(&value.to_le_bytes());
+        }
+
+        let (lores_width, lores_height) = self.display.lores_size();
+        out.extend_from_slice(&(lores_width as u16).to_le_bytes());
+        out.extend_from_slice(&(lores_height as u16).to_le_bytes());
+        out.extend_from_slice(&(self.display.width() as u16).to_le_bytes());
+        out.extend_from_slice(&(self.display.height() as u16).to_le_bytes());
+        out.push(self.display.selected_planes());
+        for plane in &self.display.planes {
+            out.extend(plane.iter().map(|pixel| *pixel as u8));
+        }
+
+        let quirks = &self.quirks;
+        out.extend_from_slice(&[
+            quirks.vf_reset as u8,
+            quirks.memory_increment as u8,
+            quirks.shift_vx_in_place as u8,
+            quirks.jump_with_vx as u8,
+            quirks.display_wait as u8,
+            quirks.clip_sprites as u8,
+            quirks.index_overflow_vf as u8,
+            quirks.key_wait_on_press as u8,
+            quirks.wrap_memory as u8,
+            quirks.count_collided_rows as u8,
+            quirks.clear_on_resolution_change as u8,
+        ]);
+
+        out.push(self.audio_pattern.is_some() as u8);
+        out.extend_from_slice(&self.audio_pattern.unwrap_or_default());
+        out.push(self.pitch);
+
+        out.extend_from_slice(&(self.timer_accumulator as u64).to_le_bytes());
+        out.extend_from_slice(&self.rpl);
+        out.push(self.halted as u8);
+        out.push(self.blank_interrupt as u8);
+
+        out.extend_from_slice(&self.keys_seen.to_le_bytes());
+        out.push(self.key_queue.len() as u8);
+        out.extend_from_slice(&self.key_queue);
+        out
+    }
+
+    /// Restore a machine serialized with [`Chip8State::to_bytes`], with a freshly seeded rng.
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<Chip8State> {
+        let mut reader = Reader { bytes };
+        if reader.take(MAGIC.len())? != MAGIC {
+            return Err(invalid("Not a chip8 save state"));
+        }
+        if reader.u8()? != VERSION {
+            return Err(invalid("Unsupported save state version"));
+        }
+
+        let mut state = Chip8State::new(&[], Quirks::default()).expect("empty rom always fits");
+        let ram_len = reader.u32()? as usize;
+        if !(RAM_SIZE..=XO_RAM_SIZE).contains(&ram_len) {
+            return Err(invalid("Unsupported ram size"));
+        }
+        state.ram = reader.take(ram_len)?.to_vec();
+        state.pc = reader.u16()?;
+        state.i = reader.u16()?;
+        state.v.copy_from_slice(reader.take(16)?);
+        state.delay_timer = reader.u8()?;
+        state.sound_timer = reader.u8()?;
+
+        state.stack = Chip8Stack::with_capacity(reader.u16()? as usize);
+        let stack_len = reader.u16()?;
+        for _ in 0..stack_len {
+            state
+                .stack
+                .push(reader.u16()?)
+                .map_err(|_| invalid("Stack larger than its capacity"))?;
+        }
+
+        let lores_width = reader.u16()? as usize;
+        let lores_height = reader.u16()? as usize;
+        let width = reader.u16()? as usize;
+        let height = reader.u16()? as usize;
+        let mut display = Chip8Display::with_size(lores_width, lores_height);
+        display.set_hires(width > lores_width);
+        if (width, height) != (display.width(), display.height()) {
+            return Err(invalid("Unsupported display resolution"));
+        }
+        display.select_planes(reader.u8()?);
+        for plane in 0..NUM_PLANES {
+            let pixels = reader.take(width * height)?;
+            for (pixel, byte) in display.planes[plane].iter_mut().zip(pixels) {
+                *pixel = *byte != 0;
+            }
+        }
+        state.display = display;
+
+        state.quirks = Quirks {
+            vf_reset: reader.bool()?,
+            memory_increment: match reader.u8()? {
+                0 => IndexIncrement::Unchanged,
+                1 => IndexIncrement::X,
+                2 => IndexIncrement::XPlusOne,
+                _ => return Err(invalid("Unknown index increment")),
+            },
+            shift_vx_in_place: reader.bool()?,
+            jump_with_vx: reader.bool()?,
+            display_wait: reader.bool()?,
+            clip_sprites: reader.bool()?,
+            index_overflow_vf: reader.bool()?,
+            key_wait_on_press: reader.bool()?,
+            wrap_memory: reader.bool()?,
+            count_collided_rows: reader.bool()?,
+            clear_on_resolution_change: reader.bool()?,
+        };
+
+        let has_pattern = reader.bool()?;
+        let pattern = reader.take(16)?.try_into().unwrap();
+        state.audio_pattern = has_pattern.then_some(pattern);
+        state.pitch = reader.u8()?;
+
+        state.timer_accumulator = reader.u64()? as u128;
+        state.rpl.copy_from_slice(reader.take(RPL_FLAGS)?);
+        state.halted = reader.bool()?;
+        state.blank_interrupt = reader.bool()?;
+
+        state.keys_seen = reader.u16()?;
+        let queued = reader.u8()? as usize;
+        if queued > 16 {
+            return Err(invalid("Too many queued keys"));
+        }
+        state.key_queue = reader.take(queued)?.to_vec();
+        if state.key_queue.iter().any(|key| *key > 15) {
+            return Err(invalid("Invalid pending key"));
+        }
+        Ok(state)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        std::fs::write(path, self.to_bytes())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Chip8State> {
+        Chip8State::from_bytes(&std::fs::read(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::keypad::Chip8Keypad;
+    use std::time::Duration;
+
+    #[test]
+    fn test_round_trip() {
+        // hires, v0 = 0x12, call 0x20a, draw "0" at (v0, v0) then loop
+        let rom = [
+            0x00, 0xff, 0x60, 0x12, 0x22, 0x0a, 0x00, 0x00, 0x00, 0x00, 0xa0, 0x50, 0xd0, 0x05,
+            0x12, 0x0e,
+        ];
+        let mut state = Chip8State::new(&rom, Quirks::COSMAC).unwrap();
+        let keypad = Chip8Keypad::default();
+        for _ in 0..5 {
+            state.vblank();
+            state.update(Duration::from_micros(5000), &keypad).unwrap();
+        }
+        state.sound_timer = 7;
+        state.rpl[3] = 9;
+
+        let restored = Chip8State::from_bytes(&state.to_bytes()).unwrap();
+        assert_eq!(restored.ram, state.ram);
+        assert_eq!(restored.pc, state.pc);
+        assert_eq!(restored.i, state.i);
+        assert_eq!(restored.v, state.v);
+        assert_eq!(restored.sound_timer, 7);
+        assert_eq!(restored.stack.values().collect::<Vec<_>>(), [0x206]);
+        assert!(restored.display.is_hires());
+        assert_eq!(restored.display.planes, state.display.planes);
+        assert_eq!(restored.quirks, Quirks::COSMAC);
+        assert_eq!(restored.timer_accumulator, state.timer_accumulator);
+        assert_eq!(restored.rpl, state.rpl);
+
+        state.display = Chip8Display::with_size(64, 64);
+        let restored = Chip8State::from_bytes(&state.to_bytes()).unwrap();
+        assert_eq!(restored.display.lores_size(), (64, 64));
+        assert!(!restored.display.is_hires());
+    }
+
+    #[test]
+    fn test_xochip_ram() {
+        let mut state = Chip8State::for_platform(&[], crate::Platform::XoChip).unwrap();
+        state.ram[0xfffe] = 0xab;
+        let restored = Chip8State::from_bytes(&state.to_bytes()).unwrap();
+        assert_eq!(restored.ram.len(), 0x10000);
+        assert_eq!(restored.ram[0xfffe], 0xab);
+    }
+
+    #[test]
+    fn test_blocked_draw_survives_restore() {
+        // Draw waits for the display refresh
+        let mut state = Chip8State::new(&[0xd0, 0x05], Quirks::default()).unwrap();
+        state
+            .update(Duration::ZERO, &Chip8Keypad::default())
+            .unwrap();
+        assert_eq!(state.pc, 0x200);
+
+        let mut restored = Chip8State::from_bytes(&state.to_bytes()).unwrap();
+        restored.vblank();
+        restored
+            .update(Duration::ZERO, &Chip8Keypad::default())
+            .unwrap();
+        assert_eq!(restored.pc, 0x202);
+    }
+
+    #[test]
+    fn test_invalid_data() {
+        assert!(Chip8State::from_bytes(b"nope").is_err());
+        let bytes = Chip8State::new(&[], Quirks::default()).unwrap().to_bytes();
+        assert!(Chip8State::from_bytes(&bytes[..100]).is_err());
+
+        // The number of queued keys is the last byte, followed by the keys
+        let mut bytes = bytes;
+        *bytes.last_mut().unwrap() = 1;
+        bytes.push(0x10);
+        let err = Chip8State::from_bytes(&bytes).unwrap_err();
+        assert_eq!(err.to_string(), "Invalid pending key");
+    }
+}
@@ -0,0 +1,89 @@
+use crate::error::Chip8Error;
+
+/// Maximum depth of the LIFO stack
+pub const STACK_CAPACITY: usize = 64;
+
+#[derive(Debug)]
+pub struct Chip8Stack {
+    buffer: [u16; STACK_CAPACITY],
+    top: usize,
+    /// Depth limit, at most STACK_CAPACITY.
+    capacity: usize,
+}
+
+impl Chip8Stack {
+    pub fn new() -> Self {
+        Self::with_capacity(STACK_CAPACITY)
+    }
+
+    /// Stack limited to `capacity` levels, e.g. 12 to emulate the COSMAC VIP.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Chip8Stack {
+            buffer: [0; _],
+            top: 0,
+            capacity: capacity.min(STACK_CAPACITY),
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn push(&mut self, value: u16) -> Result<(), Chip8Error> {
+        if self.top == self.capacity {
+            return Err(Chip8Error::StackOverflow);
+        }
+        self.buffer[self.top] = value;
+        self.top += 1;
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> Result<u16, Chip8Error> {
+        if self.top == 0 {
+            return Err(Chip8Error::StackUnderflow);
+        }
+        self.top -= 1;
+        Ok(self.buffer[self.top])
+    }
+    /// Number of values currently on the stack.
+    pub fn len(&self) -> usize {
+        self.top
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.top == 0
+    }
+
+    /// Values on the stack, from the bottom to the top.
+    pub fn values(&self) -> impl Iterator<Item = u16> + '_ {
+        self.buffer[..self.top].iter().copied()
+    }
+}
+
+impl Default for Chip8Stack {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_overflow() {
+        let mut stack = Chip8Stack::with_capacity(12);
+        for i in 0..12 {
+            assert_eq!(stack.push(i), Ok(()));
+        }
+        assert_eq!(stack.push(12), Err(Chip8Error::StackOverflow));
+        assert_eq!(stack.len(), 12);
+        assert_eq!(stack.pop(), Ok(11));
+    }
+
+    #[test]
+    fn test_underflow() {
+        let mut stack = Chip8Stack::new();
+        assert_eq!(stack.pop(), Err(Chip8Error::StackUnderflow));
+    }
+}
@@ -0,0 +1,1699 @@
+use crate::display::{Chip8Display, PixelPalette};
+use crate::error::Chip8Error;
+use crate::font;
+use crate::keypad::Chip8Keypad;
+use crate::opcode_stats::OpcodeStats;
+use crate::platform::Platform;
+use crate::quirks::Quirks;
+use crate::stack::Chip8Stack;
+use rand::rngs::StdRng;
+use rand::{RngExt, SeedableRng};
+use std::fmt;
+use std::ops::Range;
+use std::time::Duration;
+
+/// The delay and sound timers count down at 60Hz.
+const TIMER_FREQUENCY_HZ: u128 = 60;
+
+const MICROS_PER_SECOND: u128 = 1_000_000;
+
+/// Clock of [`Chip8State::update_fixed`]: 10 cycles per timer tick, so that timers count down
+/// exactly with the cycles.
+pub const FIXED_CLOCK_HZ: u32 = 600;
+
+pub const RAM_SIZE: usize = 4096;
+
+/// XO-CHIP extends ram to the whole 16-bit address space.
+pub const XO_RAM_SIZE: usize = 0x10000;
+
+/// Address where roms are loaded and execution starts by default.
+pub const PROGRAM_START: usize = 0x200;
+
+/// Roms may be loaded anywhere after the fonts.
+const MIN_LOAD_ADDRESS: usize = font::BIG_FONT_ADDRESS + font::BIG_FONT.len();
+
+/// Largest rom that fits in ram.
+pub const MAX_ROM_SIZE: usize = RAM_SIZE - PROGRAM_START;
+
+/// Number of SUPER-CHIP RPL user flags, extended from 8 to 16 by XO-CHIP.
+pub const RPL_FLAGS: usize = 16;
+
+/// XO-CHIP pitch at which the audio pattern plays at 4000 bits per second.
+const DEFAULT_PITCH: u8 = 64;
+
+/// Called with the address and opcode of each instruction once it ran, see
+/// [`Chip8State::set_exec_hook`].
+pub struct ExecHook(Box<dyn FnMut(u16, u16)>);
+
+impl fmt::Debug for ExecHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ExecHook")
+    }
+}
+
+/// A write by the program into [`Chip8State::write_protect`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProtectedWrite {
+    /// Address of the instruction that wrote.
+    pub pc: u16,
+    pub addr: u16,
+    pub old: u8,
+    pub new: u8,
+}
+
+#[derive(Debug)]
+pub struct Chip8State {
+    /// [`RAM_SIZE`] bytes, or [`XO_RAM_SIZE`] on XO-CHIP.
+    pub ram: Vec<u8>,
+    /// Program counter.
+    pub pc: u16,
+    /// Index register.
+    pub i: u16,
+    /// General purpose registers.
+    pub v: [u8; 16],
+    pub delay_timer: u8,
+    pub sound_timer: u8,
+    pub stack: Chip8Stack,
+    pub display: Chip8Display,
+    /// Seeded from the system unless [`Chip8State::seed_rng`] is called.
+    rng: StdRng,
+    pub quirks: Quirks,
+    /// XO-CHIP 1-bit audio pattern played while the sound timer runs, if one was loaded.
+    pub audio_pattern: Option<[u8; 16]>,
+    /// XO-CHIP audio pitch, see [`Chip8State::pattern_rate`].
+    pub pitch: u8,
+    /// Observes execution for tracers, profilers and the like, when installed.
+    pub exec_hook: Option<ExecHook>,
+    /// Executed instruction counts, only recorded when enabled.
+    pub opcode_stats: Option<OpcodeStats>,
+    /// Fail with [`Chip8Error::PcOutOfBounds`] rather than execute an instruction below this
+    /// address, e.g. font data after a bad jump. Unchecked by default.
+    pub min_pc: Option<u16>,
+    /// Fail with [`Chip8Error::MisalignedPc`] rather than fetch an instruction at an odd address,
+    /// usually after a bad jump. Off by default, as some programs do run at odd addresses.
+    pub aligned_pc: bool,
+    /// Ram addresses whose writes by the program are recorded in `protected_writes`, to catch
+    /// one overwriting its own code. Unchecked by default.
+    pub write_protect: Option<Range<usize>>,
+    /// Writes into `write_protect`, until the frontend takes them.
+    pub protected_writes: Vec<ProtectedWrite>,
+    /// Time not yet turned into timer ticks, in microseconds multiplied by the timer frequency,
+    /// so that a tick is exactly one second in this unit and the 60Hz period doesn't drift.
+    pub(crate) timer_accumulator: u128,
+    /// SUPER-CHIP RPL user flags, which frontends may keep between runs to save high scores.
+    pub rpl: [u8; RPL_FLAGS],
+    /// Set by 0x00fd, after which no instruction runs anymore.
+    pub halted: bool,
+    /// Set by the frontend when a frame was just presented, consumed by the next update
+    pub(crate) blank_interrupt: bool,
+    /// Bit k set if key k was held during the previous cycle. Kept here rather than read from
+    /// the keypad so that a press is seen once however often the frontend polls input.
+    pub(crate) keys_seen: u16,
+    /// Keys that went down during 0xfx0a waits, in the order they did, lowest first when several
+    /// went down in the same cycle. Each is returned by a wait once it comes back up, so that
+    /// keys pressed together between two polls of the keypad are all registered.
+    pub(crate) key_queue: Vec<u8>,
+}
+
+impl Chip8State {
+    /// Power on a machine with `rom` loaded at 0x200.
+    pub fn new(rom: &[u8], quirks: Quirks) -> Result<Self, Chip8Error> {
+        Self::with_load_address(rom, quirks, PROGRAM_START as u16)
+    }
+
+    /// Like [`Chip8State::new`], with `0xcxnn` drawing the same numbers on every run.
+    pub fn new_seeded(rom: &[u8], quirks: Quirks, seed: u64) -> Result<Self, Chip8Error> {
+        let mut state = Self::new(rom, quirks)?;
+        state.seed_rng(seed);
+        Ok(state)
+    }
+
+    /// Power on the given platform, with `rom` loaded where it expects programs and its quirks.
+    pub fn for_platform(rom: &[u8], platform: Platform) -> Result<Self, Chip8Error> {
+        Self::with_ram_size(
+            rom,
+            platform.quirks(),
+            platform.load_address(),
+            platform.ram_size(),
+        )
+    }
+
+    /// Power on a machine with `rom` loaded at `load_address`, where execution starts.
+    /// Some platforms used another address, like 0x600 on the ETI-660.
+    pub fn with_load_address(
+        rom: &[u8],
+        quirks: Quirks,
+        load_address: u16,
+    ) -> Result<Self, Chip8Error> {
+        Self::with_ram_size(rom, quirks, load_address, RAM_SIZE)
+    }
+
+    /// Like [`Chip8State::with_load_address`], with `ram_size` bytes of ram, [`XO_RAM_SIZE`] for
+    /// XO-CHIP roms larger than 4KB. Clamped between [`RAM_SIZE`] and [`XO_RAM_SIZE`].
+    pub fn with_ram_size(
+        rom: &[u8],
+        quirks: Quirks,
+        load_address: u16,
+        ram_size: usize,
+    ) -> Result<Self, Chip8Error> {
+        let ram_size = ram_size.clamp(RAM_SIZE, XO_RAM_SIZE);
+        let start = load_address as usize;
+        if !(MIN_LOAD_ADDRESS..ram_size).contains(&start) {
+            return Err(Chip8Error::InvalidLoadAddress(load_address));
+        }
+        if rom.len() > ram_size - start {
+            return Err(Chip8Error::RomTooLarge {
+                size: rom.len(),
+                max: ram_size - start,
+            });
+        }
+
+        let mut ram = vec![0; ram_size];
+
+        // Copy fonts into ram
+        ram[font::FONT_ADDRESS..font::BIG_FONT_ADDRESS].copy_from_slice(&font::FONT);
+        ram[font::BIG_FONT_ADDRESS..MIN_LOAD_ADDRESS].copy_from_slice(&font::BIG_FONT);
+        ram[start..start + rom.len()].copy_from_slice(rom);
+
+        Ok(Chip8State {
+            ram,
+            pc: load_address,
+            i: 0,
+            v: [0; 16],
+            delay_timer: 0,
+            sound_timer: 0,
+            stack: Chip8Stack::new(),
+            display: Chip8Display::new(),
+            rng: rand::make_rng(),
+            quirks,
+            audio_pattern: None,
+            pitch: DEFAULT_PITCH,
+            exec_hook: None,
+            opcode_stats: None,
+            min_pc: None,
+            aligned_pc: false,
+            write_protect: None,
+            protected_writes: vec![],
+            timer_accumulator: 0,
+            rpl: [0; RPL_FLAGS],
+            halted: false,
+            blank_interrupt: false,
+            keys_seen: 0,
+            key_queue: vec![],
+        })
+    }
+
+    /// Make random numbers reproducible, for tests and replays.
+    pub fn seed_rng(&mut self, seed: u64) {
+        self.rng = StdRng::seed_from_u64(seed);
+    }
+
+    /// Call `hook` with the address and opcode of each instruction after executing it, including
+    /// one that fails. Replaces any previous hook.
+    pub fn set_exec_hook(&mut self, hook: impl FnMut(u16, u16) + 'static) {
+        self.exec_hook = Some(ExecHook(Box::new(hook)));
+    }
+
+    /// Bits of the audio pattern played per second.
+    pub fn pattern_rate(&self) -> f32 {
+        4000.0 * 2f32.powf((self.pitch as f32 - DEFAULT_PITCH as f32) / 48.0)
+    }
+
+    /// Start counting executed instructions.
+    pub fn enable_opcode_stats(&mut self) {
+        self.opcode_stats = Some(OpcodeStats::default());
+    }
+
+    /// Row-major RGBA pixels of the display at the current resolution, see
+    /// [`Chip8Display::framebuffer`].
+    pub fn framebuffer(&self, palette: &impl PixelPalette) -> Vec<u8> {
+        self.display.framebuffer(palette)
+    }
+
+    /// Signal that the display was just refreshed.
+    /// Draw instructions wait for this before executing, like on the COSMAC VIP.
+    pub fn vblank(&mut self) {
+        self.blank_interrupt = true;
+    }
+
+    /// The instruction stored at `addr`, if it lies within ram.
+    pub fn instruction_at(&self, addr: u16) -> Option<u16> {
+        let bytes = self.ram.get(addr as usize..addr as usize + 2)?;
+        // Big endian
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    /// Value of register v`reg`.
+    pub fn get_reg(&self, reg: usize) -> Result<u8, Chip8Error> {
+        self.v
+            .get(reg)
+            .copied()
+            .ok_or(Chip8Error::InvalidRegister(reg))
+    }
+
+    pub fn set_reg(&mut self, reg: usize, value: u8) -> Result<(), Chip8Error> {
+        *self
+            .v
+            .get_mut(reg)
+            .ok_or(Chip8Error::InvalidRegister(reg))? = value;
+        Ok(())
+    }
+
+    /// The `len` bytes of ram starting at `addr`.
+    pub fn read_ram(&self, addr: u16, len: usize) -> Result<&[u8], Chip8Error> {
+        self.ram
+            .get(addr as usize..addr as usize + len)
+            .ok_or(Chip8Error::RamOutOfBounds(addr))
+    }
+
+    /// Copy `bytes` into ram at `addr`. Nothing is written if they don't all fit.
+    pub fn write_ram(&mut self, addr: u16, bytes: &[u8]) -> Result<(), Chip8Error> {
+        self.ram
+            .get_mut(addr as usize..addr as usize + bytes.len())
+            .ok_or(Chip8Error::RamOutOfBounds(addr))?
+            .copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Replace the 4x5 hexadecimal digits that 0xfx29 points at, 5 bytes per digit.
+    pub fn load_font(&mut self, font: &[u8; 80]) {
+        self.ram[font::FONT_ADDRESS..font::BIG_FONT_ADDRESS].copy_from_slice(font);
+    }
+
+    /// Replace the 8x10 decimal digits that 0xfx30 points at, 10 bytes per digit.
+    pub fn load_big_font(&mut self, font: &[u8; 100]) {
+        self.ram[font::BIG_FONT_ADDRESS..MIN_LOAD_ADDRESS].copy_from_slice(font);
+    }
+
+    /// Continue execution at `addr`, which must hold a whole instruction.
+    pub fn set_pc(&mut self, addr: u16) -> Result<(), Chip8Error> {
+        if addr as usize + 2 > self.ram.len() {
+            return Err(Chip8Error::PcOutOfBounds(addr));
+        }
+        self.pc = addr;
+        self.key_queue.clear();
+        Ok(())
+    }
+
+    /// Count the timers down at 60Hz for `delta` of emulated time, independently of the cpu clock.
+    pub fn tick_timers(&mut self, delta: Duration) {
+        self.advance_timers(delta.as_micros() * TIMER_FREQUENCY_HZ);
+    }
+
+    /// Add `units` to the timer accumulator, where a timer tick is a second's worth of
+    /// microseconds.
+    fn advance_timers(&mut self, units: u128) {
+        self.timer_accumulator += units;
+        while self.timer_accumulator >= MICROS_PER_SECOND {
+            self.delay_timer = self.delay_timer.saturating_sub(1);
+            self.sound_timer = self.sound_timer.saturating_sub(1);
+            self.timer_accumulator -= MICROS_PER_SECOND;
+        }
+    }
+
+    /// Run one cycle: count the timers down for `delta`, then fetch and execute the instruction
+    /// at `pc`. Frontends that service the timers on their own with
+    /// [`Chip8State::tick_timers`] pass a zero `delta`.
+    /// On error, `pc` is left pointing at the offending instruction.
+    /// Once [`Chip8State::halted`], only the timers keep running.
+    pub fn update(&mut self, delta: Duration, keypad: &Chip8Keypad) -> Result<(), Chip8Error> {
+        let blank_interrupt = self.blank_interrupt;
+        self.blank_interrupt = false;
+
+        self.tick_timers(delta);
+        if self.halted {
+            return Ok(());
+        }
+        self.fetch_execute(keypad, blank_interrupt).map(|_| ())
+    }
+
+    /// Like [`Chip8State::update`], with time measured in cycles of a [`FIXED_CLOCK_HZ`] clock
+    /// instead of a duration: the timers count down once every 10 cycles elapsed, so that the same
+    /// calls always lead to the same state. Usually `cycles_elapsed` is 1.
+    pub fn update_fixed(
+        &mut self,
+        keypad: &Chip8Keypad,
+        cycles_elapsed: u32,
+    ) -> Result<(), Chip8Error> {
+        let blank_interrupt = self.blank_interrupt;
+        self.blank_interrupt = false;
+
+        self.advance_timers(
+            cycles_elapsed as u128 * TIMER_FREQUENCY_HZ * MICROS_PER_SECOND
+                / FIXED_CLOCK_HZ as u128,
+        );
+        if self.halted {
+            return Ok(());
+        }
+        self.fetch_execute(keypad, blank_interrupt).map(|_| ())
+    }
+
+    /// Fetch and execute the instruction at `pc`, and return it. Unlike [`Chip8State::update`],
+    /// the timers don't run and sprites are drawn right away, whatever the display_wait quirk.
+    /// It doesn't check [`Chip8State::halted`] either, that's up to the caller.
+    /// On error, `pc` is left pointing at the offending instruction.
+    pub fn step(&mut self, keypad: &Chip8Keypad) -> Result<u16, Chip8Error> {
+        self.blank_interrupt = false;
+        self.fetch_execute(keypad, true)
+    }
+
+    fn fetch_execute(
+        &mut self,
+        keypad: &Chip8Keypad,
+        blank_interrupt: bool,
+    ) -> Result<u16, Chip8Error> {
+        // Fetch
+
+        let instr_pc = self.pc;
+        if self.min_pc.is_some_and(|min_pc| instr_pc < min_pc) {
+            return Err(Chip8Error::PcOutOfBounds(instr_pc));
+        }
+        if self.aligned_pc && !instr_pc.is_multiple_of(2) {
+            return Err(Chip8Error::MisalignedPc(instr_pc));
+        }
+        let instr = self
+            .instruction_at(instr_pc)
+            .ok_or(Chip8Error::PcOutOfBounds(instr_pc))?;
+        if let Some(stats) = &mut self.opcode_stats {
+            stats.record(instr);
+        }
+
+        self.pc = self.pc.wrapping_add(2);
+
+        // Decode + execute
+        let result = self.execute(instr, keypad, blank_interrupt);
+        self.keys_seen = keypad.to_bitmask();
+        if let Some(ExecHook(hook)) = &mut self.exec_hook {
+            hook(instr_pc, instr);
+        }
+        if let Err(err) = result {
+            self.pc = instr_pc;
+            return Err(err);
+        }
+        Ok(instr)
+    }
+
+    /// Start of the `len` bytes at the index register. They may only run past the end of ram
+    /// when the wrap_memory quirk wraps them around.
+    fn index_range(&self, len: usize) -> Result<usize, Chip8Error> {
+        let start = self.i as usize;
+        if start + len > self.ram.len() && !self.quirks.wrap_memory {
+            return Err(Chip8Error::RamOutOfBounds(self.i));
+        }
+        Ok(start)
+    }
+
+    /// One cycle of 0xfx0a: the first queued key once it was pressed, and released unless the
+    /// key_wait_on_press quirk is set. A press is queued as soon as it's seen, so that neither a
+    /// key held over many cycles nor input polled less often than cycles run registers it twice.
+    fn wait_key(&mut self, keypad: &Chip8Keypad) -> Option<u8> {
+        let held = keypad.to_bitmask();
+        let pressed = held & !self.keys_seen;
+        for key in 0..16 {
+            if pressed & (1 << key) != 0 && !self.key_queue.contains(&key) {
+                self.key_queue.push(key);
+            }
+        }
+        let key = *self.key_queue.first()?;
+        if self.quirks.key_wait_on_press || held & (1 << key) == 0 {
+            Some(self.key_queue.remove(0))
+        } else {
+            None
+        }
+    }
+
+    /// Write a byte of ram for the instruction being executed, recording it if `addr` is
+    /// protected.
+    fn store(&mut self, addr: usize, value: u8) {
+        if self
+            .write_protect
+            .as_ref()
+            .is_some_and(|range| range.contains(&addr))
+        {
+            self.protected_writes.push(ProtectedWrite {
+                // pc already moved past the instruction
+                pc: self.pc.wrapping_sub(2),
+                addr: addr as u16,
+                old: self.ram[addr],
+                new: value,
+            });
+        }
+        self.ram[addr] = value;
+    }
+
+    /// Skip the next instruction, both words of it if it's a 0xf000 nnnn long load.
+    fn skip_next(&mut self) {
+        let len = if self.instruction_at(self.pc) == Some(0xf000) {
+            4
+        } else {
+            2
+        };
+        self.pc = self.pc.wrapping_add(len);
+    }
+
+    /// Scroll distances are given in high resolution pixels, so they are halved in low resolution.
+    fn scroll_amount(&self, n: usize) -> usize {
+        if self.display.is_hires() { n } else { n / 2 }
+    }
+
+    fn set_hires(&mut self, hires: bool) {
+        if self.quirks.clear_on_resolution_change {
+            self.display.set_hires(hires);
+        } else {
+            self.display.set_hires_scaled(hires);
+        }
+    }
+
+    /// Draw the sprite at `i` to the selected planes at (vx, vy), and set vf on collision.
+    fn draw_sprite(&mut self, x: usize, y: usize, n: u16) -> Result<(), Chip8Error> {
+        let width = self.display.width();
+        let height = self.display.height();
+        let hires = self.display.is_hires();
+
+        // 0xdxy0 draws a 16x16 sprite in high resolution mode
+        let (sprite_width, sprite_height) = if n == 0 && hires { (16, 16) } else { (8, n) };
+        let bytes_per_row = sprite_width / 8;
+        // Each selected plane reads its own sprite, stored one after the other
+        let sprite_len = (sprite_height * bytes_per_row) as usize;
+        let mut sprite_addr =
+            self.index_range(sprite_len * self.display.selected_plane_indices().count())?;
+        // Bit r is set if row r collided on any plane
+        let mut collided_rows: u16 = 0;
+        // Rows past the bottom of the display, when clipped
+        let mut clipped_rows = 0;
+
+        for plane in self.display.selected_plane_indices() {
+            // The starting position always wraps, the rest of the sprite is clipped or wraps
+            let mut posy = self.v[y] as usize % height;
+
+            'yloop: for row in 0..sprite_height {
+                let mut posx = self.v[x] as usize % width;
+                let row_addr = sprite_addr + (row * bytes_per_row) as usize;
+                // Left-align the row data on 16 bits
+                let data = if sprite_width == 16 {
+                    u16::from_be_bytes([
+                        self.ram[row_addr % self.ram.len()],
+                        self.ram[(row_addr + 1) % self.ram.len()],
+                    ])
+                } else {
+                    (self.ram[row_addr % self.ram.len()] as u16) << 8
+                };
+
+                'xloop: for bit_idx in (16 - sprite_width..16).rev() {
+                    let value = (data >> bit_idx) & 0b1;
+                    if value == 0b1 && self.display.toggle(plane, posx, posy) {
+                        collided_rows |= 1 << row;
+                    }
+                    posx += 1;
+                    if posx >= width {
+                        if self.quirks.clip_sprites {
+                            break 'xloop;
+                        }
+                        posx = 0;
+                    }
+                }
+
+                posy += 1;
+                if posy >= height {
+                    if self.quirks.clip_sprites {
+                        clipped_rows = sprite_height - row - 1;
+                        break 'yloop;
+                    }
+                    posy = 0;
+                }
+            }
+
+            sprite_addr += sprite_len;
+        }
+
+        self.v[0xf] = if hires && self.quirks.count_collided_rows {
+            (collided_rows.count_ones() + clipped_rows as u32) as u8
+        } else {
+            (collided_rows != 0) as u8
+        };
+        Ok(())
+    }
+
+    fn execute(
+        &mut self,
+        instr: u16,
+        keypad: &Chip8Keypad,
+        blank_interrupt: bool,
+    ) -> Result<(), Chip8Error> {
+        let x = ((instr & 0x0f00) >> 8) as usize;
+        let y = ((instr & 0x00f0) >> 4) as usize;
+        let n = instr & 0x000f;
+        let nn = (instr & 0x00ff) as u8;
+        let nnn = instr & 0x0fff;
+
+        match (instr & 0xf000) >> 12 {
+            0x0 => {
+                if instr == 0x00e0 {
+                    // 0x00e0: clear display
+                    self.display.clear();
+                } else if instr == 0x00ee {
+                    // 0x00ee: return from subroutine
+                    self.pc = self.stack.pop()?;
+                } else if instr & 0xfff0 == 0x00c0 {
+                    // 0x00cn: scroll down n pixels (n/2 in low resolution)
+                    self.display.scroll_down(self.scroll_amount(n as usize));
+                } else if instr == 0x00fb {
+                    // 0x00fb: scroll right 4 pixels (2 in low resolution)
+                    self.display.scroll_right(self.scroll_amount(4));
+                } else if instr == 0x00fc {
+                    // 0x00fc: scroll left 4 pixels (2 in low resolution)
+                    self.display.scroll_left(self.scroll_amount(4));
+                } else if instr == 0x00fd {
+                    // 0x00fd: exit the interpreter
+                    self.halted = true;
+                } else if instr == 0x00fe {
+                    // 0x00fe: switch to low resolution
+                    self.set_hires(false);
+                } else if instr == 0x00ff {
+                    // 0x00ff: switch to high resolution
+                    self.set_hires(true);
+                }
+                // 0x0nnn: call a machine code routine on the original hardware, ignored like
+                // modern interpreters do since some old roms contain stray ones
+            }
+            0x1 => {
+                // 0x1nnn: jump
+                self.pc = nnn;
+            }
+            0x2 => {
+                // 0x2nnn: call subroutine
+                self.stack.push(self.pc)?;
+                self.pc = nnn;
+            }
+            0x3 => {
+                // 0x3xnn: skip if vx == nn
+                if self.v[x] == nn {
+                    self.skip_next();
+                }
+            }
+            0x4 => {
+                // 0x4xnn: skip if vx != nn
+                if self.v[x] != nn {
+                    self.skip_next();
+                }
+            }
+            0x5 if n == 0x0 => {
+                // 0x5xy0: skip if vx == vy
+                if self.v[x] == self.v[y] {
+                    self.skip_next();
+                }
+            }
+            0x6 => {
+                // 0x6xnn: load vx with immediate value
+                self.v[x] = nn;
+            }
+            0x7 => {
+                // 0x7xnn: add value to register vx
+                self.v[x] = self.v[x].wrapping_add(nn);
+            }
+            0x8 => {
+                // arithmetic
+                if n == 0x0 {
+                    // 0x8xy0: set
+                    self.v[x] = self.v[y];
+                } else if n == 0x1 {
+                    // 0x8xy1: binary or
+                    self.v[x] |= self.v[y];
+                    if self.quirks.vf_reset {
+                        self.v[0xf] = 0;
+                    }
+                } else if n == 0x2 {
+                    // 0x8xy2: binary and
+                    self.v[x] &= self.v[y];
+                    if self.quirks.vf_reset {
+                        self.v[0xf] = 0;
+                    }
+                } else if n == 0x3 {
+                    // 0x8xy3: binary xor
+                    self.v[x] ^= self.v[y];
+                    if self.quirks.vf_reset {
+                        self.v[0xf] = 0;
+                    }
+                } else if n == 0x4 {
+                    // 0x8xy4: add
+                    let (value, overflow) = self.v[x].overflowing_add(self.v[y]);
+                    self.v[x] = value;
+                    self.v[0xf] = if overflow { 1 } else { 0 };
+                } else if n == 0x5 {
+                    // 0x8xy5: subtract vx - vy
+                    let (value, overflow) = self.v[x].overflowing_sub(self.v[y]);
+                    self.v[x] = value;
+                    self.v[0xf] = if overflow { 0 } else { 1 };
+                } else if n == 0x6 {
+                    // 0x8xy6: shift right
+                    if !self.quirks.shift_vx_in_place {
+                        self.v[x] = self.v[y];
+                    }
+                    let bit = self.v[x] & 0b1;
+                    self.v[x] >>= 1;
+                    self.v[0xf] = bit;
+                } else if n == 0x7 {
+                    // 0x8xy7: subtract vy - vx
+                    let (value, overflow) = self.v[y].overflowing_sub(self.v[x]);
+                    self.v[x] = value;
+                    self.v[0xf] = if overflow { 0 } else { 1 };
+                } else if n == 0xe {
+                    // 0x8xye: shift left
+                    if !self.quirks.shift_vx_in_place {
+                        self.v[x] = self.v[y];
+                    }
+                    let bit = (self.v[x] & 0b10000000) >> 7;
+                    self.v[x] <<= 1;
+                    self.v[0xf] = bit;
+                } else {
+                    return Err(Chip8Error::UnknownOpcode(instr));
+                }
+            }
+            0x9 if n == 0x0 => {
+                // 0x9xy0: skip if vx != vy
+                if self.v[x] != self.v[y] {
+                    self.skip_next();
+                }
+            }
+            0xa => {
+                // 0xannn: load index register with immediate value
+                self.i = nnn;
+            }
+            0xb => {
+                if self.quirks.jump_with_vx {
+                    // 0xbxnn: jump to vx + xnn
+                    self.pc = nnn + self.v[x] as u16;
+                } else {
+                    // 0xbnnn: jump to v0 + nnn
+                    self.pc = nnn + self.v[0x0] as u16;
+                }
+            }
+            0xc => {
+                // 0xcxnn: rng
+                self.v[x] = self.rng.random::<u8>() & nn;
+            }
+            0xd => {
+                // 0xdxyn: draw sprite
+
+                if self.quirks.display_wait && !blank_interrupt {
+                    // Block on this instruction until the next render
+                    self.pc = self.pc.wrapping_sub(2);
+                } else {
+                    self.draw_sprite(x, y, n)?;
+                }
+            }
+            0xe => {
+                // Only the low nibble of vx names a key
+                let key = (self.v[x] & 0xf) as usize;
+                if nn == 0x9e {
+                    // 0xex9e: skip if key in vx is pressed
+                    if keypad.pressed[key] {
+                        self.skip_next();
+                    }
+                } else if nn == 0xa1 {
+                    // 0xexa1: skip if key in vx is not pressed
+                    if !keypad.pressed[key] {
+                        self.skip_next();
+                    }
+                } else {
+                    return Err(Chip8Error::UnknownOpcode(instr));
+                }
+            }
+            0xf => {
+                if nn == 0x01 {
+                    // 0xfn01: select the planes to draw on (XO-CHIP)
+                    self.display.select_planes(x as u8);
+                } else if instr == 0xf000 {
+                    // 0xf000 nnnn: load index register with the 16-bit word that follows (XO-CHIP)
+                    self.i = self
+                        .instruction_at(self.pc)
+                        .ok_or(Chip8Error::PcOutOfBounds(self.pc))?;
+                    self.pc = self.pc.wrapping_add(2);
+                } else if instr == 0xf002 {
+                    // 0xf002: load the audio pattern from ram at index (XO-CHIP)
+                    let mut pattern = [0; 16];
+                    for (offset, byte) in pattern.iter_mut().enumerate() {
+                        *byte = self.ram[(self.i as usize + offset) % self.ram.len()];
+                    }
+                    self.audio_pattern = Some(pattern);
+                } else if nn == 0x3a {
+                    // 0xfx3a: set the audio pattern playback pitch (XO-CHIP)
+                    self.pitch = self.v[x];
+                } else if nn == 0x07 {
+                    // 0xfx07: get delay timer
+                    self.v[x] = self.delay_timer;
+                } else if nn == 0x15 {
+                    // 0xfx15: set delay timer
+                    self.delay_timer = self.v[x];
+                } else if nn == 0x18 {
+                    // 0xfx18: set sound timer
+                    self.sound_timer = self.v[x];
+                } else if nn == 0x1e {
+                    // 0xfx1e: add to index
+                    let mut sum = self.i as usize + self.v[x] as usize;
+                    if sum >= self.ram.len() {
+                        if self.quirks.index_overflow_vf {
+                            self.v[0xf] = 1;
+                        }
+                        sum %= self.ram.len();
+                    }
+                    self.i = sum as u16;
+                } else if nn == 0x0a {
+                    // 0xfx0a: get key
+                    match self.wait_key(keypad) {
+                        Some(key) => self.v[x] = key,
+                        // Keep executing this instruction until some key is pressed
+                        None => self.pc = self.pc.wrapping_sub(2),
+                    }
+                } else if nn == 0x29 {
+                    // 0xfx29: set index to a font sprite
+                    self.i = (font::FONT_ADDRESS + self.v[x] as usize * 5) as u16;
+                } else if nn == 0x30 {
+                    // 0xfx30: set index to a big font sprite, there are only decimal digits
+                    self.i = (font::BIG_FONT_ADDRESS + self.v[x] as usize * 10) as u16;
+                } else if nn == 0x33 {
+                    // 0xfx33: vx to decimal
+                    let start = self.index_range(3)?;
+                    let ram_size = self.ram.len();
+                    let vx = self.v[x];
+                    for (offset, digit) in [vx / 100, vx / 10 % 10, vx % 10].into_iter().enumerate()
+                    {
+                        self.store((start + offset) % ram_size, digit);
+                    }
+                } else if nn == 0x55 {
+                    // 0xfx55: store to ram
+                    let start = self.index_range(x + 1)?;
+                    let ram_size = self.ram.len();
+                    for i in 0..=x {
+                        self.store((start + i) % ram_size, self.v[i]);
+                    }
+                    self.i = self.i.wrapping_add(self.quirks.memory_increment.amount(x));
+                } else if nn == 0x65 {
+                    // 0xfx65: load from ram
+                    let start = self.index_range(x + 1)?;
+                    for i in 0..=x {
+                        self.v[i] = self.ram[(start + i) % self.ram.len()];
+                    }
+                    self.i = self.i.wrapping_add(self.quirks.memory_increment.amount(x));
+                } else if nn == 0x75 {
+                    // 0xfx75: save v0 to vx in the RPL user flags (SUPER-CHIP)
+                    self.rpl[..=x].copy_from_slice(&self.v[..=x]);
+                } else if nn == 0x85 {
+                    // 0xfx85: load v0 to vx from the RPL user flags (SUPER-CHIP)
+                    self.v[..=x].copy_from_slice(&self.rpl[..=x]);
+                } else {
+                    return Err(Chip8Error::UnknownOpcode(instr));
+                }
+            }
+            // 0x5xyn and 0x9xyn with n != 0
+            _ => return Err(Chip8Error::UnknownOpcode(instr)),
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::DISPLAY_WIDTH;
+    use crate::quirks::IndexIncrement;
+
+    /// Run one instruction per rom word, with no keys pressed and no time elapsed.
+    fn run(state: &mut Chip8State, cycles: usize) {
+        let keypad = Chip8Keypad::default();
+        for _ in 0..cycles {
+            state.vblank();
+            state.update(Duration::ZERO, &keypad).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_load_and_add() {
+        // v0 = 0xff, v1 = 0x02, v0 += v1
+        let mut state =
+            Chip8State::new(&[0x60, 0xff, 0x61, 0x02, 0x80, 0x14], Quirks::default()).unwrap();
+        run(&mut state, 3);
+        assert_eq!(state.v[0x0], 0x01);
+        assert_eq!(state.v[0xf], 1);
+        assert_eq!(state.pc, 0x206);
+    }
+
+    #[test]
+    fn test_bcd() {
+        // v0 = 254, i = 0x300, bcd v0
+        let mut state =
+            Chip8State::new(&[0x60, 0xfe, 0xa3, 0x00, 0xf0, 0x33], Quirks::default()).unwrap();
+        run(&mut state, 3);
+        assert_eq!(state.ram[0x300..0x303], [2, 5, 4]);
+    }
+
+    #[test]
+    fn test_draw_font_sprite() {
+        // v0 = 0, i = font sprite for v0, draw at (v0, v0)
+        let mut state =
+            Chip8State::new(&[0x60, 0x00, 0xf0, 0x29, 0xd0, 0x05], Quirks::default()).unwrap();
+        run(&mut state, 3);
+        // Top row of the "0" glyph is 0xF0
+        let row: Vec<bool> = state.display.planes[0][..8].to_vec();
+        assert_eq!(row, [true, true, true, true, false, false, false, false]);
+        assert_eq!(state.v[0xf], 0);
+    }
+
+    #[test]
+    fn test_big_font() {
+        // hires, v0 = 5, i = big font sprite for v0, draw it at (v0, v0)
+        let rom = [0x00, 0xff, 0x60, 0x05, 0xf0, 0x30, 0xd0, 0x0a];
+        let mut state = Chip8State::new(&rom, Quirks::default()).unwrap();
+        run(&mut state, 3);
+        assert_eq!(state.i, 0xa0 + 5 * 10);
+        assert_eq!(state.read_ram(state.i, 2).unwrap(), [0xff, 0xff]);
+        run(&mut state, 1);
+        // Top row of the big "5" glyph is 0xFF
+        let width = state.display.width();
+        assert!(
+            state.display.planes[0][5 * width + 5..5 * width + 13]
+                .iter()
+                .all(|lit| *lit)
+        );
+
+        // Custom fonts
+        state.load_font(&[0x11; 80]);
+        state.load_big_font(&[0x22; 100]);
+        assert_eq!(state.read_ram(0x50 + 79, 2).unwrap(), [0x11, 0x22]);
+        assert_eq!(state.read_ram(0xa0 + 99, 2).unwrap(), [0x22, 0x00]);
+    }
+
+    #[test]
+    fn test_draw_waits_for_vblank() {
+        let mut state = Chip8State::new(&[0xd0, 0x05], Quirks::default()).unwrap();
+        state
+            .update(Duration::ZERO, &Chip8Keypad::default())
+            .unwrap();
+        assert_eq!(state.pc, 0x200);
+        state.vblank();
+        state
+            .update(Duration::ZERO, &Chip8Keypad::default())
+            .unwrap();
+        assert_eq!(state.pc, 0x202);
+    }
+
+    #[test]
+    fn test_update_fixed() {
+        // Jump to itself
+        let mut state = Chip8State::new(&[0x12, 0x00], Quirks::default()).unwrap();
+        state.delay_timer = 10;
+        state.sound_timer = 1;
+        let keypad = Chip8Keypad::default();
+        for _ in 0..9 {
+            state.update_fixed(&keypad, 1).unwrap();
+        }
+        assert_eq!((state.delay_timer, state.sound_timer), (10, 1));
+        state.update_fixed(&keypad, 1).unwrap();
+        assert_eq!((state.delay_timer, state.sound_timer), (9, 0));
+        // A whole second at once
+        state.update_fixed(&keypad, FIXED_CLOCK_HZ).unwrap();
+        assert_eq!(state.delay_timer, 0);
+        assert_eq!(state.pc, 0x200);
+    }
+
+    #[test]
+    fn test_step() {
+        // v0 = 3, draw "0" at (3, 3) without waiting for vblank, then an unknown instruction
+        let mut state = Chip8State::new(
+            &[0x60, 0x03, 0xa0, 0x50, 0xd0, 0x05, 0x50, 0x01],
+            Quirks::default(),
+        )
+        .unwrap();
+        state.delay_timer = 10;
+        let keypad = Chip8Keypad::default();
+        assert_eq!(state.step(&keypad), Ok(0x6003));
+        assert_eq!(state.step(&keypad), Ok(0xa050));
+        assert_eq!(state.step(&keypad), Ok(0xd005));
+        assert_eq!(state.pc, 0x206);
+        assert!(state.display.planes[0][3 + 3 * DISPLAY_WIDTH]);
+        assert_eq!(state.step(&keypad), Err(Chip8Error::UnknownOpcode(0x5001)));
+        assert_eq!(state.pc, 0x206);
+        assert_eq!(state.delay_timer, 10);
+    }
+
+    #[test]
+    fn test_exec_hook() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+
+        // v0 = 1, jump back to the start
+        let mut state = Chip8State::new(&[0x60, 0x01, 0x12, 0x00], Quirks::default()).unwrap();
+        let executed = Rc::new(RefCell::new(vec![]));
+        let recorder = executed.clone();
+        state.set_exec_hook(move |pc, opcode| recorder.borrow_mut().push((pc, opcode)));
+        run(&mut state, 3);
+        assert_eq!(
+            *executed.borrow(),
+            [(0x200, 0x6001), (0x202, 0x1200), (0x200, 0x6001)]
+        );
+    }
+
+    #[test]
+    fn test_min_pc() {
+        // Jump into the font
+        let mut state = Chip8State::new(&[0x10, 0x50], Quirks::default()).unwrap();
+        state.min_pc = Some(PROGRAM_START as u16);
+        let keypad = Chip8Keypad::default();
+        assert_eq!(state.step(&keypad), Ok(0x1050));
+        assert_eq!(state.step(&keypad), Err(Chip8Error::PcOutOfBounds(0x050)));
+        assert_eq!(state.pc, 0x050);
+    }
+
+    #[test]
+    fn test_write_protect() {
+        // i = 0x202, v0 = 0x12, v1 = 0x34, 0xf155 overwrites the next instructions
+        let rom = [0xa2, 0x08, 0x60, 0x12, 0x61, 0x34, 0xf1, 0x55, 0x00, 0xe0];
+        let mut state = Chip8State::new(&rom, Quirks::default()).unwrap();
+        state.write_protect = Some(0x200..0x209);
+        run(&mut state, 4);
+        assert_eq!(
+            state.protected_writes,
+            [ProtectedWrite {
+                pc: 0x206,
+                addr: 0x208,
+                old: 0x00,
+                new: 0x12,
+            }]
+        );
+        // Written all the same
+        assert_eq!(state.ram[0x208..0x20a], [0x12, 0x34]);
+    }
+
+    #[test]
+    fn test_aligned_pc() {
+        // Jump to an odd address, where 0x6001 sets v0 = 1
+        let rom = [0x12, 0x03, 0x00, 0x60, 0x01];
+        let keypad = Chip8Keypad::default();
+        let mut state = Chip8State::new(&rom, Quirks::default()).unwrap();
+        state.step(&keypad).unwrap();
+        assert_eq!(state.step(&keypad), Ok(0x6001));
+
+        let mut state = Chip8State::new(&rom, Quirks::default()).unwrap();
+        state.aligned_pc = true;
+        state.step(&keypad).unwrap();
+        assert_eq!(state.step(&keypad), Err(Chip8Error::MisalignedPc(0x203)));
+        assert_eq!(state.pc, 0x203);
+    }
+
+    #[test]
+    fn test_display_wait_quirk() {
+        // i = font sprite "0", then draw it four times in a row
+        let rom = [0xa0, 0x50, 0xd0, 0x05, 0xd0, 0x05, 0xd0, 0x05, 0xd0, 0x05];
+        let keypad = Chip8Keypad::default();
+        for (display_wait, pc) in [(true, 0x202), (false, 0x20a)] {
+            let quirks = Quirks {
+                display_wait,
+                ..Quirks::default()
+            };
+            let mut state = Chip8State::new(&rom, quirks).unwrap();
+            // All within a single frame
+            for _ in 0..5 {
+                state.update(Duration::ZERO, &keypad).unwrap();
+            }
+            assert_eq!(state.pc, pc);
+        }
+    }
+
+    #[test]
+    fn test_audio_pattern() {
+        // i = 0x50, load pattern, v0 = 112, pitch v0
+        let mut state = Chip8State::new(
+            &[0xa0, 0x50, 0xf0, 0x02, 0x60, 0x70, 0xf0, 0x3a],
+            Quirks::default(),
+        )
+        .unwrap();
+        assert_eq!(state.audio_pattern, None);
+        assert_eq!(state.pattern_rate(), 4000.0);
+        run(&mut state, 4);
+        assert_eq!(state.audio_pattern.unwrap(), state.ram[0x50..0x60]);
+        assert_eq!(state.pitch, 112);
+        assert_eq!(state.pattern_rate(), 8000.0);
+    }
+
+    #[test]
+    fn test_get_key_quirk() {
+        // Wait for a key into v0, then loop back
+        let rom = [0xf0, 0x0a, 0x12, 0x00];
+        // Key 5 goes down, stays held for a while, then comes back up
+        let held = [false, true, true, true, true, false, false];
+        for (key_wait_on_press, press_cycle) in [(true, 1), (false, 5)] {
+            let quirks = Quirks {
+                key_wait_on_press,
+                ..Quirks::default()
+            };
+            let mut state = Chip8State::new(&rom, quirks).unwrap();
+            state.v[0] = 0xff;
+            let mut keypad = Chip8Keypad::default();
+            let mut registered = vec![];
+            for (cycle, down) in held.into_iter().enumerate() {
+                keypad.advance();
+                if down {
+                    keypad.press(5);
+                } else {
+                    keypad.release(5);
+                }
+                state.vblank();
+                state.update(Duration::ZERO, &keypad).unwrap();
+                if state.pc == 0x202 {
+                    registered.push(cycle);
+                    // Run the jump back to the key wait
+                    state.update(Duration::ZERO, &keypad).unwrap();
+                }
+            }
+            assert_eq!(registered, [press_cycle]);
+            assert_eq!(state.v[0], 5);
+        }
+    }
+
+    #[test]
+    fn test_get_key_slow_polling() {
+        // Wait for a key into v0, count it in v1, then loop back
+        let rom = [0xf0, 0x0a, 0x71, 0x01, 0x12, 0x00];
+        for key_wait_on_press in [true, false] {
+            let quirks = Quirks {
+                key_wait_on_press,
+                ..Quirks::default()
+            };
+            let mut state = Chip8State::new(&rom, quirks).unwrap();
+            let mut keypad = Chip8Keypad::default();
+            // Input is polled once every 10 cycles: key 7 held for 3 polls, then released, then
+            // key 2 tapped for a single poll
+            for poll in [
+                0x0000, 0x0080, 0x0080, 0x0080, 0x0000, 0x0004, 0x0000, 0x0000,
+            ] {
+                keypad.advance();
+                keypad.set_bitmask(poll);
+                for _ in 0..10 {
+                    state.update(Duration::ZERO, &keypad).unwrap();
+                }
+            }
+            assert_eq!(state.v[1], 2);
+            assert_eq!(state.v[0], 2);
+        }
+    }
+
+    #[test]
+    fn test_get_key_queue() {
+        // Wait for a key into v0, then v1, then loop
+        let rom = [0xf0, 0x0a, 0xf1, 0x0a, 0x12, 0x04];
+        for key_wait_on_press in [true, false] {
+            let quirks = Quirks {
+                key_wait_on_press,
+                ..Quirks::default()
+            };
+            let mut state = Chip8State::new(&rom, quirks).unwrap();
+            let mut keypad = Chip8Keypad::default();
+            // Keys 9 and 3 both go down between two polls, then come back up together
+            for poll in [0x0000, 0x0208, 0x0208, 0x0000, 0x0000] {
+                keypad.advance();
+                keypad.set_bitmask(poll);
+                for _ in 0..10 {
+                    state.update(Duration::ZERO, &keypad).unwrap();
+                }
+            }
+            assert_eq!((state.v[0], state.v[1]), (3, 9));
+            assert!(state.key_queue.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_clip_sprites_quirk() {
+        // v0 = 62, v1 = 30, i = font sprite "0", draw it across the bottom right corner
+        let rom = [0x60, 0x3e, 0x61, 0x1e, 0xa0, 0x50, 0xd0, 0x15];
+        for clip_sprites in [true, false] {
+            let quirks = Quirks {
+                clip_sprites,
+                ..Quirks::default()
+            };
+            let mut state = Chip8State::new(&rom, quirks).unwrap();
+            run(&mut state, 4);
+            let pixel = |x: usize, y: usize| state.display.planes[0][x + y * DISPLAY_WIDTH];
+            assert!(pixel(62, 30) && pixel(63, 30) && pixel(62, 31));
+            // Past the right edge
+            assert_eq!(pixel(0, 30), !clip_sprites);
+            assert_eq!(pixel(1, 31), !clip_sprites);
+            // Past the bottom edge
+            assert_eq!(pixel(62, 0), !clip_sprites);
+            assert_eq!(pixel(0, 2), !clip_sprites);
+        }
+    }
+
+    #[test]
+    fn test_timers_tick_at_60hz() {
+        // Wait for ever
+        let mut state = Chip8State::new(&[0x12, 0x00], Quirks::default()).unwrap();
+        state.delay_timer = 60;
+        state.sound_timer = 60;
+        // One second at 500Hz
+        for _ in 0..500 {
+            state
+                .update(Duration::from_millis(2), &Chip8Keypad::default())
+                .unwrap();
+        }
+        assert_eq!(state.delay_timer, 0);
+        assert_eq!(state.sound_timer, 0);
+
+        // Regardless of the cpu cadence
+        state.delay_timer = 60;
+        state.tick_timers(Duration::from_millis(999));
+        assert_eq!(state.delay_timer, 1);
+        state.tick_timers(Duration::from_millis(1));
+        assert_eq!(state.delay_timer, 0);
+    }
+
+    #[test]
+    fn test_load_address() {
+        // v0 = 1 at 0x600
+        let state = Chip8State::with_load_address(&[0x60, 0x01], Quirks::default(), 0x600);
+        let mut state = state.unwrap();
+        assert_eq!(state.pc, 0x600);
+        assert_eq!(state.ram[0x600..0x602], [0x60, 0x01]);
+        run(&mut state, 1);
+        assert_eq!(state.v[0], 1);
+
+        assert_eq!(
+            Chip8State::with_load_address(&[0; 0x201], Quirks::default(), 0xe00).unwrap_err(),
+            Chip8Error::RomTooLarge {
+                size: 0x201,
+                max: 0x200
+            }
+        );
+        for addr in [0x50, 0x1000] {
+            assert_eq!(
+                Chip8State::with_load_address(&[], Quirks::default(), addr).unwrap_err(),
+                Chip8Error::InvalidLoadAddress(addr)
+            );
+        }
+    }
+
+    #[test]
+    fn test_platform() {
+        let state = Chip8State::for_platform(&[0x60, 0x01], Platform::Eti660).unwrap();
+        assert_eq!(state.pc, 0x600);
+        assert_eq!(state.quirks, Quirks::COSMAC);
+        let state = Chip8State::for_platform(&[0x60, 0x01], Platform::Schip).unwrap();
+        assert_eq!(state.pc, 0x200);
+        assert_eq!(state.quirks, Quirks::SCHIP);
+        assert_eq!(state.ram.len(), RAM_SIZE);
+    }
+
+    #[test]
+    fn test_xochip_ram() {
+        let rom = vec![0; XO_RAM_SIZE - PROGRAM_START];
+        assert!(Chip8State::new(&rom, Quirks::XOCHIP).is_err());
+        let mut state = Chip8State::for_platform(&rom, Platform::XoChip).unwrap();
+        assert_eq!(state.ram.len(), XO_RAM_SIZE);
+
+        // i = 0xfff0, v0 = 0xff, 0xf01e: i += v0 wraps around 64KB instead of 4KB
+        state
+            .write_ram(0x200, &[0xf0, 0x00, 0xff, 0xf0, 0x60, 0xff, 0xf0, 0x1e])
+            .unwrap();
+        run(&mut state, 2);
+        assert_eq!(state.i, 0xfff0);
+        run(&mut state, 1);
+        assert_eq!(state.i, 0x00ef);
+    }
+
+    #[test]
+    fn test_wait_at_end_of_xochip_ram() {
+        // Instructions that wait by running again, in the last word of 64KB ram
+        let mut state = Chip8State::for_platform(&[], Platform::XoChip).unwrap();
+        state.quirks.display_wait = true;
+        let keypad = Chip8Keypad::default();
+        for instr in [[0xf0, 0x0a], [0xd0, 0x05]] {
+            state.write_ram(0xfffe, &instr).unwrap();
+            state.set_pc(0xfffe).unwrap();
+            state.update(Duration::ZERO, &keypad).unwrap();
+            assert_eq!(state.pc, 0xfffe);
+        }
+    }
+
+    #[test]
+    fn test_long_load() {
+        // 0xf000 0x1234, then v0 = 1
+        let rom = [0xf0, 0x00, 0x12, 0x34, 0x60, 0x01];
+        let mut state = Chip8State::new(&rom, Quirks::XOCHIP).unwrap();
+        run(&mut state, 1);
+        assert_eq!(state.i, 0x1234);
+        assert_eq!(state.pc, 0x204);
+        run(&mut state, 1);
+        assert_eq!(state.v[0], 1);
+
+        // Skips jump over both words: v0 == 0 skips 0xf000 0x1234, then v1 = 1
+        let rom = [0x30, 0x00, 0xf0, 0x00, 0x12, 0x34, 0x61, 0x01];
+        let mut state = Chip8State::new(&rom, Quirks::XOCHIP).unwrap();
+        run(&mut state, 2);
+        assert_eq!(state.i, 0);
+        assert_eq!(state.v[1], 1);
+    }
+
+    #[test]
+    fn test_unknown_opcode() {
+        let mut state = Chip8State::new(&[0x00, 0xe0, 0x50, 0x01], Quirks::default()).unwrap();
+        run(&mut state, 1);
+        let result = state.update(Duration::ZERO, &Chip8Keypad::default());
+        assert_eq!(result, Err(Chip8Error::UnknownOpcode(0x5001)));
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "Unknown instruction 0x5001"
+        );
+        assert_eq!(state.pc, 0x202);
+    }
+
+    #[test]
+    fn test_unknown_opcodes_match_disassembler() {
+        // Every opcode the disassembler doesn't know is an error, never silently skipped
+        let keypad = Chip8Keypad::default();
+        for opcode in 0..=u16::MAX {
+            if crate::disasm::mnemonic(opcode).is_some() {
+                continue;
+            }
+            let mut state = Chip8State::new(&opcode.to_be_bytes(), Quirks::default()).unwrap();
+            assert_eq!(
+                state.update(Duration::ZERO, &keypad),
+                Err(Chip8Error::UnknownOpcode(opcode))
+            );
+        }
+    }
+
+    #[test]
+    fn test_sys_is_ignored() {
+        let mut state = Chip8State::new(&[0x01, 0x23, 0x60, 0x01], Quirks::default()).unwrap();
+        run(&mut state, 2);
+        assert_eq!(state.pc, 0x204);
+        assert_eq!(state.v[0], 1);
+    }
+
+    #[test]
+    fn test_poke_state() {
+        // Draw a sprite set up without a rom
+        let mut state = Chip8State::new(&[], Quirks::default()).unwrap();
+        state.write_ram(0x300, &[0b1010_0000]).unwrap();
+        state.write_ram(0x200, &[0xd0, 0x11]).unwrap();
+        state.set_reg(0, 2).unwrap();
+        state.i = 0x300;
+        run(&mut state, 1);
+        assert!(state.display.planes[0][2]);
+        assert!(state.display.planes[0][4]);
+        assert_eq!(state.read_ram(0x200, 2).unwrap(), &[0xd0, 0x11]);
+        assert_eq!(state.get_reg(0), Ok(2));
+
+        assert_eq!(state.get_reg(16), Err(Chip8Error::InvalidRegister(16)));
+        assert_eq!(state.set_reg(16, 0), Err(Chip8Error::InvalidRegister(16)));
+        assert_eq!(
+            state.write_ram(0xfff, &[1, 2]),
+            Err(Chip8Error::RamOutOfBounds(0xfff))
+        );
+        assert_eq!(state.ram[0xfff], 0);
+        assert!(state.read_ram(0xffe, 3).is_err());
+        assert!(state.set_pc(0xffe).is_ok());
+        assert_eq!(state.set_pc(0xfff), Err(Chip8Error::PcOutOfBounds(0xfff)));
+    }
+
+    #[test]
+    fn test_seeded_rng() {
+        let rom = [0xc0, 0xff, 0xc1, 0xff, 0xc2, 0xff, 0xc3, 0xff];
+        let random = |seed| {
+            let mut state = Chip8State::new_seeded(&rom, Quirks::default(), seed).unwrap();
+            run(&mut state, 4);
+            state.v
+        };
+        // The sequence only changes when rand changes its StdRng algorithm
+        assert_eq!(random(1)[..4], [97, 100, 74, 37]);
+        assert_eq!(random(1), random(1));
+        assert_ne!(random(1), random(2));
+    }
+
+    #[test]
+    fn test_key_skip_masks_vx() {
+        // Key 0x1f is key 0xf
+        let mut state = Chip8State::new(&[0x60, 0x1f, 0xe0, 0x9e], Quirks::default()).unwrap();
+        let mut keypad = Chip8Keypad::default();
+        keypad.pressed[0xf] = true;
+        for _ in 0..2 {
+            state.update(Duration::ZERO, &keypad).unwrap();
+        }
+        assert_eq!(state.pc, 0x206);
+    }
+
+    #[test]
+    fn test_memory_near_end_of_ram() {
+        // 0xff55 and 0xf033 with i at 0xff8
+        let rom = [0xaf, 0xf8, 0x60, 0xfe, 0x6f, 0x07, 0xff, 0x55, 0xf0, 0x33];
+        let mut state = Chip8State::new(&rom, Quirks::default()).unwrap();
+        run(&mut state, 3);
+        let result = state.update(Duration::ZERO, &Chip8Keypad::default());
+        assert_eq!(result, Err(Chip8Error::RamOutOfBounds(0xff8)));
+        assert_eq!(state.pc, 0x206);
+        assert!(state.ram[0xff8..].iter().all(|byte| *byte == 0));
+
+        let quirks = Quirks {
+            wrap_memory: true,
+            ..Quirks::default()
+        };
+        let mut state = Chip8State::new(&rom, quirks).unwrap();
+        run(&mut state, 4);
+        assert_eq!(state.ram[0xff8], 0xfe);
+        assert_eq!(&state.ram[..8], &[0, 0, 0, 0, 0, 0, 0, 0x07]);
+
+        state.i = 0xffe;
+        run(&mut state, 1);
+        assert_eq!(
+            (state.ram[0xffe], state.ram[0xfff], state.ram[0]),
+            (2, 5, 4)
+        );
+    }
+
+    #[test]
+    fn test_index_increment_quirk() {
+        // 0xa300, 0xff55
+        let rom = [0xa3, 0x00, 0xff, 0x55];
+        for (increment, i) in [
+            (IndexIncrement::Unchanged, 0x300),
+            (IndexIncrement::X, 0x30f),
+            (IndexIncrement::XPlusOne, 0x310),
+        ] {
+            let quirks = Quirks {
+                memory_increment: increment,
+                ..Quirks::default()
+            };
+            let mut state = Chip8State::new(&rom, quirks).unwrap();
+            run(&mut state, 2);
+            assert_eq!(state.i, i);
+        }
+    }
+
+    #[test]
+    fn test_sprite_near_end_of_ram() {
+        // 0xd008 with i at 0xffe
+        let rom = [0xaf, 0xfe, 0xd0, 0x08];
+        let mut state = Chip8State::new(&rom, Quirks::default()).unwrap();
+        run(&mut state, 1);
+        state.vblank();
+        let result = state.update(Duration::ZERO, &Chip8Keypad::default());
+        assert_eq!(result, Err(Chip8Error::RamOutOfBounds(0xffe)));
+        assert_eq!(state.pc, 0x202);
+
+        let quirks = Quirks {
+            wrap_memory: true,
+            ..Quirks::default()
+        };
+        let mut state = Chip8State::new(&rom, quirks).unwrap();
+        state.ram[0xfff] = 0x80;
+        state.ram[0x000] = 0x80;
+        run(&mut state, 2);
+        assert!(state.display.planes[0][DISPLAY_WIDTH]);
+        assert!(state.display.planes[0][2 * DISPLAY_WIDTH]);
+    }
+
+    #[test]
+    fn test_exit() {
+        let mut state =
+            Chip8State::new(&[0x60, 0x01, 0x00, 0xfd, 0x60, 0x02], Quirks::default()).unwrap();
+        run(&mut state, 4);
+        assert!(state.halted);
+        assert_eq!(state.v[0], 1);
+        assert_eq!(state.pc, 0x204);
+    }
+
+    #[test]
+    fn test_rpl_flags() {
+        // Save v0-v2, clear them and load them back
+        let rom = [
+            0x60, 0x0a, 0x61, 0x0b, 0x62, 0x0c, 0xf2, 0x75, 0x60, 0x00, 0x61, 0x00, 0x62, 0x00,
+            0xf1, 0x85,
+        ];
+        let mut state = Chip8State::new(&rom, Quirks::default()).unwrap();
+        run(&mut state, 7);
+        assert_eq!(state.rpl[..4], [0x0a, 0x0b, 0x0c, 0]);
+        assert_eq!(state.v[..3], [0, 0, 0]);
+        run(&mut state, 1);
+        assert_eq!(state.v[..3], [0x0a, 0x0b, 0]);
+    }
+
+    #[test]
+    fn test_pc_out_of_bounds() {
+        // Jump to the last byte of ram
+        let mut state = Chip8State::new(&[0x1f, 0xff], Quirks::default()).unwrap();
+        run(&mut state, 1);
+        let result = state.update(Duration::ZERO, &Chip8Keypad::default());
+        assert_eq!(result, Err(Chip8Error::PcOutOfBounds(0xfff)));
+    }
+
+    #[test]
+    fn test_hires_toggle() {
+        // hires, draw "0" at (120, 60), lores
+        let mut state = Chip8State::new(
+            &[
+                0x00, 0xff, 0x60, 0x78, 0x61, 0x3c, 0xa0, 0x50, 0xd0, 0x15, 0x00, 0xfe,
+            ],
+            Quirks::default(),
+        )
+        .unwrap();
+        run(&mut state, 5);
+        assert!(state.display.is_hires());
+        assert_eq!(state.display.planes[0].len(), 128 * 64);
+        assert!(state.display.planes[0][120 + 60 * 128]);
+        // Clipped at the bottom edge: only 4 rows of the glyph are drawn
+        assert_eq!(
+            state.display.planes[0].iter().filter(|p| **p).count(),
+            4 + 2 + 2 + 2
+        );
+        run(&mut state, 1);
+        assert!(!state.display.is_hires());
+        assert_eq!(state.display.planes[0].len(), 64 * 32);
+    }
+
+    #[test]
+    fn test_clear_on_resolution_change_quirk() {
+        // Draw "0" at (0, 0), then switch to high resolution
+        let rom = [0x60, 0x00, 0xa0, 0x50, 0xd0, 0x05, 0x00, 0xff];
+        for clear_on_resolution_change in [true, false] {
+            let quirks = Quirks {
+                clear_on_resolution_change,
+                ..Quirks::default()
+            };
+            let mut state = Chip8State::new(&rom, quirks).unwrap();
+            run(&mut state, 4);
+            assert!(state.display.is_hires());
+            let lit = state.display.planes[0].iter().filter(|p| **p).count();
+            if clear_on_resolution_change {
+                assert_eq!(lit, 0);
+            } else {
+                // 14 pixels of "0", each now 2x2
+                assert_eq!(lit, 14 * 4);
+                assert!(state.display.planes[0][1 + 128]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_custom_resolution() {
+        // Draw "0" at (0, 40), below the usual 32 rows, then at (0, 70) which wraps to row 6
+        let mut state = Chip8State::new(
+            &[
+                0x60, 0x00, 0x61, 0x28, 0xa0, 0x50, 0xd0, 0x15, 0x61, 0x46, 0xd0, 0x15,
+            ],
+            Quirks::default(),
+        )
+        .unwrap();
+        state.display = Chip8Display::with_size(64, 64);
+        run(&mut state, 6);
+        assert!(state.display.planes[0][40 * 64]);
+        assert!(state.display.planes[0][6 * 64]);
+    }
+
+    #[test]
+    fn test_draw_16x16_sprite() {
+        // hires, v0 = 0, i = 0x20a (sprite data after the code), draw 16x16 twice
+        let mut rom = vec![0x00, 0xff, 0x60, 0x00, 0xa2, 0x0a, 0xd0, 0x00, 0xd0, 0x00];
+        rom.extend([0xff; 32]);
+        let mut state = Chip8State::new(&rom, Quirks::default()).unwrap();
+        run(&mut state, 4);
+        assert_eq!(
+            state.display.planes[0].iter().filter(|p| **p).count(),
+            16 * 16
+        );
+        assert!(state.display.planes[0][15 + 15 * 128]);
+        assert!(!state.display.planes[0][16]);
+        assert_eq!(state.v[0xf], 0);
+        // Redrawing collides on every row
+        run(&mut state, 1);
+        assert_eq!(state.display.planes[0].iter().filter(|p| **p).count(), 0);
+        assert_eq!(state.v[0xf], 16);
+    }
+
+    #[test]
+    fn test_lores_collision_flag() {
+        // v0 = 0, i = font "0", draw twice
+        let mut state = Chip8State::new(
+            &[0x60, 0x00, 0xf0, 0x29, 0xd0, 0x05, 0xd0, 0x05],
+            Quirks::default(),
+        )
+        .unwrap();
+        run(&mut state, 4);
+        assert_eq!(state.v[0xf], 1);
+    }
+
+    #[test]
+    fn test_collided_rows_quirk() {
+        // hires, v0 = 0, draw font "1" then "0" over it, they overlap on their first and last rows
+        let rom = [
+            0x00, 0xff, 0x60, 0x00, 0x61, 0x01, 0xf1, 0x29, 0xd0, 0x05, 0xf0, 0x29, 0xd0, 0x05,
+        ];
+        let run_with = |count_collided_rows| {
+            let quirks = Quirks {
+                count_collided_rows,
+                ..Quirks::default()
+            };
+            let mut state = Chip8State::new(&rom, quirks).unwrap();
+            run(&mut state, 7);
+            state.v[0xf]
+        };
+        assert_eq!(run_with(true), 2);
+        assert_eq!(run_with(false), 1);
+
+        // Drawing "0" at y = 62, the three rows clipped off the bottom count as collisions
+        let rom = [0x00, 0xff, 0x60, 0x3e, 0xa0, 0x50, 0xd0, 0x05];
+        let quirks = Quirks {
+            count_collided_rows: true,
+            ..Quirks::default()
+        };
+        let mut state = Chip8State::new(&rom, quirks).unwrap();
+        run(&mut state, 4);
+        assert_eq!(state.v[0xf], 3);
+    }
+
+    #[test]
+    fn test_scroll_instructions() {
+        // hires, draw "0" at (0, 0), scroll down 3, scroll right 4, scroll left 4
+        let mut rom = vec![0x00, 0xff, 0x60, 0x00, 0xa0, 0x50, 0xd0, 0x05];
+        rom.extend([0x00, 0xc3, 0x00, 0xfb, 0x00, 0xfc]);
+        let mut state = Chip8State::new(&rom, Quirks::default()).unwrap();
+        run(&mut state, 5);
+        assert!(state.display.planes[0][3 * 128]);
+        assert!(!state.display.planes[0][0]);
+        run(&mut state, 1);
+        assert!(state.display.planes[0][4 + 3 * 128]);
+        assert!(!state.display.planes[0][3 + 3 * 128]);
+        run(&mut state, 1);
+        assert!(state.display.planes[0][3 * 128]);
+
+        // In low resolution, the same scroll moves half as far
+        let mut state = Chip8State::new(
+            &[0x60, 0x00, 0xa0, 0x50, 0xd0, 0x05, 0x00, 0xc3],
+            Quirks::default(),
+        )
+        .unwrap();
+        run(&mut state, 4);
+        assert!(state.display.planes[0][64]);
+        assert!(!state.display.planes[0][0]);
+    }
+
+    #[test]
+    fn test_draw_planes() {
+        // v0 = 0, i = font "0", select both planes, draw, select plane 2, clear
+        let mut state = Chip8State::new(
+            &[
+                0x60, 0x00, 0xa0, 0x50, 0xf3, 0x01, 0xd0, 0x01, 0xf2, 0x01, 0x00, 0xe0,
+            ],
+            Quirks::default(),
+        )
+        .unwrap();
+        run(&mut state, 4);
+        // Plane 1 got the first row of "0" (0xF0), plane 2 the next byte (0x90)
+        assert_eq!(state.display.value(0), 0b11);
+        assert_eq!(state.display.value(1), 0b01);
+        assert_eq!(state.display.value(3), 0b11);
+        run(&mut state, 2);
+        assert_eq!(state.display.value(0), 0b01);
+        assert!(state.display.planes[1].iter().all(|p| !*p));
+    }
+
+    #[test]
+    fn test_quirks_vf_reset_and_shift() {
+        // vf = 1, v0 = 0x81, v1 = 0x02, v0 |= v1, v0 = v1 >> 1 or v0 >> 1
+        let rom = [0x6f, 0x01, 0x60, 0x81, 0x61, 0x02, 0x80, 0x11, 0x80, 0x16];
+
+        let mut state = Chip8State::new(&rom, Quirks::default()).unwrap();
+        run(&mut state, 4);
+        assert_eq!(state.v[0xf], 1);
+        run(&mut state, 1);
+        assert_eq!(state.v[0x0], 0x41);
+        assert_eq!(state.v[0xf], 1);
+
+        let mut state = Chip8State::new(&rom, Quirks::COSMAC).unwrap();
+        run(&mut state, 4);
+        assert_eq!(state.v[0xf], 0);
+        run(&mut state, 1);
+        assert_eq!(state.v[0x0], 0x01);
+        assert_eq!(state.v[0xf], 0);
+    }
+
+    #[test]
+    fn test_jump_with_offset() {
+        // v0 = 0x10, v2 = 0x20, jump
+        let rom = [0x60, 0x10, 0x62, 0x20, 0xb2, 0x40];
+
+        let mut state = Chip8State::new(&rom, Quirks::default()).unwrap();
+        run(&mut state, 3);
+        assert_eq!(state.pc, 0x250);
+
+        let quirks = Quirks {
+            jump_with_vx: true,
+            ..Quirks::default()
+        };
+        let mut state = Chip8State::new(&rom, quirks).unwrap();
+        run(&mut state, 3);
+        assert_eq!(state.pc, 0x260);
+    }
+
+    #[test]
+    fn test_call_overflow() {
+        // Call self forever
+        let mut state = Chip8State::new(&[0x22, 0x00], Quirks::default()).unwrap();
+        state.stack = Chip8Stack::with_capacity(16);
+        run(&mut state, 16);
+        let result = state.update(Duration::ZERO, &Chip8Keypad::default());
+        assert_eq!(result, Err(Chip8Error::StackOverflow));
+        assert_eq!(state.pc, 0x200);
+    }
+
+    #[test]
+    fn test_rom_too_large() {
+        assert!(Chip8State::new(&[0; MAX_ROM_SIZE], Quirks::default()).is_ok());
+        assert_eq!(
+            Chip8State::new(&[0; MAX_ROM_SIZE + 1], Quirks::default()).unwrap_err(),
+            Chip8Error::RomTooLarge {
+                size: 3585,
+                max: 3584
+            }
+        );
+    }
+
+    #[test]
+    fn test_index_overflow_quirk() {
+        // i = 0xfff, v0 = 2, vf = 0, i += v0
+        let rom = [0xaf, 0xff, 0x60, 0x02, 0x6f, 0x00, 0xf0, 0x1e];
+
+        let mut state = Chip8State::new(&rom, Quirks::default()).unwrap();
+        run(&mut state, 4);
+        assert_eq!(state.i, 0x001);
+        assert_eq!(state.v[0xf], 0);
+
+        let quirks = Quirks {
+            index_overflow_vf: true,
+            ..Quirks::default()
+        };
+        let mut state = Chip8State::new(&rom, quirks).unwrap();
+        run(&mut state, 4);
+        assert_eq!(state.i, 0x001);
+        assert_eq!(state.v[0xf], 1);
+    }
+}
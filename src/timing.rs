@@ -0,0 +1,37 @@
+/// Rough number of cycles an instruction takes on the COSMAC VIP, relative to the simplest ones.
+///
+/// The VIP interpreter spends very different amounts of time per instruction: drawing shifts
+/// every sprite row into place, the decimal conversion subtracts in a loop and the memory
+/// instructions copy one register at a time. The default clock model charges one cycle for all
+/// of them.
+pub fn cycle_cost(opcode: u16) -> u32 {
+    // Registers copied by 0xfx55 and 0xfx65
+    let registers = ((opcode & 0x0f00) >> 8) as u32 + 1;
+    let n = (opcode & 0x000f) as u32;
+    match opcode >> 12 {
+        0x0 if opcode == 0x00e0 => 4,
+        0x0 if opcode == 0x00ee => 2,
+        0x2 => 2,
+        // Executed through a small generated subroutine
+        0x8 => 2,
+        0xc => 2,
+        0xd => 2 + n,
+        0xf if opcode & 0xff == 0x33 => 6,
+        0xf if opcode & 0xff == 0x55 || opcode & 0xff == 0x65 => 1 + registers / 2,
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cycle_cost() {
+        assert_eq!(cycle_cost(0x6012), 1);
+        assert_eq!(cycle_cost(0x00e0), 4);
+        assert_eq!(cycle_cost(0xd125), 7);
+        assert_eq!(cycle_cost(0xf055), 1);
+        assert_eq!(cycle_cost(0xff65), 9);
+    }
+}
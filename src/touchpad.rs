@@ -0,0 +1,193 @@
+use sdl3::pixels::Color;
+use sdl3::render::{BlendMode, FRect, WindowCanvas};
+use sdl3::sys::touch::SDL_TOUCH_MOUSEID;
+
+/// Chip8 keys on the pad, row by row, as on the COSMAC VIP hex keypad.
+const LAYOUT: [[usize; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xc],
+    [0x4, 0x5, 0x6, 0xd],
+    [0x7, 0x8, 0x9, 0xe],
+    [0xa, 0x0, 0xb, 0xf],
+];
+
+/// Side of the pad as a fraction of the smallest window dimension.
+const PAD_FRACTION: f32 = 0.45;
+
+/// Space between the pad and the window edges, and between cells.
+const MARGIN: f32 = 4.0;
+
+/// Opacity of the cells, so that the display shows through.
+const CELL_ALPHA: u8 = 160;
+
+/// Pointer id of the mouse, finger ids are given by SDL.
+const MOUSE_POINTER: u64 = u64::MAX;
+
+/// On-screen 4x4 keypad in the bottom right corner, played with the mouse or touch.
+#[derive(Debug, Default)]
+pub struct TouchPad {
+    /// Key held by each mouse button or finger that went down on the pad.
+    touches: Vec<(u64, usize)>,
+}
+
+impl TouchPad {
+    /// Handle a mouse button going down at (x, y) in a window of the given size.
+    /// Touches are handled separately, SDL also reports them as mouse events.
+    pub fn mouse_down(&mut self, which: u32, x: f32, y: f32, width: f32, height: f32) {
+        if which != SDL_TOUCH_MOUSEID.0 {
+            self.touch_down(MOUSE_POINTER, x, y, width, height);
+        }
+    }
+
+    /// Handle a mouse button going up.
+    pub fn mouse_up(&mut self, which: u32) {
+        if which != SDL_TOUCH_MOUSEID.0 {
+            self.touch_up(MOUSE_POINTER);
+        }
+    }
+
+    /// Handle a pointer going down at (x, y) in a window of the given size.
+    pub fn touch_down(&mut self, pointer: u64, x: f32, y: f32, width: f32, height: f32) {
+        if let Some(key) = key_at(x, y, width, height) {
+            self.touches.retain(|(held, _)| *held != pointer);
+            self.touches.push((pointer, key));
+        }
+    }
+
+    /// Handle a pointer going up, wherever it is.
+    pub fn touch_up(&mut self, pointer: u64) {
+        self.touches.retain(|(held, _)| *held != pointer);
+    }
+
+    /// Mark the keys held on the pad.
+    pub fn press_keys(&self, pressed: &mut [bool; 16]) {
+        for (_, key) in &self.touches {
+            pressed[*key] = true;
+        }
+    }
+}
+
+/// Draw the pad over the display, with the keys the interpreter sees held down in `fg`, whether
+/// they come from the pad, the keyboard or a gamepad.
+pub fn draw(canvas: &mut WindowCanvas, pressed: &[bool; 16], fg: Color) {
+    let (width, height) = canvas.output_size().unwrap();
+    let (left, top, cell) = pad_area(width as f32, height as f32);
+
+    canvas.set_blend_mode(BlendMode::Blend);
+    for (row, keys) in LAYOUT.iter().enumerate() {
+        for (column, key) in keys.iter().enumerate() {
+            let x = left + column as f32 * cell;
+            let y = top + row as f32 * cell;
+            let color = cell_color(pressed[*key], fg);
+            canvas.set_draw_color(color);
+            canvas
+                .fill_rect(FRect::new(
+                    x + MARGIN / 2.0,
+                    y + MARGIN / 2.0,
+                    cell - MARGIN,
+                    cell - MARGIN,
+                ))
+                .unwrap();
+            // Label centered in the cell, debug text characters are 8x8
+            canvas.set_draw_color(label_color(color));
+            canvas
+                .draw_debug_text(
+                    &format!("{:X}", key),
+                    (x + (cell - 8.0) / 2.0, y + (cell - 8.0) / 2.0),
+                )
+                .unwrap();
+        }
+    }
+    canvas.set_blend_mode(BlendMode::None);
+}
+
+/// Held keys take the foreground color, released ones stay dim.
+fn cell_color(held: bool, fg: Color) -> Color {
+    if held {
+        Color::RGBA(fg.r, fg.g, fg.b, CELL_ALPHA)
+    } else {
+        Color::RGBA(60, 60, 60, CELL_ALPHA)
+    }
+}
+
+/// Dark labels on bright cells and light labels on dark ones.
+fn label_color(cell: Color) -> Color {
+    if cell.r as u32 + cell.g as u32 + cell.b as u32 > 3 * 128 {
+        Color::RGB(25, 25, 25)
+    } else {
+        Color::RGB(230, 230, 230)
+    }
+}
+
+/// Top left corner of the pad and size of a cell, in a window of the given size.
+fn pad_area(width: f32, height: f32) -> (f32, f32, f32) {
+    let size = width.min(height) * PAD_FRACTION;
+    (width - size - MARGIN, height - size - MARGIN, size / 4.0)
+}
+
+/// Key whose cell contains (x, y), in a window of the given size.
+fn key_at(x: f32, y: f32, width: f32, height: f32) -> Option<usize> {
+    let (left, top, cell) = pad_area(width, height);
+    let column = ((x - left) / cell).floor();
+    let row = ((y - top) / cell).floor();
+    if (0.0..4.0).contains(&column) && (0.0..4.0).contains(&row) {
+        Some(LAYOUT[row as usize][column as usize])
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_key_at() {
+        // 360x360 pad in the bottom right corner
+        let (left, top, cell) = pad_area(1000.0, 800.0);
+        assert_eq!((left, top, cell), (636.0, 436.0, 90.0));
+        assert_eq!(key_at(left + 1.0, top + 1.0, 1000.0, 800.0), Some(0x1));
+        assert_eq!(
+            key_at(left + 3.5 * cell, top + 0.5 * cell, 1000.0, 800.0),
+            Some(0xc)
+        );
+        assert_eq!(
+            key_at(left + 1.5 * cell, top + 3.5 * cell, 1000.0, 800.0),
+            Some(0x0)
+        );
+        assert_eq!(key_at(left - 1.0, top + 1.0, 1000.0, 800.0), None);
+        assert_eq!(key_at(10.0, 10.0, 1000.0, 800.0), None);
+    }
+
+    #[test]
+    fn test_touches() {
+        let mut pad = TouchPad::default();
+        let (left, top, cell) = pad_area(640.0, 320.0);
+        pad.touch_down(1, left + 0.5 * cell, top + 0.5 * cell, 640.0, 320.0);
+        pad.mouse_down(0, left + 1.5 * cell, top + 0.5 * cell, 640.0, 320.0);
+        // Outside the pad
+        pad.touch_down(2, 0.0, 0.0, 640.0, 320.0);
+
+        let mut pressed = [false; 16];
+        pad.press_keys(&mut pressed);
+        assert!(pressed[0x1] && pressed[0x2]);
+        assert_eq!(pressed.iter().filter(|held| **held).count(), 2);
+
+        // Touches also reported as mouse events are ignored
+        pad.mouse_up(SDL_TOUCH_MOUSEID.0);
+        pad.mouse_up(0);
+        pad.touch_up(1);
+        let mut pressed = [false; 16];
+        pad.press_keys(&mut pressed);
+        assert!(!pressed.contains(&true));
+    }
+
+    #[test]
+    fn test_cell_colors() {
+        let fg = Color::RGB(255, 204, 0);
+        assert_eq!(cell_color(true, fg), Color::RGBA(255, 204, 0, CELL_ALPHA));
+        assert_eq!(label_color(cell_color(true, fg)), Color::RGB(25, 25, 25));
+        let dim = cell_color(false, fg);
+        assert_eq!(dim, Color::RGBA(60, 60, 60, CELL_ALPHA));
+        assert_eq!(label_color(dim), Color::RGB(230, 230, 230));
+    }
+}
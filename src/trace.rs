@@ -0,0 +1,69 @@
+use crate::disasm::disassemble;
+use std::collections::VecDeque;
+use std::fmt::Write;
+
+/// The last executed instructions, as (pc, opcode) pairs from oldest to newest.
+#[derive(Debug, Clone)]
+pub struct Trace {
+    entries: VecDeque<(u16, u16)>,
+    capacity: usize,
+}
+
+impl Trace {
+    pub fn new(capacity: usize) -> Self {
+        Trace {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Record an instruction, forgetting the oldest one when full.
+    pub fn push(&mut self, pc: u16, opcode: u16) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((pc, opcode));
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &(u16, u16)> {
+        self.entries.iter()
+    }
+
+    /// One disassembled instruction per line, oldest first.
+    pub fn dump(&self) -> String {
+        let mut out = String::new();
+        for (pc, opcode) in &self.entries {
+            writeln!(
+                out,
+                "0x{:04x}: {:04X}    {}",
+                pc,
+                opcode,
+                disassemble(*opcode)
+            )
+            .unwrap();
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ring_buffer() {
+        let mut trace = Trace::new(2);
+        trace.push(0x200, 0x00e0);
+        trace.push(0x202, 0x6001);
+        trace.push(0x204, 0x1204);
+        let entries: Vec<_> = trace.entries().copied().collect();
+        assert_eq!(entries, [(0x202, 0x6001), (0x204, 0x1204)]);
+        assert_eq!(
+            trace.dump(),
+            "0x0202: 6001    LD V0, 0x01\n0x0204: 1204    JP 0x204\n"
+        );
+    }
+}
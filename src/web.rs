@@ -0,0 +1,153 @@
+use crate::keypad::Chip8Keypad;
+use crate::quirks::Quirks;
+use crate::state::Chip8State;
+use std::time::Duration;
+
+/// Default cpu clock, as in the SDL frontend.
+const DEFAULT_CLOCK_HZ: f64 = 700.0;
+/// Fastest clock accepted, as in the SDL frontend.
+const MAX_CLOCK_HZ: f64 = 1_000_000.0;
+
+/// RGBA colors of the plane values 0b00 to 0b11, same as the SDL frontend.
+const DEFAULT_COLORS: [[u8; 4]; 4] = [
+    [10, 10, 10, 255],
+    [255, 255, 190, 255],
+    [200, 60, 40, 255],
+    [90, 90, 220, 255],
+];
+
+/// The interpreter behind an API that only uses plain numbers and byte buffers, so that a host
+/// like JavaScript can drive it through `wasm-bindgen` without knowing about the core types.
+///
+/// The host calls [`WebChip8::tick`] once per animation frame with the time elapsed and the keys
+/// held, then draws [`WebChip8::framebuffer`].
+pub struct WebChip8 {
+    state: Chip8State,
+    keypad: Chip8Keypad,
+    clock_hz: f64,
+    /// Time not yet spent on cycles, in milliseconds.
+    lag_ms: f64,
+    colors: [[u8; 4]; 4],
+    /// Set when the program crashed, after which it doesn't run anymore.
+    error: Option<String>,
+}
+
+impl WebChip8 {
+    pub fn new(rom: &[u8]) -> Result<WebChip8, String> {
+        let state = Chip8State::new(rom, Quirks::default()).map_err(|err| err.to_string())?;
+        Ok(WebChip8 {
+            state,
+            keypad: Chip8Keypad::default(),
+            clock_hz: DEFAULT_CLOCK_HZ,
+            lag_ms: 0.0,
+            colors: DEFAULT_COLORS,
+            error: None,
+        })
+    }
+
+    /// Values that are not a number of Hz above 0 and at most 1MHz are ignored, so that a bad
+    /// value from the host can't stall the machine or keep `tick` from returning.
+    pub fn set_clock(&mut self, hz: f64) {
+        if hz > 0.0 && hz <= MAX_CLOCK_HZ {
+            self.clock_hz = hz;
+        }
+    }
+
+    /// Set the `0xRRGGBB` color of a plane value, 0 for the background and 1 for lit pixels.
+    pub fn set_color(&mut self, value: u8, rgb: u32) {
+        let [_, r, g, b] = rgb.to_be_bytes();
+        self.colors[value as usize & 0b11] = [r, g, b, 255];
+    }
+
+    /// Run the cycles that fit in `delta_ms` milliseconds, with bit k of `keys` set for each
+    /// key k held down. The display is refreshed at the end.
+    pub fn tick(&mut self, delta_ms: f64, keys: u16) {
+        if self.error.is_some() {
+            return;
+        }
+        self.state
+            .tick_timers(Duration::from_secs_f64(delta_ms.max(0.0) / 1000.0));
+        let cycle_ms = 1000.0 / self.clock_hz;
+        self.lag_ms += delta_ms;
+        while self.lag_ms >= cycle_ms {
+            self.lag_ms -= cycle_ms;
+            self.keypad.advance();
+            self.keypad.set_bitmask(keys);
+            if let Err(err) = self.state.update(Duration::ZERO, &self.keypad) {
+                self.error = Some(format!("{} at pc=0x{:04x}", err, self.state.pc));
+                return;
+            }
+        }
+        self.state.vblank();
+    }
+
+    pub fn width(&self) -> usize {
+        self.state.display.width()
+    }
+
+    pub fn height(&self) -> usize {
+        self.state.display.height()
+    }
+
+    /// Row-major RGBA pixels of the display, [`WebChip8::width`] by [`WebChip8::height`].
+    pub fn framebuffer(&self) -> Vec<u8> {
+        self.state.framebuffer(&self.colors)
+    }
+
+    /// True while the beep should play.
+    pub fn sound_active(&self) -> bool {
+        self.state.sound_timer > 0 && !self.state.halted && self.error.is_none()
+    }
+
+    /// Why the program stopped, if it crashed.
+    pub fn error(&self) -> Option<String> {
+        self.error.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_web_chip8() {
+        // Wait for a key, draw its glyph, then beep
+        let rom = [
+            0xf0, 0x0a, 0xf0, 0x29, 0xd1, 0x15, 0x62, 0x10, 0xf2, 0x18, 0x12, 0x0a,
+        ];
+        let mut chip8 = WebChip8::new(&rom).unwrap();
+        chip8.set_color(1, 0xff0000);
+        chip8.tick(10.0, 1 << 7);
+        // Released, then the draw waits for the display refresh at the end of the tick
+        chip8.tick(20.0, 0);
+        chip8.tick(20.0, 0);
+        assert!(chip8.sound_active());
+        assert_eq!(chip8.error(), None);
+
+        let framebuffer = chip8.framebuffer();
+        assert_eq!(framebuffer.len(), 64 * 32 * 4);
+        // Top row of the "7" glyph
+        assert_eq!(framebuffer[..4], [255, 0, 0, 255]);
+        assert_eq!(framebuffer[4 * 4..5 * 4], DEFAULT_COLORS[0]);
+
+        let mut chip8 = WebChip8::new(&[0x50, 0x01]).unwrap();
+        chip8.tick(10.0, 0);
+        assert_eq!(
+            chip8.error().as_deref(),
+            Some("Unknown instruction 0x5001 at pc=0x0200")
+        );
+    }
+
+    #[test]
+    fn test_set_clock() {
+        let mut chip8 = WebChip8::new(&[0x70, 0x01, 0x12, 0x00]).unwrap();
+        for hz in [0.0, -700.0, f64::NAN, f64::INFINITY, 2_000_000.0] {
+            chip8.set_clock(hz);
+            assert_eq!(chip8.clock_hz, DEFAULT_CLOCK_HZ);
+        }
+        chip8.set_clock(1000.0);
+        chip8.tick(10.0, 0);
+        // 10 cycles, half of them adding 1 to v0
+        assert_eq!(chip8.state.v[0], 5);
+    }
+}
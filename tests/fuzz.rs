@@ -0,0 +1,86 @@
+//! Runs random roms with random quirks and key presses, checking that the interpreter reports
+//! bad programs with a `Chip8Error` instead of panicking.
+//!
+//! It takes a while, so it only runs when asked for. Debug builds also catch arithmetic overflows:
+//! ```sh
+//! CHIP8_FUZZ_SECONDS=60 cargo test --no-default-features --test fuzz -- --ignored
+//! ```
+//! A failure prints the seed of the offending rom, which `CHIP8_FUZZ_SEED` replays.
+
+use chip8_core::{Chip8Keypad, Chip8State, IndexIncrement, MAX_ROM_SIZE, Quirks};
+use rand::rngs::StdRng;
+use rand::{RngExt, SeedableRng};
+use std::panic;
+use std::time::{Duration, Instant};
+
+/// Cycles run per rom, unless it errors out earlier.
+const CYCLES: usize = 10_000;
+
+fn env_number(name: &str) -> Option<u64> {
+    std::env::var(name)
+        .ok()
+        .map(|value| value.parse().expect("expected a number"))
+}
+
+/// Run one random rom, everything derived from `seed`.
+fn run_random_rom(seed: u64) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let rom: Vec<u8> = (0..MAX_ROM_SIZE).map(|_| rng.random()).collect();
+    let quirks = Quirks {
+        vf_reset: rng.random(),
+        memory_increment: [
+            IndexIncrement::Unchanged,
+            IndexIncrement::X,
+            IndexIncrement::XPlusOne,
+        ][rng.random_range(0..3)],
+        shift_vx_in_place: rng.random(),
+        jump_with_vx: rng.random(),
+        display_wait: rng.random(),
+        clip_sprites: rng.random(),
+        index_overflow_vf: rng.random(),
+        key_wait_on_press: rng.random(),
+        wrap_memory: rng.random(),
+        count_collided_rows: rng.random(),
+        clear_on_resolution_change: rng.random(),
+    };
+    let mut state = Chip8State::new_seeded(&rom, quirks, seed).unwrap();
+    let mut keypad = Chip8Keypad::default();
+
+    for _ in 0..CYCLES {
+        keypad.advance();
+        let key = rng.random_range(0..16);
+        if rng.random() {
+            keypad.press(key);
+        } else {
+            keypad.release(key);
+        }
+        if rng.random_ratio(1, 12) {
+            state.vblank();
+        }
+        let delta = Duration::from_micros(rng.random_range(0..2000));
+        if state.update(delta, &keypad).is_err() {
+            break;
+        }
+    }
+}
+
+#[test]
+#[ignore = "long running, see the module documentation"]
+fn fuzz() {
+    let duration = Duration::from_secs(env_number("CHIP8_FUZZ_SECONDS").unwrap_or(10));
+    let first_seed = env_number("CHIP8_FUZZ_SEED").unwrap_or_else(rand::random);
+
+    let start = Instant::now();
+    let mut seed = first_seed;
+    while start.elapsed() < duration {
+        if panic::catch_unwind(|| run_random_rom(seed)).is_err() {
+            panic!("Panicked on the rom of seed {}", seed);
+        }
+        seed = seed.wrapping_add(1);
+        if env_number("CHIP8_FUZZ_SEED").is_some() {
+            // Replaying a single rom
+            break;
+        }
+    }
+    println!("Ran {} roms", seed.wrapping_sub(first_seed));
+}
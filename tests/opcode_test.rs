@@ -0,0 +1,37 @@
+//! Runs corax89's opcode test rom, which checks every basic instruction and prints a grid of
+//! results, each cell an opcode name followed by "OK" or "NO".
+
+use chip8_core::{Quirks, run_headless};
+
+const ROM: &[u8] = include_bytes!("roms/test_opcode.ch8");
+
+/// The "OK" drawn after an opcode that passed.
+const OK: [&str; 4] = ["###.#.#", "#.#.##.", "#.#.#.#", "###.#.#"];
+
+/// Columns where the results start in each of the three columns of the grid.
+const RESULT_COLUMNS: [usize; 3] = [10, 32, 52];
+
+/// The six rows of the grid are 5 pixels apart, starting at row 1.
+const RESULT_ROWS: usize = 6;
+
+#[test]
+fn test_opcodes() {
+    let state = run_headless(ROM, 2000, Quirks::default()).unwrap();
+    let ascii = state.display.to_ascii();
+    let lines: Vec<&str> = ascii.lines().collect();
+
+    for grid_row in 0..RESULT_ROWS {
+        for col in RESULT_COLUMNS {
+            let top = 1 + grid_row * 5;
+            let cell: Vec<&str> = lines[top..top + 4]
+                .iter()
+                .map(|line| &line[col..col + OK[0].len()])
+                .collect();
+            assert_eq!(
+                cell, OK,
+                "failed test at row {}, column {}:\n{}",
+                top, col, ascii
+            );
+        }
+    }
+}